@@ -0,0 +1,309 @@
+use crate::export::{exported_exec, read_wrapper};
+use crate::{AetherError, Environment, ExportMode, Pkg, PkgList};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{read_to_string, write, DirEntry};
+use std::path::PathBuf;
+
+/// file in an environment's database directory holding the alternatives'
+/// priorities and choices
+const ALTERNATIVES_FILE: &str = "alternatives.toml";
+
+/// a package providing an executable, and how strongly it's preferred
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AlternativeProvider {
+    /// `name-version` of the package
+    pub pkg: String,
+    pub exec: PathBuf,
+    /// higher is preferred, 0 by default
+    pub priority: i64,
+}
+
+/**
+An executable name provided by one or more installed packages, only one of
+which is exported into the bin directory
+
+Unless a provider is chosen explicitly, the one with the highest priority is
+exported; on a tie the package exported last stays, so installing another
+provider (or upgrading the active one) doesn't change which one runs.
+
+# Public fields:
+```text
+name: String
+providers: Vec<AlternativeProvider>
+active: String
+choice: Option<String>
+```
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Alternative {
+    pub name: String,
+    /// the providers by priority, best first
+    pub providers: Vec<AlternativeProvider>,
+    /// `name-version` of the package whose executable is exported
+    pub active: String,
+    /// the package name chosen to provide it, if it's installed
+    pub choice: Option<String>,
+}
+
+/// the contents of [`ALTERNATIVES_FILE`]
+#[derive(Default, Serialize, Deserialize)]
+struct Choices {
+    /// package name -> priority, for packages not at 0
+    #[serde(default)]
+    priorities: BTreeMap<String, i64>,
+    /// executable name -> the package name chosen to provide it
+    #[serde(default)]
+    chosen: BTreeMap<String, String>,
+    /// executable name -> the package name last exported for it
+    #[serde(default)]
+    current: BTreeMap<String, String>,
+}
+
+impl Environment {
+    fn alternatives_file(&self) -> PathBuf {
+        self.db_dir().join(ALTERNATIVES_FILE)
+    }
+
+    fn read_choices(&self) -> Result<Choices, AetherError> {
+        let file = self.alternatives_file();
+
+        match read_to_string(&file) {
+            Ok(raw) => {
+                toml::from_str(&raw).map_err(|source| AetherError::TomlError { file, source })
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Choices::default()),
+            Err(source) => Err(AetherError::ReadError { file, source }),
+        }
+    }
+
+    fn write_choices(&self, choices: &Choices) -> Result<(), AetherError> {
+        let file = self.alternatives_file();
+        // tables of strings and integers always serialize
+        let raw = toml::to_string(choices).unwrap();
+        write(&file, raw).map_err(|source| AetherError::WriteError { file, source })
+    }
+
+    /// return the alternatives priority of the package `name`
+    pub fn alternative_priority(&self, name: &str) -> Result<i64, AetherError> {
+        Ok(self
+            .read_choices()?
+            .priorities
+            .get(name)
+            .copied()
+            .unwrap_or(0))
+    }
+
+    /// return the package name chosen to provide the executable `name`
+    pub fn alternative_choice(&self, name: &str) -> Result<Option<String>, AetherError> {
+        Ok(self.read_choices()?.chosen.remove(name))
+    }
+}
+
+impl PkgList {
+    /// return the executable `name` as an alternative, or `None` if no
+    /// installed package provides it
+    pub fn alternative(&self, name: &str) -> Result<Option<Alternative>, AetherError> {
+        let choices = self.env().read_choices()?;
+        let current = choices.current.get(name);
+
+        let mut providers: Vec<(&Pkg, AlternativeProvider)> = vec![];
+        for pkg in self.pkgs() {
+            for exec in pkg.list_execs()? {
+                if exec.file_name() == name {
+                    let priority = choices.priorities.get(&pkg.pkginfo.pkgname);
+                    providers.push((
+                        pkg,
+                        AlternativeProvider {
+                            pkg: pkg.get_refstr(),
+                            exec: exec.path(),
+                            priority: priority.copied().unwrap_or(0),
+                        },
+                    ));
+                }
+            }
+        }
+
+        providers.sort_by(|(a_pkg, a), (b_pkg, b)| {
+            b.priority
+                .cmp(&a.priority)
+                .then_with(|| {
+                    let is_current = |pkg: &Pkg| current == Some(&pkg.pkginfo.pkgname);
+                    is_current(b_pkg).cmp(&is_current(a_pkg))
+                })
+                .then_with(|| a_pkg.pkginfo.pkgname.cmp(&b_pkg.pkginfo.pkgname))
+        });
+
+        let choice = choices
+            .chosen
+            .get(name)
+            .filter(|choice| {
+                providers
+                    .iter()
+                    .any(|(pkg, _)| pkg.pkginfo.pkgname == **choice)
+            })
+            .cloned();
+        let active = match &choice {
+            Some(choice) => providers
+                .iter()
+                .find(|(pkg, _)| pkg.pkginfo.pkgname == *choice),
+            None => providers.first(),
+        };
+
+        Ok(active.map(|(_, active)| Alternative {
+            name: name.into(),
+            active: active.pkg.clone(),
+            choice,
+            providers: providers
+                .iter()
+                .map(|(_, provider)| provider.clone())
+                .collect(),
+        }))
+    }
+
+    /// return every executable name more than one installed package provides
+    pub fn alternatives(&self) -> Result<Vec<Alternative>, AetherError> {
+        let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+        for pkg in self.pkgs() {
+            for exec in pkg.list_execs()? {
+                *counts
+                    .entry(exec.file_name().to_string_lossy().into())
+                    .or_default() += 1;
+            }
+        }
+
+        let mut alternatives = vec![];
+        for (name, _) in counts.iter().filter(|(_, count)| **count > 1) {
+            alternatives.extend(self.alternative(name)?);
+        }
+
+        Ok(alternatives)
+    }
+
+    /**
+    make the package `pkgname` provide the executable `name`, or go back to
+    picking a provider by priority if `pkgname` is `None`
+
+    The choice is remembered, and applies whenever the package is installed.
+    */
+    pub fn choose_alternative(
+        &mut self,
+        name: &str,
+        pkgname: Option<&str>,
+    ) -> Result<Alternative, AetherError> {
+        let alternative = self
+            .alternative(name)?
+            .ok_or(AetherError::MissingExec(vec![self
+                .env()
+                .bin_dir()
+                .join(name)]))?;
+
+        if let Some(pkgname) = pkgname {
+            let provides = |pkg: &Pkg| {
+                pkg.pkginfo.pkgname == pkgname
+                    && alternative
+                        .providers
+                        .iter()
+                        .any(|provider| provider.pkg == pkg.get_refstr())
+            };
+
+            if !self.pkgs().iter().any(provides) {
+                return Err(AetherError::InvalidValue {
+                    key: format!("provider of {}", name),
+                    value: pkgname.into(),
+                });
+            }
+        }
+
+        let mut choices = self.env().read_choices()?;
+        match pkgname {
+            Some(pkgname) => choices.chosen.insert(name.into(), pkgname.into()),
+            None => choices.chosen.remove(name),
+        };
+        self.env().write_choices(&choices)?;
+
+        self.export_alternative(name)?;
+        self.env().record_generation(self.pkgs())?;
+
+        Ok(self.alternative(name)?.unwrap())
+    }
+
+    /// set the priority the package `pkgname` has among the providers of
+    /// each of its executables
+    pub fn set_alternative_priority(
+        &mut self,
+        pkgname: &str,
+        priority: i64,
+    ) -> Result<(), AetherError> {
+        let mut choices = self.env().read_choices()?;
+        if priority == 0 {
+            choices.priorities.remove(pkgname);
+        } else {
+            choices.priorities.insert(pkgname.into(), priority);
+        }
+        self.env().write_choices(&choices)?;
+
+        let mut execs = vec![];
+        for pkg in self.pkgs() {
+            if pkg.pkginfo.pkgname == pkgname {
+                execs.extend(pkg.list_execs()?);
+            }
+        }
+        if !execs.is_empty() {
+            self.export_alternatives(&execs)?;
+            self.env().record_generation(self.pkgs())?;
+        }
+
+        Ok(())
+    }
+
+    /// point the bin directory's entry for `name` at its active provider,
+    /// exported the way [`PkgList::exports`] says, and remember that provider
+    pub(crate) fn export_alternative(&self, name: &str) -> Result<(), AetherError> {
+        let alternative = match self.alternative(name)? {
+            Some(alternative) => alternative,
+            None => return Ok(()),
+        };
+        let pkg = self
+            .pkgs()
+            .iter()
+            .find(|pkg| pkg.get_refstr() == alternative.active)
+            .unwrap();
+        let exec = &alternative
+            .providers
+            .iter()
+            .find(|provider| provider.pkg == alternative.active)
+            .unwrap()
+            .exec;
+
+        let link = self.env().bin_dir().join(name);
+        let mode = self.exports().mode_for(&pkg.pkginfo);
+        let is_wrapper = read_wrapper(&link).is_some();
+
+        let exported = exported_exec(&link);
+        if exported.as_ref() != Some(exec) || is_wrapper != (mode == ExportMode::Wrapper) {
+            self.env().export_to_bin(name, exec, mode)?;
+        }
+
+        let mut choices = self.env().read_choices()?;
+        let pkgname = &pkg.pkginfo.pkgname;
+        if choices.current.get(name) != Some(pkgname) {
+            choices.current.insert(name.into(), pkgname.clone());
+            self.env().write_choices(&choices)?;
+        }
+
+        Ok(())
+    }
+
+    /// export each of `execs` from whichever package is the active provider
+    /// of its name, which may no longer be the package they came from
+    pub(crate) fn export_alternatives(&self, execs: &[DirEntry]) -> Result<(), AetherError> {
+        for exec in execs {
+            self.export_alternative(&exec.file_name().to_string_lossy())?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,181 @@
+use crate::{vercmp, AetherError, Depend, Pkg, PkgInfo, PkgList, Repo, RepoPkg};
+use std::cmp::Ordering;
+
+/// the architecture of packages that run on any machine
+pub const ARCH_ANY: &str = "any";
+
+/**
+The architectures an environment accepts packages for, best first
+
+Packages built for `any` (or that don't list an architecture at all) are
+always accepted, but rank below every listed architecture. Accepting an
+x86-64 microarchitecture level like `x86_64_v3` also accepts the levels below
+it, down to plain `x86_64`.
+
+# Public methods:
+```text
+// accept packages for the machine this was built for
+Architectures::host() : pub fn host() -> Architectures
+
+// accept packages for these architectures, best first
+Architectures::new() : pub fn new(accepted: &[String]) -> Architectures
+
+// return how good a match a package's architectures are, lower is better
+Architectures::rank() : pub fn rank(&self, arch: &[String]) -> Option<usize>
+
+// return an error if a package can't be installed here
+Architectures::check() : pub fn check(&self, pkg: &Pkg) -> Result<()>
+```
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Architectures {
+    accepted: Vec<String>,
+}
+
+/// return `arch` followed by the architectures it can also run packages for
+fn with_fallbacks(arch: &str) -> Vec<String> {
+    let level = arch
+        .strip_prefix("x86_64_v")
+        .and_then(|level| level.parse::<u8>().ok());
+
+    match level {
+        Some(level) => (2..=level)
+            .rev()
+            .map(|level| format!("x86_64_v{}", level))
+            .chain(["x86_64".to_string()])
+            .collect(),
+        None => vec![arch.into()],
+    }
+}
+
+impl Architectures {
+    /// accept packages for the machine this was built for
+    #[must_use]
+    pub fn host() -> Architectures {
+        Self::new(&[std::env::consts::ARCH.to_string()])
+    }
+
+    /// accept packages for `accepted`, best first, and the architectures
+    /// they fall back to
+    #[must_use]
+    pub fn new(accepted: &[String]) -> Architectures {
+        let mut expanded: Vec<String> = vec![];
+
+        for arch in accepted.iter().flat_map(|arch| with_fallbacks(arch)) {
+            if arch != ARCH_ANY && !expanded.contains(&arch) {
+                expanded.push(arch);
+            }
+        }
+
+        Architectures { accepted: expanded }
+    }
+
+    /// the accepted architectures other than `any`, best first
+    #[must_use]
+    pub fn accepted(&self) -> &[String] {
+        &self.accepted
+    }
+
+    /// return how good a match a package built for `arch` is, lower being
+    /// better, or `None` if it can't be installed here
+    #[must_use]
+    pub fn rank(&self, arch: &[String]) -> Option<usize> {
+        if arch.is_empty() || arch.iter().any(|arch| arch == ARCH_ANY) {
+            return Some(self.accepted.len());
+        }
+
+        arch.iter()
+            .filter_map(|arch| self.accepted.iter().position(|accepted| accepted == arch))
+            .min()
+    }
+
+    /// return whether the package described by `info` can be installed here
+    #[must_use]
+    pub fn accepts(&self, info: &PkgInfo) -> bool {
+        self.rank(&info.arch).is_some()
+    }
+
+    /**
+    return an error if `pkg` can't be installed here
+
+    The .PKGINFO's architectures are used, or the .BUILDINFO's `pkgarch` if
+    the .PKGINFO doesn't list any.
+    */
+    pub fn check(&self, pkg: &Pkg) -> Result<(), AetherError> {
+        let arch = match &pkg.buildinfo {
+            Some(buildinfo) if pkg.pkginfo.arch.is_empty() => &buildinfo.pkgarch,
+            _ => &pkg.pkginfo.arch,
+        };
+
+        if self.rank(arch).is_some() {
+            Ok(())
+        } else {
+            Err(AetherError::IncompatibleArch {
+                pkg: pkg.get_refstr(),
+                arch: arch.join(" "),
+                accepted: self.accepted.join(" "),
+            })
+        }
+    }
+
+    /// return the best of `pkgs` for these architectures: the newest
+    /// version, then the best matching architecture
+    fn best<'a>(&self, pkgs: impl Iterator<Item = &'a RepoPkg>) -> Option<&'a RepoPkg> {
+        pkgs.filter_map(|pkg| Some((pkg, self.rank(&pkg.pkginfo.arch)?)))
+            .max_by(
+                |(a, a_rank), (b, b_rank)| match vercmp(&a.pkginfo.pkgver, &b.pkginfo.pkgver) {
+                    Ordering::Equal => b_rank.cmp(a_rank),
+                    ordering => ordering,
+                },
+            )
+            .map(|(pkg, _)| pkg)
+    }
+}
+
+impl Default for Architectures {
+    fn default() -> Self {
+        Self::host()
+    }
+}
+
+impl Repo {
+    /// like [`Repo::get`], but only for packages `arches` accepts, preferring
+    /// the best matching architecture among builds of the newest version
+    #[must_use]
+    pub fn get_for(&self, name: &str, arches: &Architectures) -> Option<&RepoPkg> {
+        arches.best(self.pkgs().iter().filter(|pkg| pkg.pkginfo.pkgname == name))
+    }
+
+    /// like [`Repo::find`], but only for packages `arches` accepts,
+    /// preferring the best matching architecture among builds of the newest
+    /// version
+    #[must_use]
+    pub fn find_for(&self, depend: &Depend, arches: &Architectures) -> Option<&RepoPkg> {
+        let pkgs = self.pkgs();
+
+        arches
+            .best(
+                pkgs.iter()
+                    .filter(|pkg| depend.satisfied_by(&pkg.pkginfo.pkgname, &pkg.pkginfo.pkgver)),
+            )
+            .or_else(|| {
+                arches.best(
+                    pkgs.iter()
+                        .filter(|pkg| depend.satisfied_by_provides(&pkg.pkginfo.provides)),
+                )
+            })
+    }
+}
+
+impl PkgList {
+    #[must_use]
+    pub fn architectures(&self) -> &Architectures {
+        &self.architectures
+    }
+
+    /// set which architectures packages may be installed for
+    pub fn set_architectures(&mut self, architectures: Architectures) {
+        self.architectures = architectures;
+    }
+}
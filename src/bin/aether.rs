@@ -0,0 +1,1450 @@
+/*!
+# aether - install, remove and inspect packages in Aether environments
+
+Every subcommand works on the global environment unless `--env` names another
+one, and prints JSON instead of text with `--json`.
+
+Built only with the `cli` feature: `cargo build --features cli`.
+*/
+
+use clap::{Parser, Subcommand, ValueEnum};
+use libaether::{
+    sha256sum, vercmp, AetherError, Alternative, Architectures, Depend, Environment, ExportMode,
+    Exports, ExtractDir, GroupSelection, LinkedLib, Lockfile, Manifest, OptDepend, Pkg, PkgInfo,
+    PkgList, Relocation, RelocationReport, RemovalReport, Repo, RepoSource, ScriptletPolicy,
+    SearchQuery, Store, LOCK_FILE, MANIFEST_FILE,
+};
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs::{canonicalize, symlink_metadata};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+#[derive(Parser)]
+#[command(
+    name = "aether",
+    version,
+    about = "Manage packages in Aether environments"
+)]
+struct Cli {
+    /// operate on a named environment instead of the global one
+    #[arg(long, global = true, value_name = "NAME")]
+    env: Option<String>,
+
+    /// directory holding named environments, instead of the default one
+    #[arg(long, global = true, value_name = "DIR")]
+    root: Option<PathBuf>,
+
+    /// a sync repository to install from, searched in the order given;
+    /// defaults to the repositories of ./aether.toml
+    #[arg(long = "repo", global = true, value_name = "NAME=PATH", value_parser = parse_repo)]
+    repos: Vec<RepoSource>,
+
+    /// an architecture to accept packages for, best first; defaults to the
+    /// architectures of ./aether.toml, or the host's
+    #[arg(long = "arch", global = true, value_name = "ARCH")]
+    arches: Vec<String>,
+
+    /// rewrite hard-coded /usr paths in installed scripts, pkg-config,
+    /// libtool and config files to point into the package
+    #[arg(long, global = true)]
+    relocate: bool,
+
+    /// point the runpath of installed ELF files at their package's usr/lib
+    /// when they need a library from there; needs patchelf
+    #[arg(long, global = true)]
+    patch_runpath: bool,
+
+    /// don't relocate this package even with --relocate
+    #[arg(long, global = true, value_name = "NAME")]
+    no_relocate: Vec<String>,
+
+    /// export executables as wrapper scripts setting PATH, LD_LIBRARY_PATH
+    /// and the XDG directories for their package, instead of symlinks
+    #[arg(long, global = true)]
+    wrap: bool,
+
+    /// export this package's executables as wrapper scripts
+    #[arg(long, global = true, value_name = "NAME")]
+    wrap_pkg: Vec<String>,
+
+    /// export this package's executables as symlinks even with --wrap
+    #[arg(long, global = true, value_name = "NAME")]
+    no_wrap: Vec<String>,
+
+    /// how to run install scriptlets and hooks
+    #[arg(long, global = true, value_enum, default_value_t = Policy::Sandbox)]
+    scriptlets: Policy,
+
+    /// print JSON instead of text
+    #[arg(long, global = true)]
+    json: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// install packages from directories, archives or repositories
+    Install {
+        /// a package directory, a package archive, or a dependency string
+        /// like `hello>=1.0` to look up in the repositories
+        #[arg(required = true)]
+        targets: Vec<String>,
+        /// which members of the groups among the targets to install, like
+        /// `1-3 ^2` or `^name`; defaults to all of them
+        #[arg(long, value_name = "ITEMS")]
+        select: Option<String>,
+        /// install as a dependency of an installed package, so the targets
+        /// become orphans once nothing needs them anymore
+        #[arg(long, value_name = "NAME")]
+        dependency_of: Option<String>,
+    },
+    /// remove installed packages
+    Remove {
+        /// a package name, optionally with a version constraint
+        #[arg(required = true)]
+        names: Vec<String>,
+    },
+    /// replace installed packages with other versions
+    Upgrade {
+        /// like for install; without targets, every package with a newer
+        /// version in the repositories is upgraded
+        targets: Vec<String>,
+    },
+    /// inspect installed packages
+    #[command(subcommand)]
+    Query(Query),
+    /// check installed files against the packages' .MTREE
+    Verify {
+        /// the packages to check, or all of them
+        names: Vec<String>,
+    },
+    /// search the repositories and installed packages by name, provides,
+    /// groups and description
+    Search {
+        term: String,
+        /// treat the term as a regular expression instead of a substring
+        #[arg(long)]
+        regex: bool,
+    },
+    /// list the groups in the repositories, or the members of one
+    Groups { group: Option<String> },
+    /// manage environments and their generations
+    #[command(subcommand)]
+    Env(Env),
+    /// choose between installed packages providing the same executable
+    #[command(subcommand)]
+    Alternatives(Alternatives),
+    /// check the executables exported into the bin directory for dangling
+    /// and missing entries, and files in the way
+    Exports {
+        /// remove dangling entries and export missing ones
+        #[arg(long)]
+        repair: bool,
+    },
+    /// delete store objects no installed package uses
+    Gc,
+}
+
+#[derive(Subcommand)]
+enum Query {
+    /// list installed packages
+    List,
+    /// show a package's metadata
+    Info { name: String },
+    /// list a package's files
+    Files { name: String },
+    /// find the package a file belongs to
+    Owner { path: PathBuf },
+    /// list the groups of installed packages, or the installed members of one
+    Groups { group: Option<String> },
+    /// list the shared libraries a package's ELF files need and where they
+    /// are found
+    Libs {
+        name: String,
+        /// only list the ones that can't be found
+        #[arg(long)]
+        missing: bool,
+    },
+    /// list a package's optional dependencies and whether they're installed
+    Optdeps { name: String },
+    /// compare the sonames a package's libraries provide and need with its
+    /// declared provides and depends
+    Sonames { name: String },
+    /// list the installed packages that would gain features if a package
+    /// were installed
+    Enables { name: String },
+    /// list packages installed as dependencies that nothing needs anymore
+    Orphans,
+}
+
+#[derive(Subcommand)]
+enum Env {
+    /// list named environments
+    List,
+    /// create a named environment
+    Create { name: String },
+    /// delete a named environment and everything installed in it
+    Destroy { name: String },
+    /// list the generations of the environment
+    Generations,
+    /// make an earlier generation the active one
+    Rollback { number: u64 },
+    /// run a command with the environment's packages mounted over /usr,
+    /// exiting with its exit code
+    Run {
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+    /// resolve a project's aether.toml into aether.lock
+    Lock {
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+    },
+    /// install and remove packages until the environment matches aether.lock
+    Sync {
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum Alternatives {
+    /// list the executables more than one installed package provides
+    List,
+    /// make a package provide an executable, even after upgrades
+    Set { name: String, pkg: String },
+    /// go back to picking the provider of an executable by priority
+    Auto { name: String },
+    /// set how strongly a package is preferred as a provider; higher wins
+    Priority {
+        pkg: String,
+        #[arg(allow_hyphen_values = true)]
+        priority: i64,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Policy {
+    Run,
+    Sandbox,
+    Skip,
+}
+
+impl From<Policy> for ScriptletPolicy {
+    fn from(policy: Policy) -> Self {
+        match policy {
+            Policy::Run => ScriptletPolicy::Run,
+            Policy::Sandbox => ScriptletPolicy::Sandbox,
+            Policy::Skip => ScriptletPolicy::Skip,
+        }
+    }
+}
+
+fn parse_repo(s: &str) -> Result<RepoSource, String> {
+    match s.split_once('=') {
+        Some((name, path)) if !name.is_empty() && !path.is_empty() => Ok(RepoSource {
+            name: name.into(),
+            path: path.into(),
+        }),
+        _ => Err(format!("expected NAME=PATH, found '{}'", s)),
+    }
+}
+
+/// the packages a transaction changed and what their scriptlets and hooks
+/// printed
+#[derive(Default, Serialize)]
+struct Transaction {
+    installed: Vec<String>,
+    removed: Vec<String>,
+    upgraded: Vec<Upgraded>,
+    scriptlets: Vec<libaether::ScriptletOutput>,
+    hooks: Vec<libaether::HookOutput>,
+    relocated: Vec<RelocationReport>,
+    /// what removing packages deleted
+    deleted: Vec<RemovalReport>,
+    /// the libraries of installed and upgraded packages that can't be found
+    missing_libs: BTreeMap<String, Vec<LinkedLib>>,
+    /// the optdepends of installed and upgraded packages that nothing
+    /// installed satisfies
+    missing_optdepends: BTreeMap<String, Vec<OptDepend>>,
+}
+
+#[derive(Serialize)]
+struct Upgraded {
+    from: String,
+    to: String,
+}
+
+impl Transaction {
+    fn finish(mut self, pkglist: &PkgList) -> Result<Self, AetherError> {
+        self.scriptlets = pkglist.scriptlet_output().clone();
+        self.hooks = pkglist.hook_output().clone();
+        self.relocated = pkglist.relocation_output().clone();
+
+        let changed = self
+            .installed
+            .iter()
+            .chain(self.upgraded.iter().map(|upgraded| &upgraded.to));
+        for refstr in changed {
+            if let Some(pkg) = pkglist
+                .pkgs()
+                .iter()
+                .find(|pkg| pkg.get_refstr() == *refstr)
+            {
+                let missing = pkglist.missing_optdepends(pkg);
+                if !missing.is_empty() {
+                    self.missing_optdepends.insert(refstr.clone(), missing);
+                }
+
+                let missing = pkglist.missing_libs(pkg)?;
+                if !missing.is_empty() {
+                    self.missing_libs.insert(refstr.clone(), missing);
+                }
+            }
+        }
+
+        Ok(self)
+    }
+
+    fn text(&self) -> String {
+        let mut text = String::new();
+
+        for output in &self.scriptlets {
+            if let Some(reason) = &output.skipped {
+                writeln!(
+                    text,
+                    "warning: skipped {} {}: {}",
+                    output.pkg,
+                    output.stage.function(),
+                    reason
+                )
+                .unwrap();
+            }
+
+            let out = format!("{}{}", output.stdout, output.stderr);
+            if !out.is_empty() {
+                writeln!(text, "==> {} {}", output.pkg, output.stage.function()).unwrap();
+                text.push_str(&out);
+            }
+        }
+        for output in &self.hooks {
+            if let Some(reason) = &output.skipped {
+                writeln!(text, "warning: skipped hook {}: {}", output.hook, reason).unwrap();
+                continue;
+            }
+
+            let out = format!("{}{}", output.stdout, output.stderr);
+            if !out.is_empty() || !output.success() {
+                writeln!(text, "==> hook {}", output.hook).unwrap();
+                text.push_str(&out);
+            }
+        }
+
+        for refstr in &self.removed {
+            writeln!(text, "removed {}", refstr).unwrap();
+        }
+        for refstr in &self.installed {
+            writeln!(text, "installed {}", refstr).unwrap();
+        }
+        for upgraded in &self.upgraded {
+            writeln!(text, "upgraded {} -> {}", upgraded.from, upgraded.to).unwrap();
+        }
+        for report in &self.deleted {
+            match &report.kept {
+                Some(kept) => writeln!(
+                    text,
+                    "kept {} at {} for rolling back",
+                    report.pkg,
+                    kept.display()
+                ),
+                None => writeln!(
+                    text,
+                    "deleted {} files of {} from {}",
+                    report.files.len(),
+                    report.pkg,
+                    report.path.display()
+                ),
+            }
+            .unwrap();
+        }
+        for report in &self.relocated {
+            writeln!(
+                text,
+                "relocated {} files of {} to {}",
+                report.files.len(),
+                report.pkg,
+                report.prefix.display()
+            )
+            .unwrap();
+        }
+        for (refstr, missing) in &self.missing_libs {
+            writeln!(text, "missing libraries for {}:", refstr).unwrap();
+            for lib in missing {
+                writeln!(
+                    text,
+                    "    {} (needed by {})",
+                    lib.needed,
+                    lib.file.display()
+                )
+                .unwrap();
+            }
+        }
+        for (refstr, missing) in &self.missing_optdepends {
+            writeln!(text, "optional dependencies for {}:", refstr).unwrap();
+            for optdepend in missing {
+                writeln!(text, "    {}", optdepend).unwrap();
+            }
+        }
+
+        text
+    }
+}
+
+#[derive(Serialize)]
+struct Optdep {
+    optdepend: OptDepend,
+    installed: bool,
+}
+
+#[derive(Serialize)]
+struct Enabled {
+    pkg: String,
+    optdepend: OptDepend,
+}
+
+#[derive(Serialize)]
+struct Owner {
+    path: PathBuf,
+    pkg: String,
+}
+
+#[derive(Serialize)]
+struct Verified {
+    pkg: String,
+    missing: Vec<PathBuf>,
+    modified: Vec<PathBuf>,
+}
+
+#[derive(Serialize)]
+struct Generations {
+    current: Option<u64>,
+    generations: Vec<libaether::Generation>,
+}
+
+/// a package read from a directory, possibly extracted from an archive
+/// into a directory that's deleted again along with it
+struct Staged {
+    pkg: Pkg,
+    /// only held so the directory lives as long as the package
+    _extracted: Option<ExtractDir>,
+}
+
+struct Context {
+    cli: Cli,
+    repos: Option<Vec<Repo>>,
+}
+
+impl Context {
+    fn env(&self) -> Result<Environment, AetherError> {
+        match (&self.cli.env, &self.cli.root) {
+            (Some(name), Some(root)) => Environment::open_in(name, root),
+            (Some(name), None) => Environment::open(name),
+            (None, Some(root)) => Err(AetherError::InvalidValue {
+                key: "--root".into(),
+                value: format!("{} (requires --env)", root.display()),
+            }),
+            (None, None) => Ok(Environment::global()),
+        }
+    }
+
+    fn pkglist(&self, env: &Environment) -> Result<PkgList, AetherError> {
+        env.ensure_dirs()?;
+
+        let mut pkglist = env.pkglist()?;
+        pkglist.set_scriptlet_policy(self.cli.scriptlets.into());
+        pkglist.set_architectures(self.architectures()?);
+
+        let mut relocation = if self.cli.relocate {
+            Relocation::enabled()
+        } else {
+            Relocation::disabled()
+        };
+        if self.cli.patch_runpath {
+            relocation.patch_runpath();
+        }
+        for name in &self.cli.no_relocate {
+            relocation.skip(name);
+        }
+        pkglist.set_relocation(relocation);
+
+        let mut exports = Exports::new(if self.cli.wrap {
+            ExportMode::Wrapper
+        } else {
+            ExportMode::Symlink
+        });
+        for name in &self.cli.wrap_pkg {
+            exports.set(name, ExportMode::Wrapper);
+        }
+        for name in &self.cli.no_wrap {
+            exports.set(name, ExportMode::Symlink);
+        }
+        pkglist.set_exports(exports);
+
+        Ok(pkglist)
+    }
+
+    /// the architectures from `--arch` or ./aether.toml
+    fn architectures(&self) -> Result<Architectures, AetherError> {
+        if !self.cli.arches.is_empty() {
+            Ok(Architectures::new(&self.cli.arches))
+        } else if Path::new(MANIFEST_FILE).is_file() {
+            Ok(Manifest::parse(&MANIFEST_FILE)?.architectures())
+        } else {
+            Ok(Architectures::host())
+        }
+    }
+
+    /// load the repositories once, from `--repo` or ./aether.toml
+    fn repos(&mut self) -> Result<&[Repo], AetherError> {
+        if self.repos.is_none() {
+            let sources = if !self.cli.repos.is_empty() {
+                self.cli.repos.clone()
+            } else if Path::new(MANIFEST_FILE).is_file() {
+                Manifest::parse(&MANIFEST_FILE)?.repos
+            } else {
+                vec![]
+            };
+
+            let repos = sources
+                .iter()
+                .map(RepoSource::load)
+                .collect::<Result<_, _>>()?;
+            self.repos = Some(repos);
+        }
+
+        Ok(self.repos.as_deref().unwrap_or_default())
+    }
+
+    /// replace the targets that name a group in the repositories rather than
+    /// a package with the selected members of the group, marking those
+    fn expand_groups(
+        &mut self,
+        targets: &[String],
+        selection: &GroupSelection,
+    ) -> Result<Vec<(String, bool)>, AetherError> {
+        let mut expanded = vec![];
+
+        for target in targets {
+            if Path::new(target).exists() {
+                expanded.push((target.clone(), false));
+                continue;
+            }
+
+            let arches = self.architectures()?;
+            let repos = self.repos()?;
+            let depend = Depend::try_parse(target)?;
+            if repos
+                .iter()
+                .any(|repo| repo.find_for(&depend, &arches).is_some())
+            {
+                expanded.push((target.clone(), false));
+                continue;
+            }
+
+            let mut members: Vec<String> = vec![];
+            for repo in repos {
+                for pkg in repo.group(target) {
+                    if !members.contains(&pkg.pkginfo.pkgname) {
+                        members.push(pkg.pkginfo.pkgname.clone());
+                    }
+                }
+            }
+
+            if members.is_empty() {
+                // not a group either, which staging reports
+                expanded.push((target.clone(), false));
+            } else {
+                for name in selection.select(&members)? {
+                    expanded.push((name, true));
+                }
+            }
+        }
+
+        Ok(expanded)
+    }
+
+    /// read a package from a directory, an archive or the repositories
+    fn stage(&mut self, target: &str) -> Result<Staged, AetherError> {
+        let path = Path::new(target);
+
+        if path.is_dir() {
+            return Ok(Staged {
+                pkg: Pkg::from_dir(&path)?,
+                _extracted: None,
+            });
+        }
+
+        if path.is_file() {
+            let dest = ExtractDir::new(&path.file_name().unwrap_or(path.as_os_str()))?;
+
+            return Ok(Staged {
+                pkg: Pkg::from_archive(&path, &dest.path())?,
+                _extracted: Some(dest),
+            });
+        }
+
+        let depend = Depend::try_parse(target)?;
+        let arches = self.architectures()?;
+        for repo in self.repos()? {
+            if let Some(repo_pkg) = repo.find_for(&depend, &arches) {
+                let dest = ExtractDir::new(&repo_pkg.get_refstr())?;
+
+                return Ok(Staged {
+                    pkg: repo.extract(repo_pkg, &dest.path())?,
+                    _extracted: Some(dest),
+                });
+            }
+        }
+
+        Err(AetherError::MissingPkg {
+            name: depend.name,
+            ver: depend.constraint.to_string(),
+        })
+    }
+
+    /// print `value` as JSON, or the text `text` returns
+    fn show<T: Serialize + ?Sized>(
+        &self,
+        value: &T,
+        text: impl FnOnce() -> String,
+    ) -> Result<(), AetherError> {
+        if self.cli.json {
+            println!("{}", to_json(value)?);
+        } else {
+            let text = text();
+            if !text.is_empty() {
+                print!("{}", text);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn to_json<T: Serialize + ?Sized>(value: &T) -> Result<String, AetherError> {
+    serde_json::to_string_pretty(value).map_err(|err| AetherError::InvalidValue {
+        key: "JSON output".into(),
+        value: err.to_string(),
+    })
+}
+
+/// find the installed package matching `name`, which may carry a version
+/// constraint
+fn installed<'a>(pkglist: &'a PkgList, name: &str) -> Result<&'a Pkg, AetherError> {
+    let depend = Depend::try_parse(name)?;
+
+    pkglist
+        .pkgs()
+        .iter()
+        .find(|pkg| depend.satisfied_by(&pkg.pkginfo.pkgname, &pkg.pkginfo.pkgver))
+        .ok_or(AetherError::MissingPkg {
+            name: depend.name,
+            ver: depend.constraint.to_string(),
+        })
+}
+
+fn install(
+    ctx: &mut Context,
+    targets: &[String],
+    select: Option<&str>,
+    dependency_of: Option<&str>,
+) -> Result<ExitCode, AetherError> {
+    let env = ctx.env()?;
+    let mut pkglist = ctx.pkglist(&env)?;
+    let mut transaction = Transaction::default();
+
+    let selection = match select {
+        Some(select) => GroupSelection::parse(select)?,
+        None => GroupSelection::all(),
+    };
+
+    for (target, from_group) in ctx.expand_groups(targets, &selection)? {
+        let is_installed = |name: &str| {
+            pkglist
+                .pkgs()
+                .iter()
+                .find(|old| old.pkginfo.pkgname == name)
+                .cloned()
+        };
+
+        // installed members of a group are skipped like in pacman
+        if from_group && is_installed(&target).is_some() {
+            continue;
+        }
+
+        let staged = ctx.stage(&target)?;
+        let pkg = &staged.pkg;
+
+        if let Some(old) = is_installed(&pkg.pkginfo.pkgname) {
+            return Err(AetherError::AlreadyExists(format!(
+                "{} is already installed",
+                old.get_refstr()
+            )));
+        }
+
+        match dependency_of {
+            Some(of) => pkglist.install_as_dependency(pkg.clone(), of)?,
+            None => pkglist.install(pkg.clone())?,
+        };
+        transaction.installed.push(pkg.get_refstr());
+    }
+
+    let transaction = transaction.finish(&pkglist)?;
+    ctx.show(&transaction, || transaction.text())?;
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn remove(ctx: &mut Context, names: &[String]) -> Result<ExitCode, AetherError> {
+    let env = ctx.env()?;
+    let mut pkglist = ctx.pkglist(&env)?;
+    let mut transaction = Transaction::default();
+
+    for name in names {
+        let pkg = installed(&pkglist, name)?.clone();
+
+        let report = pkglist.remove(&pkg)?;
+        transaction.removed.push(pkg.get_refstr());
+        transaction.deleted.push(report);
+    }
+
+    let transaction = transaction.finish(&pkglist)?;
+    ctx.show(&transaction, || transaction.text())?;
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn upgrade(ctx: &mut Context, targets: &[String]) -> Result<ExitCode, AetherError> {
+    let env = ctx.env()?;
+    let mut pkglist = ctx.pkglist(&env)?;
+    let mut transaction = Transaction::default();
+
+    let targets = if targets.is_empty() {
+        outdated(ctx, &pkglist)?
+    } else {
+        targets.to_vec()
+    };
+
+    for target in &targets {
+        let staged = ctx.stage(target)?;
+        let new = &staged.pkg;
+        let old = installed(&pkglist, &new.pkginfo.pkgname)?.clone();
+
+        if old.get_refstr() == new.get_refstr() {
+            continue;
+        }
+
+        pkglist.upgrade(&old, new.clone())?;
+        transaction.upgraded.push(Upgraded {
+            from: old.get_refstr(),
+            to: new.get_refstr(),
+        });
+    }
+
+    let transaction = transaction.finish(&pkglist)?;
+    ctx.show(&transaction, || transaction.text())?;
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// the installed packages that have a newer version in the repositories, as
+/// exact dependency strings
+fn outdated(ctx: &mut Context, pkglist: &PkgList) -> Result<Vec<String>, AetherError> {
+    let repos = ctx.repos()?;
+    let mut targets = vec![];
+
+    for pkg in pkglist.pkgs() {
+        let info = &pkg.pkginfo;
+        let newest = repos
+            .iter()
+            .filter_map(|repo| repo.get_for(&info.pkgname, pkglist.architectures()))
+            .max_by(|a, b| vercmp(&a.pkginfo.pkgver, &b.pkginfo.pkgver));
+
+        if let Some(newest) = newest {
+            if vercmp(&newest.pkginfo.pkgver, &info.pkgver) == Ordering::Greater {
+                targets.push(format!("{}={}", info.pkgname, newest.pkginfo.pkgver));
+            }
+        }
+    }
+
+    Ok(targets)
+}
+
+fn query(ctx: &mut Context, query: &Query) -> Result<ExitCode, AetherError> {
+    let env = ctx.env()?;
+    let pkglist = env.pkglist()?;
+
+    match query {
+        Query::List => {
+            let mut pkgs: Vec<&Pkg> = pkglist.pkgs().iter().collect();
+            pkgs.sort_by(|a, b| a.pkginfo.pkgname.cmp(&b.pkginfo.pkgname));
+
+            let infos: Vec<_> = pkgs.iter().map(|pkg| &pkg.pkginfo).collect();
+            ctx.show(&infos, || {
+                infos.iter().fold(String::new(), |mut text, info| {
+                    writeln!(text, "{} {}", info.pkgname, info.pkgver).unwrap();
+                    text
+                })
+            })?;
+        }
+        Query::Info { name } => {
+            let pkg = installed(&pkglist, name)?;
+            ctx.show(pkg, || info_text(pkg))?;
+        }
+        Query::Files { name } => {
+            let pkg = installed(&pkglist, name)?;
+            let files = pkg_files(pkg);
+
+            ctx.show(&files, || {
+                files.iter().fold(String::new(), |mut text, file| {
+                    writeln!(text, "{} {}", pkg.pkginfo.pkgname, file.display()).unwrap();
+                    text
+                })
+            })?;
+        }
+        Query::Groups { group } => {
+            show_groups(ctx, pkglist.groups(), group.as_deref())?;
+        }
+        Query::Libs { name, missing } => {
+            let pkg = installed(&pkglist, name)?;
+            let libs = if *missing {
+                pkglist.missing_libs(pkg)?
+            } else {
+                pkglist.linked_libs(pkg)?
+            };
+
+            ctx.show(&libs, || {
+                libs.iter().fold(String::new(), |mut text, lib| {
+                    let resolved = lib
+                        .resolved
+                        .as_ref()
+                        .map_or("not found".into(), |path| path.display().to_string());
+                    writeln!(
+                        text,
+                        "{}: {} => {}",
+                        lib.file.display(),
+                        lib.needed,
+                        resolved
+                    )
+                    .unwrap();
+                    text
+                })
+            })?;
+        }
+        Query::Sonames { name } => {
+            let check = installed(&pkglist, name)?.check_sonames()?;
+
+            ctx.show(&check, || {
+                let mut text = String::new();
+                for soname in &check.provides {
+                    writeln!(text, "provides {}", soname).unwrap();
+                }
+                for soname in &check.needs {
+                    writeln!(text, "needs {}", soname).unwrap();
+                }
+                for soname in &check.undeclared_provides {
+                    writeln!(text, "undeclared provide {}", soname).unwrap();
+                }
+                for soname in &check.undeclared_depends {
+                    writeln!(text, "undeclared depend {}", soname).unwrap();
+                }
+                for provide in &check.stale_provides {
+                    writeln!(text, "stale provide {}", provide).unwrap();
+                }
+                text
+            })?;
+        }
+        Query::Optdeps { name } => {
+            let pkg = installed(&pkglist, name)?;
+            let optdepends: Vec<Optdep> = pkg
+                .pkginfo
+                .optdepends()
+                .into_iter()
+                .map(|optdepend| Optdep {
+                    installed: pkglist.is_satisfied(&optdepend.depend),
+                    optdepend,
+                })
+                .collect();
+
+            ctx.show(&optdepends, || {
+                optdepends.iter().fold(String::new(), |mut text, optdep| {
+                    let marker = if optdep.installed { " [installed]" } else { "" };
+                    writeln!(text, "{}{}", optdep.optdepend, marker).unwrap();
+                    text
+                })
+            })?;
+        }
+        Query::Enables { name } => {
+            let depend = Depend::try_parse(name)?;
+            let from_repo = ctx
+                .repos()?
+                .iter()
+                .find_map(|repo| repo.find_for(&depend, pkglist.architectures()))
+                .map(|repo_pkg| repo_pkg.pkginfo.clone());
+            let info = from_repo.unwrap_or_else(|| {
+                let mut info = PkgInfo::new();
+                info.pkgname = depend.name.clone();
+                info.pkgver = depend.constraint.version.clone();
+                info
+            });
+
+            let enabled: Vec<Enabled> = pkglist
+                .enabled_by(&info)
+                .into_iter()
+                .map(|(pkg, optdepend)| Enabled {
+                    pkg: pkg.get_refstr(),
+                    optdepend,
+                })
+                .collect();
+
+            ctx.show(&enabled, || {
+                enabled.iter().fold(String::new(), |mut text, enabled| {
+                    writeln!(text, "{}: {}", enabled.pkg, enabled.optdepend).unwrap();
+                    text
+                })
+            })?;
+        }
+        Query::Orphans => {
+            let orphans: Vec<String> = pkglist
+                .orphans()?
+                .iter()
+                .map(|pkg| pkg.get_refstr())
+                .collect();
+
+            ctx.show(&orphans, || {
+                orphans
+                    .iter()
+                    .map(|refstr| format!("{}\n", refstr))
+                    .collect()
+            })?;
+        }
+        Query::Owner { path } => {
+            let pkg = owner(&pkglist, path)?;
+            let owner = Owner {
+                path: path.clone(),
+                pkg: pkg.get_refstr(),
+            };
+
+            ctx.show(&owner, || {
+                format!(
+                    "{} is owned by {} {}\n",
+                    path.display(),
+                    pkg.pkginfo.pkgname,
+                    pkg.pkginfo.pkgver
+                )
+            })?;
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// a package's installed files, without its metadata files
+fn pkg_files(pkg: &Pkg) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = pkg
+        .files
+        .iter()
+        .filter(|file| {
+            file.strip_prefix(&pkg.path)
+                .is_ok_and(|rel| !rel.to_string_lossy().starts_with('.'))
+        })
+        .cloned()
+        .collect();
+    files.sort();
+
+    files
+}
+
+/// find the package owning `path`, which is either a path on disk (e.g. an
+/// exported executable) or relative to the package roots, like `usr/bin/foo`
+fn owner<'a>(pkglist: &'a PkgList, path: &Path) -> Result<&'a Pkg, AetherError> {
+    let resolved = canonicalize(path).ok();
+    let rel = path.strip_prefix("/").unwrap_or(path);
+
+    pkglist
+        .pkgs()
+        .iter()
+        .find(|pkg| {
+            let root = canonicalize(&pkg.path).unwrap_or_else(|_| pkg.path.clone());
+
+            pkg_files(pkg).iter().any(|file| {
+                let on_disk = file
+                    .strip_prefix(&pkg.path)
+                    .map(|file_rel| root.join(file_rel))
+                    .ok();
+
+                resolved.is_some() && on_disk == resolved || file.strip_prefix(&pkg.path) == Ok(rel)
+            })
+        })
+        .ok_or_else(|| AetherError::MissingFile(vec![path.into()]))
+}
+
+fn info_text(pkg: &Pkg) -> String {
+    let info = &pkg.pkginfo;
+    let list = |values: &[String]| {
+        if values.is_empty() {
+            "None".to_string()
+        } else {
+            values.join("  ")
+        }
+    };
+    let single = |value: &str| {
+        if value.is_empty() {
+            "None".to_string()
+        } else {
+            value.to_string()
+        }
+    };
+
+    let fields = [
+        ("Name", info.pkgname.clone()),
+        ("Version", info.pkgver.clone()),
+        ("Description", single(&info.pkgdesc)),
+        ("Architecture", list(&info.arch)),
+        ("URL", single(&info.url)),
+        ("Licenses", list(&info.license)),
+        ("Groups", list(&info.group)),
+        ("Provides", list(&info.provides)),
+        ("Depends On", list(&info.depend)),
+        ("Optional Deps", list(&info.optdepend)),
+        ("Conflicts With", list(&info.conflict)),
+        ("Replaces", list(&info.replaces)),
+        ("Installed Size", format!("{} B", info.size)),
+        ("Packager", single(&info.packager)),
+        ("Build Date", info.builddate.to_string()),
+        (
+            "Install Script",
+            if pkg.scriptlet.is_some() { "Yes" } else { "No" }.to_string(),
+        ),
+        ("Path", pkg.path.display().to_string()),
+    ];
+
+    fields.iter().fold(String::new(), |mut text, (key, value)| {
+        writeln!(text, "{:<15} : {}", key, value).unwrap();
+        text
+    })
+}
+
+fn verify(ctx: &mut Context, names: &[String]) -> Result<ExitCode, AetherError> {
+    let env = ctx.env()?;
+    let pkglist = env.pkglist()?;
+
+    let pkgs = if names.is_empty() {
+        pkglist.pkgs().iter().collect()
+    } else {
+        names
+            .iter()
+            .map(|name| installed(&pkglist, name))
+            .collect::<Result<Vec<_>, _>>()?
+    };
+
+    let mut results = vec![];
+    for pkg in pkgs {
+        let mut verified = Verified {
+            pkg: pkg.get_refstr(),
+            missing: vec![],
+            modified: vec![],
+        };
+        let relocated = pkg.relocated()?;
+
+        for entry in pkg.mtree.entries()? {
+            let file = pkg.path.join(entry.path());
+            let rel = file.strip_prefix(&pkg.path).unwrap_or(&file);
+            if rel.to_string_lossy().starts_with('.') {
+                continue;
+            }
+
+            if symlink_metadata(&file).is_err() {
+                verified.missing.push(file);
+                continue;
+            }
+
+            if let (Some(mtree::FileType::File), Some(digest)) = (entry.file_type(), entry.sha256())
+            {
+                let expected = digest.iter().fold(String::new(), |mut hex, b| {
+                    write!(hex, "{:02x}", b).unwrap();
+                    hex
+                });
+
+                // relocated files are checked against their relocated contents
+                let expected = relocated
+                    .iter()
+                    .find(|relocated| relocated.path == rel)
+                    .map_or(expected, |relocated| relocated.sha256.clone());
+
+                if sha256sum(&file)? != expected {
+                    verified.modified.push(file);
+                }
+            }
+        }
+
+        results.push(verified);
+    }
+
+    ctx.show(&results, || {
+        let mut text = String::new();
+        for verified in &results {
+            for file in &verified.missing {
+                writeln!(text, "{}: missing {}", verified.pkg, file.display()).unwrap();
+            }
+            for file in &verified.modified {
+                writeln!(text, "{}: modified {}", verified.pkg, file.display()).unwrap();
+            }
+        }
+        text
+    })?;
+
+    let clean = results
+        .iter()
+        .all(|verified| verified.missing.is_empty() && verified.modified.is_empty());
+
+    Ok(if clean {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+/// print a map of groups to members, or the members of just `group`
+fn show_groups(
+    ctx: &Context,
+    mut groups: BTreeMap<String, Vec<String>>,
+    group: Option<&str>,
+) -> Result<(), AetherError> {
+    if let Some(group) = group {
+        groups.retain(|name, _| name == group);
+    }
+
+    ctx.show(&groups, || {
+        let mut text = String::new();
+        for (group, members) in &groups {
+            for member in members {
+                writeln!(text, "{} {}", group, member).unwrap();
+            }
+        }
+        text
+    })
+}
+
+fn groups(ctx: &mut Context, group: Option<&str>) -> Result<ExitCode, AetherError> {
+    let mut groups: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for repo in ctx.repos()? {
+        for (name, members) in repo.groups() {
+            let all = groups.entry(name).or_default();
+            all.extend(members);
+            all.sort();
+            all.dedup();
+        }
+    }
+
+    show_groups(ctx, groups, group)?;
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn search(ctx: &mut Context, term: &str, regex: bool) -> Result<ExitCode, AetherError> {
+    let query = if regex {
+        SearchQuery::regex(term)?
+    } else {
+        SearchQuery::substring(term)
+    };
+
+    let env = ctx.env()?;
+    let pkglist = ctx.pkglist(&env)?;
+    let found = pkglist.search(&query, ctx.repos()?);
+
+    ctx.show(&found, || {
+        found.iter().fold(String::new(), |mut text, result| {
+            let info = &result.pkginfo;
+            let repo = result.repo.as_deref().unwrap_or("local");
+            let marker = match &result.installed {
+                Some(_) if result.is_installed() => " [installed]".to_string(),
+                Some(version) => format!(" [installed: {}]", version),
+                None => String::new(),
+            };
+
+            writeln!(text, "{}/{} {}{}", repo, info.pkgname, info.pkgver, marker).unwrap();
+            writeln!(text, "    {}", info.pkgdesc).unwrap();
+            text
+        })
+    })?;
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn env(ctx: &mut Context, command: &Env) -> Result<ExitCode, AetherError> {
+    let root = ctx.cli.root.clone();
+
+    match command {
+        Env::List => {
+            let names = match &root {
+                Some(root) => Environment::list_in(root)?,
+                None => Environment::list()?,
+            };
+
+            ctx.show(&names, || {
+                names.iter().map(|name| format!("{}\n", name)).collect()
+            })?;
+        }
+        Env::Create { name } => {
+            let env = match &root {
+                Some(root) => Environment::create_in(name, root)?,
+                None => Environment::create(name)?,
+            };
+
+            ctx.show(&env.root(), || {
+                format!("created environment {}\n", env.name())
+            })?;
+        }
+        Env::Destroy { name } => {
+            let env = match &root {
+                Some(root) => Environment::open_in(name, root)?,
+                None => Environment::open(name)?,
+            };
+            env.destroy()?;
+
+            ctx.show(name, || format!("destroyed environment {}\n", name))?;
+        }
+        Env::Generations => {
+            let env = ctx.env()?;
+            let generations = Generations {
+                current: env.current_generation()?,
+                generations: env.list_generations()?,
+            };
+
+            ctx.show(&generations, || {
+                let mut text = String::new();
+                for generation in &generations.generations {
+                    let marker = if generations.current == Some(generation.number) {
+                        "*"
+                    } else {
+                        " "
+                    };
+                    writeln!(
+                        text,
+                        "{} {:>4}  {}  {} packages",
+                        marker,
+                        generation.number,
+                        generation.created,
+                        generation.pkgs.len()
+                    )
+                    .unwrap();
+                }
+                text
+            })?;
+        }
+        Env::Rollback { number } => {
+            let generation = ctx.env()?.rollback_to(*number)?;
+
+            ctx.show(&generation, || {
+                format!("rolled back to generation {}\n", generation.number)
+            })?;
+        }
+        Env::Run { command } => {
+            let status = ctx
+                .env()?
+                .run(std::process::Command::new(&command[0]).args(&command[1..]))?;
+
+            // a command killed by a signal has no exit code
+            let code = status.code().unwrap_or(1);
+            return Ok(ExitCode::from(u8::try_from(code).unwrap_or(1)));
+        }
+        Env::Lock { dir } => {
+            let lock = Manifest::from_dir(dir)?.resolve()?;
+            let file = dir.join(LOCK_FILE);
+            lock.write_to(&file)?;
+
+            ctx.show(&lock, || {
+                format!(
+                    "locked {} packages in {}\n",
+                    lock.packages.len(),
+                    file.display()
+                )
+            })?;
+        }
+        Env::Sync { dir } => {
+            let lock = Lockfile::from_dir(dir)?;
+            let report = ctx.env()?.sync_to_lock(&lock)?;
+
+            ctx.show(&report, || {
+                let mut text = String::new();
+                for refstr in &report.removed {
+                    writeln!(text, "removed {}", refstr).unwrap();
+                }
+                for refstr in &report.installed {
+                    writeln!(text, "installed {}", refstr).unwrap();
+                }
+                text
+            })?;
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn alternatives_text(alternatives: &[Alternative]) -> String {
+    let mut text = String::new();
+    for alternative in alternatives {
+        writeln!(text, "{}:", alternative.name).unwrap();
+        for provider in &alternative.providers {
+            let marker = if provider.pkg == alternative.active {
+                "*"
+            } else {
+                " "
+            };
+            writeln!(
+                text,
+                "  {} {} ({})",
+                marker, provider.pkg, provider.priority
+            )
+            .unwrap();
+        }
+    }
+    text
+}
+
+fn alternatives(ctx: &mut Context, command: &Alternatives) -> Result<ExitCode, AetherError> {
+    let env = ctx.env()?;
+    let mut pkglist = ctx.pkglist(&env)?;
+
+    let alternatives = match command {
+        Alternatives::List => pkglist.alternatives()?,
+        Alternatives::Set { name, pkg } => vec![pkglist.choose_alternative(name, Some(pkg))?],
+        Alternatives::Auto { name } => vec![pkglist.choose_alternative(name, None)?],
+        Alternatives::Priority { pkg, priority } => {
+            let refstr = installed(&pkglist, pkg)?.get_refstr();
+            pkglist.set_alternative_priority(pkg, *priority)?;
+
+            let mut alternatives = pkglist.alternatives()?;
+            alternatives.retain(|alternative| {
+                alternative
+                    .providers
+                    .iter()
+                    .any(|provider| provider.pkg == refstr)
+            });
+            alternatives
+        }
+    };
+
+    ctx.show(&alternatives, || alternatives_text(&alternatives))?;
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn exports(ctx: &mut Context, repair: bool) -> Result<ExitCode, AetherError> {
+    let env = ctx.env()?;
+    let mut pkglist = ctx.pkglist(&env)?;
+
+    let check = if repair {
+        pkglist.repair_exports()?
+    } else {
+        pkglist.check_exports()?
+    };
+
+    ctx.show(&check, || {
+        let mut text = String::new();
+        for (problem, files) in [
+            ("dangling", &check.dangling),
+            ("missing", &check.missing),
+            ("foreign", &check.foreign),
+        ] {
+            for file in files {
+                writeln!(text, "{} {}", problem, file.display()).unwrap();
+            }
+        }
+        text
+    })?;
+
+    // after repairing, only files aether won't touch are left
+    let clean = if repair {
+        check.foreign.is_empty()
+    } else {
+        check.is_ok()
+    };
+
+    Ok(if clean {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+fn gc(ctx: &mut Context) -> Result<ExitCode, AetherError> {
+    let report = Store::new().gc()?;
+
+    ctx.show(&report, || {
+        format!(
+            "removed {} objects, {} bytes\n",
+            report.objects, report.bytes
+        )
+    })?;
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn run(ctx: &mut Context) -> Result<ExitCode, AetherError> {
+    // the command is moved out so the handlers can borrow the context
+    let command = std::mem::replace(&mut ctx.cli.command, Command::Gc);
+
+    match &command {
+        Command::Install {
+            targets,
+            select,
+            dependency_of,
+        } => install(ctx, targets, select.as_deref(), dependency_of.as_deref()),
+        Command::Remove { names } => remove(ctx, names),
+        Command::Upgrade { targets } => upgrade(ctx, targets),
+        Command::Query(query_command) => query(ctx, query_command),
+        Command::Verify { names } => verify(ctx, names),
+        Command::Search { term, regex } => search(ctx, term, *regex),
+        Command::Groups { group } => groups(ctx, group.as_deref()),
+        Command::Env(env_command) => env(ctx, env_command),
+        Command::Alternatives(alternatives_command) => alternatives(ctx, alternatives_command),
+        Command::Exports { repair } => exports(ctx, *repair),
+        Command::Gc => gc(ctx),
+    }
+}
+
+fn main() -> ExitCode {
+    let mut ctx = Context {
+        cli: Cli::parse(),
+        repos: None,
+    };
+
+    match run(&mut ctx) {
+        Ok(code) => code,
+        Err(err) => {
+            if ctx.cli.json {
+                let error = serde_json::json!({ "error": err });
+                if let Ok(json) = to_json(&error) {
+                    println!("{}", json);
+                }
+            } else {
+                eprintln!("error: {}", err);
+
+                let mut source = std::error::Error::source(&err);
+                while let Some(err) = source {
+                    eprintln!("  caused by: {}", err);
+                    source = err.source();
+                }
+            }
+
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,290 @@
+use crate::{AetherError, BuildInfo, BuildInfoFormat, PkgInfo, PkgType};
+use scan_dir::ScanDir;
+use std::fs::{copy, symlink_metadata, File};
+use std::io::Write as _;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// the keywords makepkg records in a package's .MTREE
+const MTREE_OPTIONS: &str = "!all,use-set,type,uid,gid,mode,time,size,md5,sha256,link";
+
+/**
+Turns a staged package root into a `.pkg.tar.zst` archive, like the packaging
+phase of makepkg
+
+The root holds the files as they should be installed (e.g. `usr/bin/foo`).
+`.PKGINFO`, `.BUILDINFO` and `.MTREE` are generated into it, replacing any
+that are already there, and every file is recorded as owned by root.
+
+# Public fields:
+```text
+root: PathBuf
+pkginfo: PkgInfo
+buildinfo: Option<BuildInfo>
+install: Option<PathBuf>
+```
+
+# Public methods:
+```text
+// return a PkgBuilder for a staged root
+PkgBuilder::new() : pub fn new(root: &dyn AsRef<Path>, pkginfo: PkgInfo) -> PkgBuilder
+
+// write the package archive into a directory and return its path
+PkgBuilder::build() : pub fn build(&self, dest: &dyn AsRef<Path>) -> Result<PathBuf>
+```
+*/
+#[derive(Clone, Debug)]
+pub struct PkgBuilder {
+    pub root: PathBuf,
+    /// `size`, and `builddate` and the `pkgtype` xdata if unset, are filled
+    /// in when building
+    pub pkginfo: PkgInfo,
+    /// generated from `pkginfo` when `None`
+    pub buildinfo: Option<BuildInfo>,
+    /// a scriptlet to include as the package's .INSTALL
+    pub install: Option<PathBuf>,
+}
+
+impl PkgBuilder {
+    /// return a `PkgBuilder` for the package staged in `root`
+    pub fn new(root: &dyn AsRef<Path>, pkginfo: PkgInfo) -> PkgBuilder {
+        PkgBuilder {
+            root: root.as_ref().into(),
+            pkginfo,
+            buildinfo: None,
+            install: None,
+        }
+    }
+
+    /// return the archive's file name, `name-version-arch.pkg.tar.zst`
+    #[must_use]
+    pub fn filename(&self) -> String {
+        let arch = self.pkginfo.arch.first().map_or("any", String::as_str);
+
+        format!(
+            "{}-{}-{}.pkg.tar.zst",
+            self.pkginfo.pkgname, self.pkginfo.pkgver, arch
+        )
+    }
+
+    /// write the package archive into `dest` and return its path
+    pub fn build(&self, dest: &dyn AsRef<Path>) -> Result<PathBuf, AetherError> {
+        let root = &self.root;
+
+        for (key, value) in [
+            ("pkgname", &self.pkginfo.pkgname),
+            ("pkgver", &self.pkginfo.pkgver),
+        ] {
+            if value.is_empty() || value.contains(char::is_whitespace) {
+                return Err(AetherError::InvalidValue {
+                    key: key.into(),
+                    value: value.clone(),
+                });
+            }
+        }
+
+        let contents = package_files(root)?;
+
+        let mut pkginfo = self.pkginfo.clone();
+        pkginfo.size = installed_size(root, &contents)?;
+        if pkginfo.builddate == 0 {
+            pkginfo.builddate = build_date();
+        }
+        if pkginfo.xdata_value("pkgtype").is_none() {
+            pkginfo.xdata.push(format!("pkgtype={}", PkgType::Pkg));
+        }
+
+        let buildinfo = match &self.buildinfo {
+            Some(buildinfo) => buildinfo.clone(),
+            None => default_buildinfo(&pkginfo, root),
+        };
+
+        let mut meta = vec![PathBuf::from(".PKGINFO"), PathBuf::from(".BUILDINFO")];
+
+        pkginfo.write_to(&root.join(".PKGINFO"))?;
+        buildinfo.write_to(&root.join(".BUILDINFO"))?;
+
+        if let Some(install) = &self.install {
+            let to = root.join(".INSTALL");
+            copy(install, &to).map_err(|source| AetherError::WriteError { file: to, source })?;
+            meta.push(PathBuf::from(".INSTALL"));
+        }
+
+        meta.extend(contents);
+        self.write_mtree(&meta)?;
+
+        let dest = dest.as_ref();
+        let archive = dest.join(self.filename());
+
+        let mut files = vec![PathBuf::from(".MTREE")];
+        files.extend(meta);
+
+        pipe_tar(
+            root,
+            &[],
+            &files,
+            &["zstd", "-c", "-q", "-T0", "-19"],
+            &archive,
+        )?;
+
+        Ok(archive)
+    }
+
+    /// generate the gzipped .MTREE for `files` in the package root
+    fn write_mtree(&self, files: &[PathBuf]) -> Result<(), AetherError> {
+        let options = format!("--options={}", MTREE_OPTIONS);
+
+        pipe_tar(
+            &self.root,
+            &["--format=mtree", &options],
+            files,
+            &["gzip", "-c", "-f", "-n"],
+            &self.root.join(".MTREE"),
+        )
+    }
+}
+
+/**
+run bsdtar over `files` in `root`, recording them as owned by root, and pipe
+the result through `filter` into `out`, which is removed on failure
+
+Files are passed on stdin rather than as arguments and aren't recursed into,
+so large packages don't run into the argument length limit.
+*/
+fn pipe_tar(
+    root: &Path,
+    args: &[&str],
+    files: &[PathBuf],
+    filter: &[&str],
+    out: &Path,
+) -> Result<(), AetherError> {
+    let out_file = File::create(out).map_err(|source| AetherError::WriteError {
+        file: out.into(),
+        source,
+    })?;
+
+    let mut tar = Command::new("bsdtar")
+        .env("LANG", "C")
+        .args(["--no-fflags", "-cnf", "-", "--null", "--files-from", "-"])
+        .args([
+            "--uid", "0", "--gid", "0", "--uname", "root", "--gname", "root",
+        ])
+        .args(args)
+        .arg("-C")
+        .arg(root)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(AetherError::ProcessError)?;
+
+    let mut filter = Command::new(filter[0])
+        .args(&filter[1..])
+        .stdin(tar.stdout.take().unwrap())
+        .stdout(out_file)
+        .spawn()
+        .map_err(AetherError::ProcessError)?;
+
+    let mut list = vec![];
+    for file in files {
+        list.extend_from_slice(file.as_os_str().as_bytes());
+        list.push(0);
+    }
+
+    let mut stdin = tar.stdin.take().unwrap();
+    stdin.write_all(&list).map_err(AetherError::ProcessError)?;
+    drop(stdin);
+
+    let tar = tar.wait().map_err(AetherError::ProcessError)?;
+    let filtered = filter.wait().map_err(AetherError::ProcessError)?;
+
+    if !tar.success() || !filtered.success() {
+        let _ = std::fs::remove_file(out);
+
+        return Err(AetherError::BuildError {
+            path: root.into(),
+            note: format!("unable to create {}", out.display()),
+        });
+    }
+
+    Ok(())
+}
+
+/// every path in `root` except the package metadata, relative to it and
+/// sorted bytewise like makepkg does
+fn package_files(root: &Path) -> Result<Vec<PathBuf>, AetherError> {
+    let mut files = vec![];
+    ScanDir::all()
+        .walk(root, |iter| {
+            for (entry, _) in iter {
+                files.push(entry.path());
+            }
+        })
+        .map_err(|_| AetherError::BuildError {
+            path: root.into(),
+            note: "unable to walk package root".into(),
+        })?;
+
+    let mut files: Vec<PathBuf> = files
+        .iter()
+        .filter_map(|file| file.strip_prefix(root).ok())
+        .filter(|rel| !rel.to_string_lossy().starts_with('.'))
+        .map(Path::to_path_buf)
+        .collect();
+    files.sort_by(|a, b| a.as_os_str().as_bytes().cmp(b.as_os_str().as_bytes()));
+
+    Ok(files)
+}
+
+/// the build date: `SOURCE_DATE_EPOCH` if set, otherwise now
+fn build_date() -> i64 {
+    if let Some(epoch) = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse().ok())
+    {
+        return epoch;
+    }
+
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// the total apparent size of `files` in `root`, in bytes
+fn installed_size(root: &Path, files: &[PathBuf]) -> Result<u64, AetherError> {
+    let mut size: u64 = 0;
+    for file in files {
+        let file = root.join(file);
+        let meta = symlink_metadata(&file).map_err(|source| AetherError::ReadError {
+            file: file.clone(),
+            source,
+        })?;
+        size += meta.len();
+    }
+
+    Ok(size)
+}
+
+fn default_buildinfo(pkginfo: &PkgInfo, root: &Path) -> BuildInfo {
+    let mut buildinfo = BuildInfo::new();
+
+    buildinfo.format = BuildInfoFormat::V2;
+    buildinfo.pkgname = pkginfo.pkgname.clone();
+    buildinfo.pkgbase = if pkginfo.pkgbase.is_empty() {
+        pkginfo.pkgname.clone()
+    } else {
+        pkginfo.pkgbase.clone()
+    };
+    buildinfo.pkgver = pkginfo.pkgver.clone();
+    buildinfo.pkgarch = pkginfo.arch.clone();
+    buildinfo.packager = pkginfo.packager.clone();
+    buildinfo.builddate = pkginfo.builddate;
+    buildinfo.builddir = root.display().to_string();
+    buildinfo.startdir = root.display().to_string();
+    buildinfo.buildtool = env!("CARGO_PKG_NAME").into();
+    buildinfo.buildtoolver = env!("CARGO_PKG_VERSION").into();
+
+    buildinfo
+}
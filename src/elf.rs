@@ -0,0 +1,254 @@
+use crate::{AetherError, Pkg, PkgList};
+use goblin::elf::Elf;
+use scan_dir::ScanDir;
+use std::fs::{read, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// the directories the dynamic loader searches by default, relative to /
+/// (and to a package root for libraries installed alongside it)
+const LIB_DIRS: [&str; 6] = [
+    "usr/lib",
+    "usr/lib64",
+    "usr/lib32",
+    "lib",
+    "lib64",
+    "usr/local/lib",
+];
+
+/**
+The dynamic linking information of an ELF file
+
+# Public fields:
+```text
+path: PathBuf
+bits: u8
+soname: Option<String>
+needed: Vec<String>
+rpath: Vec<String>
+runpath: Vec<String>
+interpreter: Option<String>
+```
+
+# Public methods:
+```text
+// parse an ELF file, or return None for any other file
+ElfInfo::parse() : pub fn parse(file: &dyn AsRef<Path>) -> Result<Option<ElfInfo>>
+
+// return the directories the loader searches first for this file
+ElfInfo::search_path() : pub fn search_path(&self) -> Vec<PathBuf>
+```
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ElfInfo {
+    pub path: PathBuf,
+    /// 32 or 64
+    pub bits: u8,
+    /// the `DT_SONAME` of a shared library
+    pub soname: Option<String>,
+    /// the `DT_NEEDED` libraries, in order
+    pub needed: Vec<String>,
+    /// the `DT_RPATH` entries, which the loader ignores if there's a runpath
+    pub rpath: Vec<String>,
+    /// the `DT_RUNPATH` entries
+    pub runpath: Vec<String>,
+    pub interpreter: Option<String>,
+}
+
+/// return the ELF class of `file` as 32 or 64, or `None` if it isn't ELF
+fn elf_bits(file: &Path) -> Option<u8> {
+    let mut ident = [0; 5];
+    File::open(file).ok()?.read_exact(&mut ident).ok()?;
+
+    match ident {
+        [0x7f, b'E', b'L', b'F', 1] => Some(32),
+        [0x7f, b'E', b'L', b'F', 2] => Some(64),
+        _ => None,
+    }
+}
+
+impl ElfInfo {
+    /// parse an ELF file, or return `None` for any other file
+    pub fn parse(file: &dyn AsRef<Path>) -> Result<Option<ElfInfo>, AetherError> {
+        let file = file.as_ref();
+
+        if elf_bits(file).is_none() {
+            return Ok(None);
+        }
+
+        let raw = read(file).map_err(|source| AetherError::ReadError {
+            file: file.into(),
+            source,
+        })?;
+        let elf = Elf::parse(&raw).map_err(|_| AetherError::InvalidValue {
+            key: "ELF file".into(),
+            value: file.display().to_string(),
+        })?;
+
+        let split = |paths: &[&str]| {
+            paths
+                .iter()
+                .flat_map(|path| path.split(':'))
+                .filter(|path| !path.is_empty())
+                .map(String::from)
+                .collect()
+        };
+
+        Ok(Some(ElfInfo {
+            path: file.into(),
+            bits: if elf.is_64 { 64 } else { 32 },
+            soname: elf.soname.map(String::from),
+            needed: elf.libraries.iter().map(|lib| lib.to_string()).collect(),
+            rpath: split(&elf.rpaths),
+            runpath: split(&elf.runpaths),
+            interpreter: elf.interpreter.map(String::from),
+        }))
+    }
+
+    /// return the directories the loader searches before the default ones:
+    /// the runpath, or the rpath if there's no runpath, with `$ORIGIN`
+    /// expanded
+    #[must_use]
+    pub fn search_path(&self) -> Vec<PathBuf> {
+        let origin = self.path.parent().unwrap_or(Path::new("/"));
+        let origin = origin.to_string_lossy();
+
+        let paths = if self.runpath.is_empty() {
+            &self.rpath
+        } else {
+            &self.runpath
+        };
+
+        paths
+            .iter()
+            .map(|path| {
+                path.replace("${ORIGIN}", &origin)
+                    .replace("$ORIGIN", &origin)
+                    .into()
+            })
+            .collect()
+    }
+}
+
+/// a `DT_NEEDED` entry of a file in a package, and where it was found
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LinkedLib {
+    /// the file needing the library, relative to the package root
+    pub file: PathBuf,
+    pub needed: String,
+    pub resolved: Option<PathBuf>,
+}
+
+impl Pkg {
+    /// parse every ELF file in the package
+    pub fn elf_files(&self) -> Result<Vec<ElfInfo>, AetherError> {
+        let mut files = vec![];
+        ScanDir::files()
+            .walk(&self.path, |iter| {
+                for (entry, _) in iter {
+                    files.push(entry.path());
+                }
+            })
+            .map_err(|_| AetherError::InvalidPkg {
+                path: self.path.clone(),
+                note: "unable to walk package directory".into(),
+            })?;
+        files.sort();
+
+        let mut elfs = vec![];
+        for file in files {
+            let rel = file.strip_prefix(&self.path).unwrap();
+            if rel.to_string_lossy().starts_with('.') || file.is_symlink() {
+                continue;
+            }
+
+            elfs.extend(ElfInfo::parse(&file)?);
+        }
+
+        Ok(elfs)
+    }
+}
+
+impl PkgList {
+    /**
+    return the directories libraries are looked up in for `pkg`, after an
+    ELF file's own search path
+
+    These are the package's own library directories, those of every other
+    installed package, and then the system's.
+    */
+    #[must_use]
+    pub fn lib_dirs(&self, pkg: &Pkg) -> Vec<PathBuf> {
+        let roots = std::iter::once(pkg.path.as_path())
+            .chain(
+                self.pkgs()
+                    .iter()
+                    .filter(|other| other.get_refstr() != pkg.get_refstr())
+                    .map(|other| other.path.as_path()),
+            )
+            .chain([Path::new("/")]);
+
+        let mut dirs = vec![];
+        for root in roots {
+            for dir in LIB_DIRS {
+                dirs.push(root.join(dir));
+            }
+        }
+
+        // Debian and its derivatives keep libraries in multiarch directories
+        let multiarch = format!("{}-linux-gnu", std::env::consts::ARCH);
+        for dir in ["usr/lib", "lib"] {
+            dirs.push(Path::new("/").join(dir).join(&multiarch));
+        }
+
+        dirs
+    }
+
+    /// return every library the ELF files of `pkg` need, and where each one
+    /// is found
+    pub fn linked_libs(&self, pkg: &Pkg) -> Result<Vec<LinkedLib>, AetherError> {
+        let lib_dirs = self.lib_dirs(pkg);
+        let mut linked = vec![];
+
+        for elf in pkg.elf_files()? {
+            let dirs: Vec<PathBuf> = elf
+                .search_path()
+                .into_iter()
+                .chain(lib_dirs.clone())
+                .collect();
+            let file = elf.path.strip_prefix(&pkg.path).unwrap_or(&elf.path);
+
+            for needed in &elf.needed {
+                // a needed entry with a slash is a path rather than a name
+                let candidates: Vec<PathBuf> = if needed.contains('/') {
+                    vec![needed.into()]
+                } else {
+                    dirs.iter().map(|dir| dir.join(needed)).collect()
+                };
+
+                let resolved = candidates
+                    .into_iter()
+                    .find(|candidate| elf_bits(candidate) == Some(elf.bits));
+
+                linked.push(LinkedLib {
+                    file: file.into(),
+                    needed: needed.clone(),
+                    resolved,
+                });
+            }
+        }
+
+        Ok(linked)
+    }
+
+    /// return the libraries the ELF files of `pkg` need that can't be found
+    /// in the environment or on the system
+    pub fn missing_libs(&self, pkg: &Pkg) -> Result<Vec<LinkedLib>, AetherError> {
+        let mut linked = self.linked_libs(pkg)?;
+        linked.retain(|lib| lib.resolved.is_none());
+
+        Ok(linked)
+    }
+}
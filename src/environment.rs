@@ -0,0 +1,461 @@
+use crate::{
+    bin_dir, config_dir, db_dir, env_dir, pkg_dir, sandbox, sha256sum, usr_dir, walk_dir,
+    AetherError, ExtractDir, Lockfile, Pkg, PkgList,
+};
+use std::collections::BTreeMap;
+use std::fs::{create_dir_all, read_dir, read_link, remove_dir, remove_dir_all, remove_file};
+use std::os::unix::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus};
+
+/**
+An isolated set of installed packages, with its own package, bin, database and
+config directories
+
+The global environment uses the user-wide directories returned by [`pkg_dir`],
+[`bin_dir`], [`db_dir`] and [`config_dir`]; named environments live under
+[`env_dir`] and are completely separate from it and from each other.
+
+# Public methods:
+```text
+// return the global environment
+Environment::global() : pub fn global() -> Environment
+
+// create a new named environment on disk and return it
+Environment::create() : pub fn create(name: &str) -> Result<Environment>
+
+// open an existing named environment
+Environment::open() : pub fn open(name: &str) -> Result<Environment>
+
+// delete a named environment and everything installed in it
+Environment::destroy() : pub fn destroy(self) -> Result<()>
+
+// list the names of all named environments
+Environment::list() : pub fn list() -> Result<Vec<String>>
+
+// read the packages installed in this environment
+Environment::pkglist() : pub fn pkglist(&self) -> Result<PkgList>
+
+// run a command with this environment's usr tree mounted over /usr
+Environment::run() : pub fn run(&self, cmd: &mut Command) -> Result<ExitStatus>
+
+// install and remove packages until the environment matches a lockfile
+Environment::sync_to_lock() : pub fn sync_to_lock(&self, lock: &Lockfile) -> Result<SyncReport>
+```
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Environment {
+    name: String,
+    root: Option<PathBuf>,
+    pkg_dir: PathBuf,
+    bin_dir: PathBuf,
+    db_dir: PathBuf,
+    config_dir: PathBuf,
+    usr_dir: PathBuf,
+}
+
+impl Environment {
+    /// name of the global environment, which can't be used for named ones
+    pub const GLOBAL: &'static str = "global";
+
+    /// return the global environment
+    #[must_use]
+    pub fn global() -> Environment {
+        Environment {
+            name: Self::GLOBAL.into(),
+            root: None,
+            pkg_dir: pkg_dir(),
+            bin_dir: bin_dir(),
+            db_dir: db_dir(),
+            config_dir: config_dir(),
+            usr_dir: usr_dir(),
+        }
+    }
+
+    /// return the environment rooted at `root` without touching the filesystem
+    fn at(name: &str, root: &dyn AsRef<Path>) -> Environment {
+        let root = root.as_ref();
+
+        Environment {
+            name: name.into(),
+            root: Some(root.into()),
+            pkg_dir: root.join("pkg"),
+            bin_dir: root.join("bin"),
+            db_dir: root.join("db"),
+            config_dir: root.join("config"),
+            usr_dir: root.join("usr"),
+        }
+    }
+
+    fn check_name(name: &str) -> Result<(), AetherError> {
+        let valid = !name.is_empty()
+            && name != Self::GLOBAL
+            && !name.starts_with('.')
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+
+        if valid {
+            Ok(())
+        } else {
+            Err(AetherError::InvalidValue {
+                key: "environment name".into(),
+                value: name.into(),
+            })
+        }
+    }
+
+    /// create a new named environment on disk and return it
+    pub fn create(name: &str) -> Result<Environment, AetherError> {
+        Self::create_in(name, &env_dir())
+    }
+
+    /// create a new named environment under `path` and return it
+    pub fn create_in(name: &str, path: &dyn AsRef<Path>) -> Result<Environment, AetherError> {
+        Self::check_name(name)?;
+
+        let root = path.as_ref().join(name);
+        if root.exists() {
+            return Err(AetherError::AlreadyExists(format!(
+                "environment {} already exists",
+                name
+            )));
+        }
+
+        let env = Self::at(name, &root);
+        env.ensure_dirs()?;
+
+        Ok(env)
+    }
+
+    /// open an existing named environment, or the global one for "global"
+    pub fn open(name: &str) -> Result<Environment, AetherError> {
+        if name == Self::GLOBAL {
+            return Ok(Self::global());
+        }
+
+        Self::open_in(name, &env_dir())
+    }
+
+    /// open an existing named environment under `path`
+    pub fn open_in(name: &str, path: &dyn AsRef<Path>) -> Result<Environment, AetherError> {
+        Self::check_name(name)?;
+
+        let root = path.as_ref().join(name);
+        if !root.is_dir() {
+            return Err(AetherError::MissingEnv(name.into()));
+        }
+
+        Ok(Self::at(name, &root))
+    }
+
+    /// delete a named environment and everything installed in it
+    pub fn destroy(self) -> Result<(), AetherError> {
+        let root = match self.root {
+            Some(root) => root,
+            None => {
+                return Err(AetherError::InvalidValue {
+                    key: "environment".into(),
+                    value: format!("{} can't be destroyed", self.name),
+                })
+            }
+        };
+
+        remove_dir_all(&root).map_err(|source| AetherError::WriteError { file: root, source })
+    }
+
+    /// list the names of all named environments
+    pub fn list() -> Result<Vec<String>, AetherError> {
+        Self::list_in(&env_dir())
+    }
+
+    /// list the names of all named environments under `path`
+    pub fn list_in(path: &dyn AsRef<Path>) -> Result<Vec<String>, AetherError> {
+        let path = path.as_ref();
+
+        let entries = match read_dir(path) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(source) => {
+                return Err(AetherError::ReadError {
+                    file: path.into(),
+                    source,
+                })
+            }
+        };
+
+        let mut names = vec![];
+        for entry in entries {
+            let entry = entry.map_err(|source| AetherError::ReadError {
+                file: path.into(),
+                source,
+            })?;
+
+            if entry.path().is_dir() {
+                if let Some(name) = entry.file_name().to_str() {
+                    names.push(name.to_string());
+                }
+            }
+        }
+
+        names.sort();
+        Ok(names)
+    }
+
+    /// read the packages installed in this environment
+    pub fn pkglist(&self) -> Result<PkgList, AetherError> {
+        PkgList::new_in(self)
+    }
+
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// the directory containing the environment, or `None` for the global one
+    #[must_use]
+    pub fn root(&self) -> Option<&Path> {
+        self.root.as_deref()
+    }
+
+    #[must_use]
+    pub fn is_global(&self) -> bool {
+        self.root.is_none()
+    }
+
+    #[must_use]
+    pub fn pkg_dir(&self) -> &Path {
+        &self.pkg_dir
+    }
+
+    #[must_use]
+    pub fn bin_dir(&self) -> &Path {
+        &self.bin_dir
+    }
+
+    #[must_use]
+    pub fn db_dir(&self) -> &Path {
+        &self.db_dir
+    }
+
+    #[must_use]
+    pub fn config_dir(&self) -> &Path {
+        &self.config_dir
+    }
+
+    /// the merged tree of every installed package's usr directory, which
+    /// [`Environment::run`] mounts over /usr
+    #[must_use]
+    pub fn usr_dir(&self) -> &Path {
+        &self.usr_dir
+    }
+
+    fn dirs(&self) -> [&Path; 5] {
+        [
+            &self.pkg_dir,
+            &self.bin_dir,
+            &self.db_dir,
+            &self.config_dir,
+            &self.usr_dir,
+        ]
+    }
+
+    /// create any of this environment's directories that don't exist yet
+    pub fn ensure_dirs(&self) -> Result<(), AetherError> {
+        for dir in self.dirs() {
+            create_dir_all(dir).map_err(|source| AetherError::WriteError {
+                file: dir.into(),
+                source,
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /**
+    symlink every file under each installed package's usr directory into
+    [`Environment::usr_dir`], returning the links that were created
+
+    The tree is rebuilt from the installed packages each time: links left
+    over from packages that were since upgraded or removed are deleted, along
+    with the directories they leave empty.
+    */
+    pub fn link_usr(&self) -> Result<Vec<PathBuf>, AetherError> {
+        self.ensure_dirs()?;
+
+        // path relative to usr -> the file its link points to
+        let mut wanted: BTreeMap<PathBuf, PathBuf> = BTreeMap::new();
+        for pkg in self.pkglist()? {
+            let pkg_usr = pkg.path.join("usr");
+            if !pkg_usr.is_dir() {
+                continue;
+            }
+
+            for file in walk_dir(&pkg_usr)? {
+                if file.is_dir() && !file.is_symlink() {
+                    continue;
+                }
+
+                let rel = file.strip_prefix(&pkg_usr).unwrap().to_path_buf();
+                if let Some(other) = wanted.get(&rel) {
+                    return Err(AetherError::AlreadyExists(format!(
+                        "{} is provided by both {} and {}",
+                        rel.display(),
+                        other.display(),
+                        file.display()
+                    )));
+                }
+
+                wanted.insert(rel, file);
+            }
+        }
+
+        // going backwards empties each directory before it's looked at
+        for entry in walk_dir(&self.usr_dir)?.into_iter().rev() {
+            let rel = entry.strip_prefix(&self.usr_dir).unwrap();
+            let write_error = |source| AetherError::WriteError {
+                file: entry.clone(),
+                source,
+            };
+
+            if entry.is_symlink() {
+                if read_link(&entry).ok().as_ref() != wanted.get(rel) {
+                    remove_file(&entry).map_err(write_error)?;
+                }
+            } else if entry.is_dir() {
+                let mut children = read_dir(&entry).map_err(|source| AetherError::ReadError {
+                    file: entry.clone(),
+                    source,
+                })?;
+                if children.next().is_none() {
+                    remove_dir(&entry).map_err(write_error)?;
+                }
+            }
+        }
+
+        let mut linked = vec![];
+        for (rel, file) in &wanted {
+            let link = self.usr_dir.join(rel);
+            if read_link(&link).ok().as_ref() == Some(file) {
+                continue;
+            }
+
+            let parent = link.parent().unwrap();
+            create_dir_all(parent).map_err(|source| AetherError::WriteError {
+                file: parent.into(),
+                source,
+            })?;
+
+            fs::symlink(file, &link).map_err(|source| AetherError::LinkError {
+                from: file.clone(),
+                to: link.clone(),
+                source,
+            })?;
+
+            linked.push(link);
+        }
+
+        Ok(linked)
+    }
+
+    /**
+    run `cmd` with this environment's usr tree mounted over /usr
+
+    This uses unprivileged user and mount namespaces, so neither root nor
+    proot is needed; the command runs as the calling user and sees the rest
+    of the filesystem unchanged. The host's /usr is hidden, so the environment
+    has to provide everything the command links against. Returns
+    [`AetherError::SandboxError`] when namespaces are unavailable on this
+    system.
+    */
+    pub fn run(&self, cmd: &mut Command) -> Result<ExitStatus, AetherError> {
+        self.link_usr()?;
+
+        sandbox::run_with_usr(&self.usr_dir, cmd)
+    }
+}
+
+/// the packages changed by [`Environment::sync_to_lock`], as `name-version`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SyncReport {
+    pub installed: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl Environment {
+    /**
+    install and remove packages until the environment contains exactly the
+    packages pinned in `lock`
+
+    Archives are taken from the lockfile's repositories and must match the
+    locked sha256 sums, so the result is the same on every machine.
+    */
+    pub fn sync_to_lock(&self, lock: &Lockfile) -> Result<SyncReport, AetherError> {
+        self.ensure_dirs()?;
+
+        let mut pkglist = self.pkglist()?;
+        pkglist.set_architectures(lock.architectures());
+        let mut report = SyncReport::default();
+
+        let stale: Vec<Pkg> = pkglist
+            .pkgs()
+            .iter()
+            .filter(|pkg| {
+                !lock
+                    .packages
+                    .iter()
+                    .any(|locked| locked.get_refstr() == pkg.get_refstr())
+            })
+            .cloned()
+            .collect();
+
+        for pkg in stale {
+            pkglist.remove(&pkg)?;
+            report.removed.push(pkg.get_refstr());
+        }
+
+        for locked in &lock.packages {
+            if pkglist
+                .pkgs()
+                .iter()
+                .any(|pkg| pkg.get_refstr() == locked.get_refstr())
+            {
+                continue;
+            }
+
+            let source = lock
+                .repos
+                .iter()
+                .find(|repo| repo.name == locked.repo)
+                .ok_or_else(|| AetherError::InvalidValue {
+                    key: "lockfile repository".into(),
+                    value: locked.repo.clone(),
+                })?;
+
+            let archive = source.path.join(&locked.filename);
+            let sum = sha256sum(&archive)?;
+            if sum != locked.sha256 {
+                return Err(AetherError::ChecksumError {
+                    file: archive,
+                    expected: locked.sha256.clone(),
+                    found: sum,
+                });
+            }
+
+            // deleted again when dropped, whether or not installing worked
+            let extracted = ExtractDir::new(&locked.get_refstr())?;
+            pkglist.install(Pkg::from_archive(&archive, &extracted.path())?)?;
+
+            report.installed.push(locked.get_refstr());
+        }
+
+        Ok(report)
+    }
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self::global()
+    }
+}
@@ -0,0 +1,503 @@
+use crate::{AetherError, Environment, Pkg, PkgInfo, PkgList};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{
+    read_dir, read_link, read_to_string, remove_file, rename, symlink_metadata, write, OpenOptions,
+};
+use std::io::Write;
+use std::os::unix::fs::{self, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+
+/// file in an environment's database directory recording the entries
+/// aether created in its bin directory
+const EXPORTS_FILE: &str = "exports.toml";
+
+/// the comment on the second line of a wrapper script, followed by the
+/// executable it runs
+const WRAPPER_MARKER: &str = "# aether wrapper for ";
+
+/// how a package's executables are exported into the bin directory
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum ExportMode {
+    /// a symlink to the executable
+    #[default]
+    Symlink,
+    /// a shell script pointing the library and data search paths at the
+    /// package before running the executable
+    Wrapper,
+}
+
+/**
+How to export each package's executables
+
+Symlinked executables run with the host's search paths, so those that load
+libraries or data files from their own package only work once relocated.
+Wrapper scripts instead prepend the package's directories to `PATH`,
+`LD_LIBRARY_PATH`, `XDG_DATA_DIRS` and `XDG_CONFIG_DIRS`, then `exec` the
+real executable. Executables are symlinked by default.
+
+# Public methods:
+```text
+// export every package's executables the same way
+Exports::new() : pub fn new(mode: ExportMode) -> Exports
+
+// export the package `name` differently
+Exports::set() : pub fn set(&mut self, name: &str, mode: ExportMode)
+
+// return how a package's executables are exported
+Exports::mode_for() : pub fn mode_for(&self, info: &PkgInfo) -> ExportMode
+```
+*/
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Exports {
+    mode: ExportMode,
+    overrides: BTreeMap<String, ExportMode>,
+}
+
+impl Exports {
+    /// export every package's executables with `mode`
+    #[must_use]
+    pub fn new(mode: ExportMode) -> Exports {
+        Exports {
+            mode,
+            overrides: BTreeMap::new(),
+        }
+    }
+
+    /// export the executables of the package `name` with `mode`
+    pub fn set(&mut self, name: &str, mode: ExportMode) {
+        self.overrides.insert(name.into(), mode);
+    }
+
+    /// return how the executables of the package described by `info` are
+    /// exported
+    #[must_use]
+    pub fn mode_for(&self, info: &PkgInfo) -> ExportMode {
+        self.overrides
+            .get(&info.pkgname)
+            .copied()
+            .unwrap_or(self.mode)
+    }
+}
+
+/// quote `path` for a POSIX shell
+fn quote(path: &Path) -> String {
+    format!("'{}'", path.to_string_lossy().replace('\'', r"'\''"))
+}
+
+/// return the root of the package `exec` belongs to, given that it's in the
+/// package's usr/bin or bin
+fn pkg_root(exec: &Path) -> &Path {
+    let dir = exec.parent().unwrap_or(exec);
+    let root = dir.parent().unwrap_or(dir);
+
+    match root.file_name() {
+        Some(name) if name == "usr" => root.parent().unwrap_or(root),
+        _ => root,
+    }
+}
+
+/// return a script running `exec` with its package's directories in front
+/// of the search paths
+fn wrapper_script(exec: &Path) -> String {
+    let root = pkg_root(exec);
+    let existing = |dirs: &[&str]| -> Vec<String> {
+        dirs.iter()
+            .map(|dir| root.join(dir))
+            .filter(|dir| dir.is_dir())
+            .map(|dir| quote(&dir))
+            .collect()
+    };
+
+    let mut script = format!("#!/bin/sh\n{}{}\n", WRAPPER_MARKER, exec.display());
+
+    // variables without a default are only extended when already set
+    let vars = [
+        ("PATH", existing(&["usr/bin", "bin"]), None),
+        (
+            "LD_LIBRARY_PATH",
+            existing(&["usr/lib", "usr/lib64", "lib", "lib64"]),
+            None,
+        ),
+        (
+            "XDG_DATA_DIRS",
+            existing(&["usr/share"]),
+            Some("/usr/local/share:/usr/share"),
+        ),
+        ("XDG_CONFIG_DIRS", existing(&["etc/xdg"]), Some("/etc/xdg")),
+    ];
+    for (var, dirs, default) in vars {
+        if dirs.is_empty() {
+            continue;
+        }
+
+        let rest = match default {
+            Some(default) => format!(":${{{}:-{}}}", var, default),
+            None => format!("${{{0}:+:${0}}}", var),
+        };
+        script += &format!("export {}={}\"{}\"\n", var, dirs.join(":"), rest);
+    }
+
+    script += &format!("exec {} \"$@\"\n", quote(exec));
+    script
+}
+
+/// create an executable wrapper script at `path` running `exec`
+pub(crate) fn write_wrapper(exec: &Path, path: &Path) -> Result<(), AetherError> {
+    let write_error = |source| AetherError::WriteError {
+        file: path.into(),
+        source,
+    };
+
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o755)
+        .open(path)
+        .map_err(write_error)?;
+    file.write_all(wrapper_script(exec).as_bytes())
+        .map_err(write_error)
+}
+
+/// return the executable the wrapper script at `path` runs, or `None` if it
+/// isn't a wrapper script
+#[must_use]
+pub fn read_wrapper(path: &dyn AsRef<Path>) -> Option<PathBuf> {
+    let path = path.as_ref();
+    if path.is_symlink() {
+        return None;
+    }
+
+    let script = read_to_string(path).ok()?;
+    let marker = script.lines().nth(1)?;
+
+    marker.strip_prefix(WRAPPER_MARKER).map(PathBuf::from)
+}
+
+/// return the executable the bin directory entry `path` exports, whether
+/// it's a symlink or a wrapper script
+pub(crate) fn exported_exec(path: &Path) -> Option<PathBuf> {
+    read_link(path).ok().or_else(|| read_wrapper(&path))
+}
+
+/// export `exec` to `path` with `mode`, refusing to replace an existing file
+pub(crate) fn export_exec(exec: &Path, path: &Path, mode: ExportMode) -> Result<(), AetherError> {
+    if symlink_metadata(path).is_ok() {
+        return Err(AetherError::ForeignFile(path.into()));
+    }
+
+    match mode {
+        ExportMode::Symlink => fs::symlink(exec, path).map_err(|source| AetherError::LinkError {
+            from: exec.into(),
+            to: path.into(),
+            source,
+        }),
+        ExportMode::Wrapper => write_wrapper(exec, path),
+    }
+}
+
+/// the contents of [`EXPORTS_FILE`]
+#[derive(Default, Serialize, Deserialize)]
+struct Exported {
+    /// entry name in the bin directory -> the executable it exports
+    #[serde(default)]
+    links: BTreeMap<String, PathBuf>,
+}
+
+/**
+What's wrong with the entries in an environment's bin directory
+
+# Public fields:
+```text
+dangling: Vec<PathBuf>
+missing: Vec<PathBuf>
+foreign: Vec<PathBuf>
+```
+*/
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExportCheck {
+    /// entries aether created for executables no installed package has
+    pub dangling: Vec<PathBuf>,
+    /// entries for installed executables that don't exist
+    pub missing: Vec<PathBuf>,
+    /// files aether didn't create where an installed executable should be
+    pub foreign: Vec<PathBuf>,
+}
+
+impl ExportCheck {
+    /// return whether every installed executable is exported and nothing
+    /// else aether created is left over
+    #[must_use]
+    pub fn is_ok(&self) -> bool {
+        self.dangling.is_empty() && self.missing.is_empty() && self.foreign.is_empty()
+    }
+}
+
+impl Environment {
+    fn exports_file(&self) -> PathBuf {
+        self.db_dir().join(EXPORTS_FILE)
+    }
+
+    fn read_exported(&self) -> Result<Exported, AetherError> {
+        let file = self.exports_file();
+
+        match read_to_string(&file) {
+            Ok(raw) => {
+                toml::from_str(&raw).map_err(|source| AetherError::TomlError { file, source })
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Exported::default()),
+            Err(source) => Err(AetherError::ReadError { file, source }),
+        }
+    }
+
+    fn write_exported(&self, exported: &Exported) -> Result<(), AetherError> {
+        let file = self.exports_file();
+        // a table of strings always serializes
+        let raw = toml::to_string(exported).unwrap();
+        write(&file, raw).map_err(|source| AetherError::WriteError { file, source })
+    }
+
+    /// return the entries aether created in the bin directory, by name, and
+    /// the executables they export
+    pub fn exported_links(&self) -> Result<BTreeMap<String, PathBuf>, AetherError> {
+        Ok(self.read_exported()?.links)
+    }
+
+    /**
+    return whether aether created the entry `name` in the bin directory
+
+    Entries are recorded as they're created; ones created before that count
+    as well if they export a file in the package directory and the active
+    generation lists them. An entry that was replaced after aether created it
+    doesn't.
+    */
+    pub fn owns_export(&self, name: &str) -> Result<bool, AetherError> {
+        let exec = match exported_exec(&self.bin_dir().join(name)) {
+            Some(exec) => exec,
+            None => return Ok(false),
+        };
+
+        if self.read_exported()?.links.get(name) == Some(&exec) {
+            return Ok(true);
+        }
+        if !exec.starts_with(self.pkg_dir()) {
+            return Ok(false);
+        }
+
+        let current = match self.current_generation()? {
+            Some(current) => current,
+            None => return Ok(false),
+        };
+        Ok(self
+            .list_generations()?
+            .iter()
+            .find(|generation| generation.number == current)
+            .is_some_and(|generation| generation.links.get(name) == Some(&exec)))
+    }
+
+    /**
+    export `exec` as the entry `name` in the bin directory, replacing an
+    entry aether created but no other file, and record it
+
+    The new entry is created next to the old one and renamed over it, so a
+    failed export leaves the old entry in place.
+    */
+    pub(crate) fn export_to_bin(
+        &self,
+        name: &str,
+        exec: &Path,
+        mode: ExportMode,
+    ) -> Result<(), AetherError> {
+        let link = self.bin_dir().join(name);
+        if symlink_metadata(&link).is_ok() && !self.owns_export(name)? {
+            return Err(AetherError::ForeignFile(link));
+        }
+
+        let new = self.bin_dir().join(format!(".{}.aether-new", name));
+        // left over from an export that was interrupted
+        if symlink_metadata(&new).is_ok() {
+            remove_file(&new).map_err(|source| AetherError::WriteError {
+                file: new.clone(),
+                source,
+            })?;
+        }
+        export_exec(exec, &new, mode)?;
+        if let Err(source) = rename(&new, &link) {
+            let _ = remove_file(&new);
+            return Err(AetherError::WriteError { file: link, source });
+        }
+
+        let mut exported = self.read_exported()?;
+        exported.links.insert(name.into(), exec.into());
+        self.write_exported(&exported)
+    }
+
+    /// remove the entry `name` from the bin directory and forget it, or
+    /// return an error if aether didn't create it
+    pub(crate) fn unexport(&self, name: &str) -> Result<(), AetherError> {
+        let link = self.bin_dir().join(name);
+
+        if symlink_metadata(&link).is_ok() {
+            if !self.owns_export(name)? {
+                return Err(AetherError::ForeignFile(link));
+            }
+
+            remove_file(&link).map_err(|source| AetherError::WriteError {
+                file: link.clone(),
+                source,
+            })?;
+        }
+
+        let mut exported = self.read_exported()?;
+        if exported.links.remove(name).is_some() {
+            self.write_exported(&exported)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Pkg {
+    /// create a wrapper script in the bin directory of `env` for each of the
+    /// package's executables
+    pub fn wrap_execs(&self, env: &Environment) -> Result<Vec<PathBuf>, AetherError> {
+        self.wrap_execs_to(&env.bin_dir())
+    }
+
+    /// create a wrapper script in `bin` for each of the package's
+    /// executables, setting the search paths for its directories
+    pub fn wrap_execs_to(&self, bin: &dyn AsRef<Path>) -> Result<Vec<PathBuf>, AetherError> {
+        self.export_execs_to(bin, ExportMode::Wrapper)
+    }
+
+    /// export each of the package's executables into `bin` with `mode`
+    pub fn export_execs_to(
+        &self,
+        bin: &dyn AsRef<Path>,
+        mode: ExportMode,
+    ) -> Result<Vec<PathBuf>, AetherError> {
+        let bin = bin.as_ref();
+        let mut exported = vec![];
+
+        for file in self.list_execs()? {
+            let path = bin.join(file.file_name());
+            export_exec(&file.path(), &path, mode)?;
+            exported.push(path);
+        }
+
+        Ok(exported)
+    }
+}
+
+impl PkgList {
+    #[must_use]
+    pub fn exports(&self) -> &Exports {
+        &self.exports
+    }
+
+    /// set how executables of installed packages are exported
+    pub fn set_exports(&mut self, exports: Exports) {
+        self.exports = exports;
+    }
+
+    /// return an error if a file aether didn't create is where one of the
+    /// executables of `pkg` would be exported
+    pub(crate) fn check_foreign_exports(&self, pkg: &Pkg) -> Result<(), AetherError> {
+        for exec in pkg.list_execs()? {
+            let link = self.env().bin_dir().join(exec.file_name());
+            let name = exec.file_name().to_string_lossy().to_string();
+
+            if symlink_metadata(&link).is_ok() && !self.env().owns_export(&name)? {
+                return Err(AetherError::ForeignFile(link));
+            }
+        }
+
+        Ok(())
+    }
+
+    /**
+    check the bin directory against the installed packages: entries left
+    over for executables that are gone, executables without an entry, and
+    files in the way of executables
+    */
+    pub fn check_exports(&self) -> Result<ExportCheck, AetherError> {
+        let bin = self.env().bin_dir();
+        let mut names = BTreeSet::new();
+        let mut execs = BTreeSet::new();
+        for pkg in self.pkgs() {
+            for exec in pkg.list_execs()? {
+                names.insert(exec.file_name().to_string_lossy().to_string());
+                execs.insert(exec.path());
+            }
+        }
+
+        let mut check = ExportCheck::default();
+
+        let entries = match read_dir(bin) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(check),
+            Err(source) => {
+                return Err(AetherError::ReadError {
+                    file: bin.into(),
+                    source,
+                })
+            }
+        };
+        for entry in entries {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            let exec = exported_exec(&entry.path());
+
+            if exec.is_some_and(|exec| !execs.contains(&exec)) && self.env().owns_export(&name)? {
+                check.dangling.push(entry.path());
+            }
+        }
+        check.dangling.sort();
+
+        for name in names {
+            let link = bin.join(&name);
+            if symlink_metadata(&link).is_err() {
+                check.missing.push(link);
+            } else if !self.env().owns_export(&name)? {
+                check.foreign.push(link);
+            }
+        }
+
+        Ok(check)
+    }
+
+    /**
+    remove dangling entries from the bin directory and export executables
+    that are missing there, returning what was wrong beforehand
+
+    Files aether didn't create are left alone and stay in the returned
+    check's `foreign`.
+    */
+    pub fn repair_exports(&mut self) -> Result<ExportCheck, AetherError> {
+        let check = self.check_exports()?;
+
+        for link in check.dangling.iter().chain(&check.missing) {
+            let name = link.file_name().unwrap().to_string_lossy();
+            if symlink_metadata(link).is_ok() {
+                self.env().unexport(&name)?;
+            }
+            self.export_alternative(&name)?;
+        }
+
+        // forget entries that were deleted behind aether's back
+        let bin = self.env().bin_dir();
+        let mut exported = self.env().read_exported()?;
+        let before = exported.links.len();
+        exported
+            .links
+            .retain(|name, _| symlink_metadata(bin.join(name)).is_ok());
+        if exported.links.len() != before {
+            self.env().write_exported(&exported)?;
+        }
+
+        Ok(check)
+    }
+}
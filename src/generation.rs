@@ -0,0 +1,279 @@
+use crate::export::{exported_exec, read_wrapper};
+use crate::{AetherError, Environment, ExportMode, Pkg};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{
+    create_dir_all, read_dir, read_to_string, remove_dir_all, remove_file, symlink_metadata, write,
+};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/**
+A numbered snapshot of an environment's state: the installed packages and
+the links exported for them into its bin directory
+
+# Public fields:
+```text
+number: u64
+created: u64
+pkgs: Vec<String>
+wrapped: BTreeSet<String>
+links: BTreeMap<String, PathBuf>
+```
+*/
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Generation {
+    pub number: u64,
+    /// seconds since the unix epoch
+    pub created: u64,
+    /// `name-version` of every installed package
+    pub pkgs: Vec<String>,
+    /// the names in `links` exported as wrapper scripts instead of symlinks
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub wrapped: BTreeSet<String>,
+    /// link name in the bin directory -> the file it points to
+    pub links: BTreeMap<String, PathBuf>,
+}
+
+impl Generation {
+    /// snapshot the given installed packages as generation `number`
+    pub fn from_pkgs(number: u64, pkgs: &[Pkg]) -> Result<Generation, AetherError> {
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_secs())
+            .unwrap_or(0);
+
+        let mut links = BTreeMap::new();
+        for pkg in pkgs {
+            for exec in pkg.list_execs()? {
+                if let Some(name) = exec.file_name().to_str() {
+                    links.insert(name.to_string(), exec.path());
+                }
+            }
+        }
+
+        Ok(Generation {
+            number,
+            created,
+            pkgs: pkgs.iter().map(Pkg::get_refstr).collect(),
+            wrapped: BTreeSet::new(),
+            links,
+        })
+    }
+}
+
+impl Environment {
+    fn generations_dir(&self) -> PathBuf {
+        self.db_dir().join("generations")
+    }
+
+    fn current_generation_file(&self) -> PathBuf {
+        self.db_dir().join("generation")
+    }
+
+    /// list every recorded generation, oldest first
+    pub fn list_generations(&self) -> Result<Vec<Generation>, AetherError> {
+        let dir = self.generations_dir();
+
+        let entries = match read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(source) => return Err(AetherError::ReadError { file: dir, source }),
+        };
+
+        let mut generations = vec![];
+        for entry in entries {
+            let file = entry?.path();
+
+            let raw = read_to_string(&file).map_err(|source| AetherError::ReadError {
+                file: file.clone(),
+                source,
+            })?;
+            let generation: Generation =
+                toml::from_str(&raw).map_err(|source| AetherError::TomlError { file, source })?;
+
+            generations.push(generation);
+        }
+
+        generations.sort_by_key(|generation| generation.number);
+        Ok(generations)
+    }
+
+    /// return the number of the active generation, if any has been recorded
+    pub fn current_generation(&self) -> Result<Option<u64>, AetherError> {
+        let file = self.current_generation_file();
+
+        match read_to_string(&file) {
+            Ok(raw) => raw
+                .trim()
+                .parse()
+                .map(Some)
+                .map_err(|_| AetherError::InvalidValue {
+                    key: "current generation".into(),
+                    value: raw.trim().into(),
+                }),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(source) => Err(AetherError::ReadError { file, source }),
+        }
+    }
+
+    /// return the generation numbered `number`
+    pub fn generation(&self, number: u64) -> Result<Generation, AetherError> {
+        self.list_generations()?
+            .into_iter()
+            .find(|generation| generation.number == number)
+            .ok_or(AetherError::MissingGeneration(number))
+    }
+
+    fn set_current_generation(&self, number: u64) -> Result<(), AetherError> {
+        let file = self.current_generation_file();
+
+        write(&file, format!("{}\n", number))
+            .map_err(|source| AetherError::WriteError { file, source })
+    }
+
+    /// record `pkgs` as a new generation and make it the active one
+    pub fn record_generation(&self, pkgs: &[Pkg]) -> Result<Generation, AetherError> {
+        let dir = self.generations_dir();
+        create_dir_all(&dir).map_err(|source| AetherError::WriteError {
+            file: dir.clone(),
+            source,
+        })?;
+
+        let number = self
+            .list_generations()?
+            .last()
+            .map_or(1, |generation| generation.number + 1);
+        let mut generation = Generation::from_pkgs(number, pkgs)?;
+
+        // of several packages providing an executable, record the one exported
+        for pkg in pkgs {
+            for exec in pkg.list_execs()? {
+                let link = self.bin_dir().join(exec.file_name());
+                if exported_exec(&link) == Some(exec.path()) {
+                    let name = exec.file_name().to_string_lossy().to_string();
+                    if read_wrapper(&link).is_some() {
+                        generation.wrapped.insert(name.clone());
+                    }
+                    generation.links.insert(name, exec.path());
+                }
+            }
+        }
+
+        let file = dir.join(number.to_string());
+        // serializing plain strings, numbers and tables can't fail
+        let raw = toml::to_string(&generation).unwrap();
+        write(&file, raw).map_err(|source| AetherError::WriteError { file, source })?;
+
+        self.set_current_generation(number)?;
+
+        Ok(generation)
+    }
+
+    /**
+    switch the environment back (or forward) to generation `number`
+
+    Package directories are versioned, so this only re-points the links (and
+    rewrites the wrapper scripts) in the bin directory; it fails if a package
+    the generation needs has since been deleted, or if a file not exported by
+    aether is in the way.
+    */
+    pub fn rollback_to(&self, number: u64) -> Result<Generation, AetherError> {
+        let target = self.generation(number)?;
+
+        for pkg in &target.pkgs {
+            if !self.pkg_dir().join(pkg).is_dir() {
+                return Err(AetherError::MissingPkg {
+                    name: pkg.clone(),
+                    ver: format!("(needed by generation {})", number),
+                });
+            }
+        }
+
+        // check first, so a foreign file doesn't leave a half-switched bin
+        for name in target.links.keys() {
+            let link = self.bin_dir().join(name);
+            if symlink_metadata(&link).is_ok() && !self.owns_export(name)? {
+                return Err(AetherError::ForeignFile(link));
+            }
+        }
+
+        // entries the target generation has too are replaced in place
+        if let Some(current) = self.current_generation()? {
+            for (name, from) in self.generation(current)?.links {
+                if target.links.contains_key(&name) {
+                    continue;
+                }
+                if exported_exec(&self.bin_dir().join(&name)).as_ref() == Some(&from) {
+                    self.unexport(&name)?;
+                }
+            }
+        }
+
+        for (name, from) in &target.links {
+            let mode = if target.wrapped.contains(name) {
+                ExportMode::Wrapper
+            } else {
+                ExportMode::Symlink
+            };
+            self.export_to_bin(name, from, mode)?;
+        }
+
+        self.set_current_generation(number)?;
+
+        Ok(target)
+    }
+
+    /**
+    delete every generation created before `older_than`, except the active
+    one, then delete package directories no remaining generation refers to
+
+    Returns the numbers of the deleted generations.
+    */
+    pub fn delete_generations(&self, older_than: SystemTime) -> Result<Vec<u64>, AetherError> {
+        let cutoff = older_than
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_secs())
+            .unwrap_or(0);
+        let current = self.current_generation()?;
+
+        let mut deleted = vec![];
+        let mut kept = vec![];
+        for generation in self.list_generations()? {
+            if generation.created < cutoff && Some(generation.number) != current {
+                let file = self.generations_dir().join(generation.number.to_string());
+                remove_file(&file).map_err(|source| AetherError::WriteError { file, source })?;
+
+                deleted.push(generation.number);
+            } else {
+                kept.push(generation);
+            }
+        }
+
+        if deleted.is_empty() {
+            return Ok(deleted);
+        }
+
+        let pkg_dir = self.pkg_dir();
+        let entries = read_dir(pkg_dir).map_err(|source| AetherError::ReadError {
+            file: pkg_dir.into(),
+            source,
+        })?;
+
+        for entry in entries {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+
+            if !kept
+                .iter()
+                .any(|generation| generation.pkgs.contains(&name))
+            {
+                let path = entry.path();
+                remove_dir_all(&path)
+                    .map_err(|source| AetherError::WriteError { file: path, source })?;
+            }
+        }
+
+        Ok(deleted)
+    }
+}
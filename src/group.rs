@@ -0,0 +1,213 @@
+use crate::{vercmp, AetherError, Pkg, PkgList, Repo, RepoPkg};
+use std::collections::BTreeMap;
+
+/**
+Which members of a package group to use, written like pacman's group prompt
+
+The selection is a list of space or comma separated items, each a 1-based
+index, an inclusive range like `2-4`, or a package name. Items starting with
+`^` exclude instead. An empty selection, or one with only exclusions, starts
+from every member.
+
+# Public methods:
+```text
+// select every member
+GroupSelection::all() : pub fn all() -> GroupSelection
+
+// parse a selection like "1-3 ^2" or "^foo"
+GroupSelection::parse() : pub fn parse(s: &str) -> Result<GroupSelection>
+
+// return the selected names out of a group's members
+GroupSelection::select() : pub fn select(&self, members: &[String]) -> Result<Vec<String>>
+```
+*/
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GroupSelection {
+    include: Vec<SelectionItem>,
+    exclude: Vec<SelectionItem>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum SelectionItem {
+    Range(usize, usize),
+    Name(String),
+}
+
+impl SelectionItem {
+    fn parse(s: &str) -> Result<SelectionItem, AetherError> {
+        let invalid = || AetherError::InvalidValue {
+            key: "group selection".into(),
+            value: s.into(),
+        };
+        let index = |s: &str| match s.parse::<usize>() {
+            Ok(0) | Err(_) => Err(invalid()),
+            Ok(index) => Ok(index),
+        };
+
+        let numeric = s.starts_with(|c: char| c.is_ascii_digit())
+            && s.chars().all(|c| c.is_ascii_digit() || c == '-');
+
+        if numeric {
+            match s.split_once('-') {
+                Some((start, end)) => {
+                    let (start, end) = (index(start)?, index(end)?);
+                    if start > end {
+                        return Err(invalid());
+                    }
+
+                    Ok(SelectionItem::Range(start, end))
+                }
+                None => {
+                    let index = index(s)?;
+                    Ok(SelectionItem::Range(index, index))
+                }
+            }
+        } else {
+            Ok(SelectionItem::Name(s.into()))
+        }
+    }
+
+    /// return whether the member at 1-based `index` is selected
+    fn matches(&self, index: usize, name: &str) -> bool {
+        match self {
+            SelectionItem::Range(start, end) => (*start..=*end).contains(&index),
+            SelectionItem::Name(item) => item == name,
+        }
+    }
+
+    /// return an error if the item doesn't refer to any of `members`
+    fn check(&self, members: &[String]) -> Result<(), AetherError> {
+        let valid = match self {
+            SelectionItem::Range(_, end) => *end <= members.len(),
+            SelectionItem::Name(name) => members.contains(name),
+        };
+
+        if valid {
+            Ok(())
+        } else {
+            Err(AetherError::InvalidValue {
+                key: "group selection".into(),
+                value: match self {
+                    SelectionItem::Range(start, end) => format!("{}-{}", start, end),
+                    SelectionItem::Name(name) => name.clone(),
+                },
+            })
+        }
+    }
+}
+
+impl GroupSelection {
+    /// select every member
+    #[must_use]
+    pub fn all() -> GroupSelection {
+        GroupSelection::default()
+    }
+
+    /// parse a selection like "1-3 ^2" or "^foo"
+    pub fn parse(s: &str) -> Result<GroupSelection, AetherError> {
+        let mut selection = GroupSelection::default();
+
+        for item in s.split([' ', ',']).filter(|item| !item.is_empty()) {
+            match item.strip_prefix('^') {
+                Some(item) => selection.exclude.push(SelectionItem::parse(item)?),
+                None => selection.include.push(SelectionItem::parse(item)?),
+            }
+        }
+
+        Ok(selection)
+    }
+
+    /// return the selected names out of a group's `members`, in their order
+    pub fn select(&self, members: &[String]) -> Result<Vec<String>, AetherError> {
+        for item in self.include.iter().chain(&self.exclude) {
+            item.check(members)?;
+        }
+
+        let selected = members
+            .iter()
+            .enumerate()
+            .map(|(i, name)| (i + 1, name))
+            .filter(|(index, name)| {
+                self.include.is_empty()
+                    || self.include.iter().any(|item| item.matches(*index, name))
+            })
+            .filter(|(index, name)| !self.exclude.iter().any(|item| item.matches(*index, name)))
+            .map(|(_, name)| name.clone())
+            .collect();
+
+        Ok(selected)
+    }
+}
+
+/// add `name` to the members of each of `groups`
+fn add_member(map: &mut BTreeMap<String, Vec<String>>, groups: &[String], name: &str) {
+    for group in groups {
+        let members = map.entry(group.clone()).or_default();
+        if !members.iter().any(|member| member == name) {
+            members.push(name.into());
+            members.sort();
+        }
+    }
+}
+
+impl Repo {
+    /// map every group in the repository to the names of its members, sorted
+    #[must_use]
+    pub fn groups(&self) -> BTreeMap<String, Vec<String>> {
+        let mut groups = BTreeMap::new();
+        for pkg in self.pkgs() {
+            add_member(&mut groups, &pkg.pkginfo.group, &pkg.pkginfo.pkgname);
+        }
+
+        groups
+    }
+
+    /// return the newest version of every member of `group`, sorted by name
+    #[must_use]
+    pub fn group(&self, group: &str) -> Vec<&RepoPkg> {
+        let mut members: BTreeMap<&str, &RepoPkg> = BTreeMap::new();
+
+        for pkg in self.pkgs() {
+            if !pkg.pkginfo.group.iter().any(|name| name == group) {
+                continue;
+            }
+
+            let name = pkg.pkginfo.pkgname.as_str();
+            let newer = members
+                .get(name)
+                .is_none_or(|old| vercmp(&pkg.pkginfo.pkgver, &old.pkginfo.pkgver).is_gt());
+            if newer {
+                members.insert(name, pkg);
+            }
+        }
+
+        members.into_values().collect()
+    }
+}
+
+impl PkgList {
+    /// map every group of an installed package to the names of its installed
+    /// members, sorted
+    #[must_use]
+    pub fn groups(&self) -> BTreeMap<String, Vec<String>> {
+        let mut groups = BTreeMap::new();
+        for pkg in self.pkgs() {
+            add_member(&mut groups, &pkg.pkginfo.group, &pkg.pkginfo.pkgname);
+        }
+
+        groups
+    }
+
+    /// return the installed members of `group`, sorted by name
+    #[must_use]
+    pub fn group(&self, group: &str) -> Vec<&Pkg> {
+        let mut members: Vec<&Pkg> = self
+            .pkgs()
+            .iter()
+            .filter(|pkg| pkg.pkginfo.group.iter().any(|name| name == group))
+            .collect();
+        members.sort_by(|a, b| a.pkginfo.pkgname.cmp(&b.pkginfo.pkgname));
+
+        members
+    }
+}
@@ -0,0 +1,643 @@
+use crate::sandbox::{self, Layout};
+use crate::{AetherError, Depend, Environment, Pkg, ScriptletPolicy};
+use std::fs::{read, read_dir, read_link};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::str::from_utf8;
+
+/// where packages ship their hooks, relative to the package root
+const PKG_HOOK_DIR: &str = "usr/share/libalpm/hooks";
+
+/// a kind of change to a package that a hook can trigger on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HookOperation {
+    Install,
+    Upgrade,
+    Remove,
+}
+
+/// what the targets of a trigger are matched against
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HookTriggerType {
+    /// package names
+    Package,
+    /// file paths relative to the package root, e.g. `usr/share/icons/*`
+    Path,
+}
+
+/// when a hook runs relative to the rest of the transaction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HookWhen {
+    PreTransaction,
+    PostTransaction,
+}
+
+/// a `[Trigger]` section of a hook
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HookTrigger {
+    pub operations: Vec<HookOperation>,
+    pub kind: HookTriggerType,
+    /// glob patterns; a leading `!` excludes, and the last matching one wins
+    pub targets: Vec<String>,
+}
+
+impl HookTrigger {
+    /// return whether the package name or path `target` is selected by this
+    /// trigger's patterns
+    #[must_use]
+    pub fn selects(&self, target: &str) -> bool {
+        match_patterns(&self.targets, target)
+    }
+
+    /// return the targets of this trigger touched by `changes`
+    fn matches(&self, changes: &[(HookOperation, &Pkg)]) -> Vec<String> {
+        let mut matched = vec![];
+
+        for (operation, pkg) in changes {
+            if !self.operations.contains(operation) {
+                continue;
+            }
+
+            match self.kind {
+                HookTriggerType::Package => {
+                    let name = &pkg.pkginfo.pkgname;
+                    if self.selects(name) {
+                        matched.push(name.clone());
+                    }
+                }
+                HookTriggerType::Path => {
+                    for file in &pkg.files {
+                        let rel = match file.strip_prefix(&pkg.path) {
+                            Ok(rel) => rel.to_string_lossy(),
+                            Err(_) => continue,
+                        };
+
+                        // package metadata isn't installed anywhere
+                        if rel.starts_with('.') {
+                            continue;
+                        }
+
+                        if self.selects(&rel) {
+                            matched.push(rel.to_string());
+                        }
+                    }
+                }
+            }
+        }
+
+        matched
+    }
+}
+
+/// the captured result of running one hook
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HookOutput {
+    pub hook: String,
+    pub status: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+    /// why the command didn't run, when the sandbox couldn't be set up
+    pub skipped: Option<String>,
+}
+
+impl HookOutput {
+    #[must_use]
+    pub fn success(&self) -> bool {
+        self.status == Some(0)
+    }
+}
+
+/**
+Contains a transaction hook parsed from a `.hook` file in the `alpm-hooks(5)`
+format
+
+Hooks are loaded from `usr/share/libalpm/hooks` in every installed package and
+from the `hooks` directory in the environment's config directory, which
+overrides package hooks of the same file name. A hook symlinked to /dev/null
+is disabled.
+
+# Public fields:
+```text
+name: String
+description: Option<String>
+triggers: Vec<HookTrigger>
+when: HookWhen
+exec: Vec<String>
+depends: Vec<String>
+abort_on_fail: bool
+needs_targets: bool
+```
+
+# Public methods:
+```text
+// parse a .hook file
+Hook::parse() : pub fn parse(file: &dyn AsRef<Path>) -> Result<Hook>
+
+// parse the contents of a .hook file for the hook `name`
+Hook::parse_str() : pub fn parse_str(name: &str, raw: &str) -> Result<Hook>
+
+// load every hook from a list of directories
+Hook::load() : pub fn load(dirs: &[PathBuf]) -> Result<Vec<Hook>>
+
+// return the targets that trigger this hook in a transaction
+Hook::matches() : pub fn matches(&self, changes: &[(HookOperation, &Pkg)]) -> Vec<String>
+
+// run the hook's command, capturing its output
+Hook::run() : pub fn run(&self, ...) -> Result<Option<HookOutput>>
+```
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Hook {
+    /// the file name without the `.hook` extension
+    pub name: String,
+    pub description: Option<String>,
+    pub triggers: Vec<HookTrigger>,
+    pub when: HookWhen,
+    /// the command and its arguments
+    pub exec: Vec<String>,
+    /// dependency strings for packages the command needs
+    pub depends: Vec<String>,
+    /// abort the transaction if the hook fails; only for `PreTransaction`
+    pub abort_on_fail: bool,
+    /// pass the matched targets to the command on stdin, one per line
+    pub needs_targets: bool,
+}
+
+impl Hook {
+    /// parse a .hook file
+    pub fn parse(file: &dyn AsRef<Path>) -> Result<Hook, AetherError> {
+        let file = file.as_ref();
+
+        let raw = read(file).map_err(|source| AetherError::ReadError {
+            file: file.into(),
+            source,
+        })?;
+        let raw = from_utf8(&raw).map_err(AetherError::Utf8Error)?;
+
+        let name = file
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        Self::parse_str(&name, raw)
+    }
+
+    /**
+    parse the contents of a .hook file for the hook `name`
+
+    Path targets are relative to the package root, so a leading `/` is
+    dropped from them.
+    */
+    pub fn parse_str(name: &str, raw: &str) -> Result<Hook, AetherError> {
+        let value_error = |value: &str| AetherError::InfoValueError {
+            kind: "Hook".into(),
+            value: value.into(),
+        };
+
+        let mut triggers = vec![];
+        let mut section = None;
+        let mut description = None;
+        let mut when = None;
+        let mut exec = None;
+        let mut depends = vec![];
+        let mut abort_on_fail = false;
+        let mut needs_targets = false;
+
+        for line in raw.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(header) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                section = match header {
+                    "Trigger" => {
+                        triggers.push((vec![], None, vec![]));
+                        Some("Trigger")
+                    }
+                    "Action" => Some("Action"),
+                    _ => return Err(value_error(line)),
+                };
+                continue;
+            }
+
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), Some(value.trim())),
+                None => (line, None),
+            };
+
+            match (section, key, value) {
+                (Some("Trigger"), "Operation", Some(value)) => {
+                    let operation = match value {
+                        "Install" => HookOperation::Install,
+                        "Upgrade" => HookOperation::Upgrade,
+                        "Remove" => HookOperation::Remove,
+                        _ => return Err(value_error(value)),
+                    };
+                    triggers.last_mut().unwrap().0.push(operation);
+                }
+                (Some("Trigger"), "Type", Some(value)) => {
+                    let kind = match value {
+                        "Package" => HookTriggerType::Package,
+                        // `File` is the deprecated name for `Path`
+                        "Path" | "File" => HookTriggerType::Path,
+                        _ => return Err(value_error(value)),
+                    };
+                    triggers.last_mut().unwrap().1 = Some(kind);
+                }
+                (Some("Trigger"), "Target", Some(value)) => {
+                    triggers.last_mut().unwrap().2.push(value.to_string());
+                }
+                (Some("Action"), "Description", Some(value)) => {
+                    description = Some(value.to_string());
+                }
+                (Some("Action"), "When", Some(value)) => {
+                    when = Some(match value {
+                        "PreTransaction" => HookWhen::PreTransaction,
+                        "PostTransaction" => HookWhen::PostTransaction,
+                        _ => return Err(value_error(value)),
+                    });
+                }
+                (Some("Action"), "Exec", Some(value)) => {
+                    exec = Some(split_words(value).ok_or_else(|| value_error(value))?);
+                }
+                (Some("Action"), "Depends", Some(value)) => depends.push(value.to_string()),
+                (Some("Action"), "AbortOnFail", None) => abort_on_fail = true,
+                (Some("Action"), "NeedsTargets", None) => needs_targets = true,
+                _ => {
+                    return Err(AetherError::InfoKeyError {
+                        kind: "Hook".into(),
+                        key: key.into(),
+                    })
+                }
+            }
+        }
+
+        let missing = |key: &str| AetherError::InvalidValue {
+            key: format!("{} in hook {}", key, name),
+            value: String::new(),
+        };
+
+        if triggers.is_empty() {
+            return Err(missing("[Trigger]"));
+        }
+
+        let mut parsed = vec![];
+        for (operations, kind, targets) in triggers {
+            if operations.is_empty() {
+                return Err(missing("Operation"));
+            }
+            if targets.is_empty() {
+                return Err(missing("Target"));
+            }
+
+            let kind = kind.ok_or_else(|| missing("Type"))?;
+            let targets = match kind {
+                HookTriggerType::Package => targets,
+                HookTriggerType::Path => targets
+                    .into_iter()
+                    .map(|target| match target.strip_prefix('!') {
+                        Some(target) => format!("!{}", target.trim_start_matches('/')),
+                        None => target.trim_start_matches('/').into(),
+                    })
+                    .collect(),
+            };
+
+            parsed.push(HookTrigger {
+                operations,
+                kind,
+                targets,
+            });
+        }
+
+        let when = when.ok_or_else(|| missing("When"))?;
+        let exec = exec.filter(|exec| !exec.is_empty());
+
+        Ok(Hook {
+            name: name.into(),
+            description,
+            triggers: parsed,
+            when,
+            exec: exec.ok_or_else(|| missing("Exec"))?,
+            depends,
+            abort_on_fail: abort_on_fail && when == HookWhen::PreTransaction,
+            needs_targets,
+        })
+    }
+
+    /**
+    load every `*.hook` file in `dirs`, sorted by name
+
+    A hook in a later directory replaces one with the same file name in an
+    earlier one. Missing directories are skipped.
+    */
+    pub fn load(dirs: &[PathBuf]) -> Result<Vec<Hook>, AetherError> {
+        let mut files: Vec<(String, PathBuf)> = vec![];
+
+        for dir in dirs {
+            let entries = match read_dir(dir) {
+                Ok(entries) => entries,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(source) => {
+                    return Err(AetherError::ReadError {
+                        file: dir.clone(),
+                        source,
+                    })
+                }
+            };
+
+            for entry in entries {
+                let path = entry?.path();
+                if path.extension().is_none_or(|ext| ext != "hook") {
+                    continue;
+                }
+
+                let name = path.file_name().unwrap().to_string_lossy().to_string();
+                files.retain(|(other, _)| *other != name);
+                files.push((name, path));
+            }
+        }
+
+        files.sort();
+
+        let mut hooks = vec![];
+        for (_, file) in files {
+            if read_link(&file).is_ok_and(|target| target == Path::new("/dev/null")) {
+                continue;
+            }
+
+            hooks.push(Hook::parse(&file)?);
+        }
+
+        Ok(hooks)
+    }
+
+    /**
+    return the targets that trigger this hook in a transaction making
+    `changes`, sorted and without duplicates; the hook should only run if this
+    isn't empty
+
+    Path triggers are matched against every file of the package being
+    installed, upgraded to or removed.
+    */
+    #[must_use]
+    pub fn matches(&self, changes: &[(HookOperation, &Pkg)]) -> Vec<String> {
+        let mut targets: Vec<String> = self
+            .triggers
+            .iter()
+            .flat_map(|trigger| trigger.matches(changes))
+            .collect();
+
+        targets.sort();
+        targets.dedup();
+        targets
+    }
+
+    /// return an error for the first of the hook's `depends` that none of
+    /// `pkgs` satisfies
+    pub fn check_depends(&self, pkgs: &[Pkg]) -> Result<(), AetherError> {
+        for depend in &self.depends {
+            let parsed = Depend::parse(depend);
+
+            if !pkgs.iter().any(|pkg| {
+                parsed.satisfied_by(&pkg.pkginfo.pkgname, &pkg.pkginfo.pkgver)
+                    || parsed.satisfied_by_provides(&pkg.pkginfo.provides)
+            }) {
+                return Err(AetherError::UnsatisfiedDepend {
+                    depend: depend.clone(),
+                    note: format!("needed by hook {}", self.name),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /**
+    run the hook's command for `env`, capturing its output
+
+    The command runs in the environment's package directory, with
+    `AETHER_ENV`, `AETHER_PKG_DIR` and `AETHER_BIN_DIR` set. A sandboxed hook
+    may only write inside the directory containing the package directory,
+    the user's XDG cache and data directories and /tmp; where no sandbox can
+    be set up, the hook is skipped and the output says why. Returns `None`
+    when nothing ran; a failing command is only an error if the hook has
+    `AbortOnFail`.
+    */
+    pub fn run(
+        &self,
+        targets: &[String],
+        env: &Environment,
+        policy: ScriptletPolicy,
+    ) -> Result<Option<HookOutput>, AetherError> {
+        if policy == ScriptletPolicy::Skip {
+            return Ok(None);
+        }
+
+        let pkg_dir = env.pkg_dir();
+        // caches like icon or font caches live in the user's XDG directories
+        let xdg = [dirs::cache_dir(), dirs::data_dir()];
+        let mut writable = vec![pkg_dir.parent().unwrap_or(pkg_dir)];
+        writable.extend(
+            xdg.iter()
+                .flatten()
+                .map(PathBuf::as_path)
+                .filter(|dir| dir.is_dir()),
+        );
+
+        let mut cmd = Command::new(&self.exec[0]);
+        cmd.args(&self.exec[1..])
+            .current_dir(pkg_dir)
+            .env("AETHER_ENV", env.name())
+            .env("AETHER_PKG_DIR", pkg_dir)
+            .env("AETHER_BIN_DIR", env.bin_dir())
+            .stdin(if self.needs_targets {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        let mut child = match policy {
+            ScriptletPolicy::Sandbox => match sandbox::spawn(&mut cmd, Layout::ReadOnly(&writable))
+            {
+                Ok(child) => child,
+                Err(AetherError::SandboxError { note, .. }) => {
+                    return Ok(Some(HookOutput {
+                        hook: self.name.clone(),
+                        status: None,
+                        stdout: String::new(),
+                        stderr: String::new(),
+                        skipped: Some(note),
+                    }));
+                }
+                Err(err) => return Err(err),
+            },
+            _ => cmd.spawn().map_err(AetherError::ProcessError)?,
+        };
+
+        if let Some(mut stdin) = child.stdin.take() {
+            // a command that exits without reading its targets isn't an error
+            let _ = stdin.write_all(format!("{}\n", targets.join("\n")).as_bytes());
+        }
+
+        let output = child
+            .wait_with_output()
+            .map_err(AetherError::ProcessError)?;
+
+        let output = HookOutput {
+            hook: self.name.clone(),
+            status: output.status.code(),
+            stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+            skipped: None,
+        };
+
+        if self.abort_on_fail && !output.success() {
+            return Err(AetherError::HookError {
+                hook: output.hook,
+                status: output.status,
+                stderr: output.stderr,
+            });
+        }
+
+        Ok(Some(output))
+    }
+}
+
+impl Environment {
+    /// return the directories hooks are loaded from for `pkgs`, lowest
+    /// precedence first
+    pub(crate) fn hook_dirs(&self, pkgs: &[Pkg]) -> Vec<PathBuf> {
+        let mut dirs: Vec<PathBuf> = pkgs.iter().map(|pkg| pkg.path.join(PKG_HOOK_DIR)).collect();
+        dirs.push(self.config_dir().join("hooks"));
+
+        dirs
+    }
+
+    /// load the hooks of the packages installed in this environment and those
+    /// in its config directory
+    pub fn hooks(&self) -> Result<Vec<Hook>, AetherError> {
+        let pkglist = self.pkglist()?;
+
+        Hook::load(&self.hook_dirs(pkglist.pkgs()))
+    }
+}
+
+/// return whether `text` is selected by `patterns`, where the last matching
+/// pattern decides and a leading `!` inverts it
+fn match_patterns(patterns: &[String], text: &str) -> bool {
+    for pattern in patterns.iter().rev() {
+        let (inverted, pattern) = match pattern.strip_prefix('!') {
+            Some(pattern) => (true, pattern),
+            None => (false, pattern.as_str()),
+        };
+
+        if fnmatch(pattern.as_bytes(), text.as_bytes()) {
+            return !inverted;
+        }
+    }
+
+    false
+}
+
+/// shell-style glob matching like fnmatch(3) without flags, so `*` also
+/// matches `/`
+fn fnmatch(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some(b'*') => (0..=text.len()).any(|i| fnmatch(&pattern[1..], &text[i..])),
+        Some(b'?') => !text.is_empty() && fnmatch(&pattern[1..], &text[1..]),
+        Some(b'[') => {
+            let c = match text.first() {
+                Some(c) => *c,
+                None => return false,
+            };
+
+            match match_class(&pattern[1..], c) {
+                Some((matched, rest)) => matched && fnmatch(rest, &text[1..]),
+                // an unterminated class is a literal '['
+                None => c == b'[' && fnmatch(&pattern[1..], &text[1..]),
+            }
+        }
+        Some(b'\\') if pattern.len() > 1 => {
+            text.first() == Some(&pattern[1]) && fnmatch(&pattern[2..], &text[1..])
+        }
+        Some(p) => text.first() == Some(p) && fnmatch(&pattern[1..], &text[1..]),
+    }
+}
+
+/// match `c` against the bracket expression starting just after `[`,
+/// returning whether it matched and the rest of the pattern
+fn match_class(pattern: &[u8], c: u8) -> Option<(bool, &[u8])> {
+    let (negated, mut i) = match pattern.first() {
+        Some(b'!' | b'^') => (true, 1),
+        _ => (false, 0),
+    };
+
+    let mut matched = false;
+    let mut first = true;
+    while i < pattern.len() {
+        if pattern[i] == b']' && !first {
+            return Some((matched != negated, &pattern[i + 1..]));
+        }
+        first = false;
+
+        let lo = pattern[i];
+        if i + 2 < pattern.len() && pattern[i + 1] == b'-' && pattern[i + 2] != b']' {
+            matched |= (lo..=pattern[i + 2]).contains(&c);
+            i += 3;
+        } else {
+            matched |= lo == c;
+            i += 1;
+        }
+    }
+
+    None
+}
+
+/// split an `Exec` line into words, honouring quotes and backslashes; returns
+/// `None` for an unterminated quote
+fn split_words(s: &str) -> Option<Vec<String>> {
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote = None;
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (Some(q), c) if c == q => quote = None,
+            (None | Some('"'), '\\') => {
+                word.push(chars.next()?);
+                in_word = true;
+            }
+            (_, c) => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+
+    if quote.is_some() {
+        return None;
+    }
+    if in_word {
+        words.push(word);
+    }
+
+    Some(words)
+}
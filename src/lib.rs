@@ -6,23 +6,72 @@ The end goal is to create a modern replacement for ALPM and pacman, featuring
 more flexible and comprehensive tools for managing packages and proper per-user package
 management, no root required - while maintaining as much of the simplicity of
 Arch as is practical.
+
+The `aether` command line tool is behind the `cli` feature, so depending on the
+library doesn't pull in its dependencies; build it with `cargo build --features
+cli`. The `serde` feature derives `Serialize` and `Deserialize` for package
+metadata.
 */
 
 #![warn(clippy::all)]
 #![allow(clippy::missing_panics_doc)]
 #![allow(clippy::missing_errors_doc)]
 
-use fs_extra::dir;
 use scan_dir::ScanDir;
+use sha2::{Digest, Sha256};
 use std::fmt;
 use std::fs::{metadata, read, read_dir, DirEntry};
 use std::io::{Cursor, Write};
-use std::os::unix::fs;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use std::str::from_utf8;
 use thiserror::Error;
 
+mod alternative;
+mod arch;
+mod builder;
+mod elf;
+mod environment;
+mod export;
+mod generation;
+mod group;
+mod hook;
+mod manifest;
+mod optdepend;
+mod relocate;
+mod repo;
+mod reproduce;
+mod sandbox;
+mod scriptlet;
+mod search;
+#[cfg(feature = "serde")]
+mod serialize;
+mod soname;
+mod store;
+mod version;
+
+pub use alternative::{Alternative, AlternativeProvider};
+pub use arch::{Architectures, ARCH_ANY};
+pub use builder::PkgBuilder;
+pub use elf::{ElfInfo, LinkedLib};
+pub use environment::{Environment, SyncReport};
+pub use export::{read_wrapper, ExportCheck, ExportMode, Exports};
+pub use generation::Generation;
+pub use group::GroupSelection;
+pub use hook::{Hook, HookOperation, HookOutput, HookTrigger, HookTriggerType, HookWhen};
+pub use manifest::{LockedPkg, Lockfile, Manifest, RepoSource, LOCK_FILE, MANIFEST_FILE};
+pub use optdepend::{InstallReason, OptDepend};
+pub use relocate::{RelocatedFile, Relocation, RelocationKind, RelocationReport, RELOCATED_FILE};
+pub use repo::{Repo, RepoPkg};
+pub use reproduce::{ReproducePlan, ReproduceSource, ReproduceStep};
+pub use scriptlet::{Scriptlet, ScriptletOutput, ScriptletPolicy, ScriptletStage};
+pub use search::{SearchField, SearchQuery, SearchResult};
+#[cfg(feature = "serde")]
+pub use serialize::MTreeEntry;
+pub use soname::{Soname, SonameCheck};
+pub use store::{GcReport, Store};
+pub use version::{vercmp, Constraint, Depend, VersionOp};
+
 #[must_use]
 pub fn bin_dir() -> PathBuf {
     dirs::executable_dir().unwrap()
@@ -30,17 +79,117 @@ pub fn bin_dir() -> PathBuf {
 
 #[must_use]
 pub fn cache_dir() -> PathBuf {
-    dirs::cache_dir().unwrap().join(&"aether")
+    dirs::cache_dir().unwrap().join("aether")
 }
 
 #[must_use]
 pub fn config_dir() -> PathBuf {
-    dirs::config_dir().unwrap().join(&"aether")
+    dirs::config_dir().unwrap().join("aether")
+}
+
+/// return the lowercase hex sha256 digest of a file's contents
+pub fn sha256sum(file: &dyn AsRef<Path>) -> Result<String, AetherError> {
+    let file = file.as_ref();
+
+    let mut reader = std::fs::File::open(file).map_err(|source| AetherError::ReadError {
+        file: file.into(),
+        source,
+    })?;
+
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut reader, &mut hasher).map_err(|source| AetherError::ReadError {
+        file: file.into(),
+        source,
+    })?;
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[must_use]
+pub fn db_dir() -> PathBuf {
+    dirs::state_dir().unwrap().join("aether/db")
+}
+
+#[must_use]
+pub fn env_dir() -> PathBuf {
+    dirs::state_dir().unwrap().join("aether/env")
 }
 
 #[must_use]
 pub fn pkg_dir() -> PathBuf {
-    dirs::state_dir().unwrap().join(&"aether/pkg")
+    dirs::state_dir().unwrap().join("aether/pkg")
+}
+
+#[must_use]
+pub fn store_dir() -> PathBuf {
+    dirs::state_dir().unwrap().join("aether/store")
+}
+
+#[must_use]
+pub fn usr_dir() -> PathBuf {
+    dirs::state_dir().unwrap().join("aether/usr")
+}
+
+/**
+A directory in the cache to extract a package archive into, deleted again
+when dropped
+
+It is emptied when created, so files left behind by an extraction that was
+interrupted don't end up in the package.
+*/
+#[derive(Debug)]
+pub struct ExtractDir {
+    path: PathBuf,
+}
+
+impl ExtractDir {
+    /// return an empty directory `name` in the cache's extract directory
+    pub fn new(name: &dyn AsRef<Path>) -> Result<ExtractDir, AetherError> {
+        let path = cache_dir().join("extract").join(name);
+
+        if std::fs::symlink_metadata(&path).is_ok() {
+            std::fs::remove_dir_all(&path).map_err(|source| AetherError::WriteError {
+                file: path.clone(),
+                source,
+            })?;
+        }
+
+        Ok(ExtractDir { path })
+    }
+
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for ExtractDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+/// return every entry under `dir` without following symlinks, sorted so that
+/// each directory comes before everything in it
+pub(crate) fn walk_dir(dir: &Path) -> Result<Vec<PathBuf>, AetherError> {
+    let read_error = |source| AetherError::ReadError {
+        file: dir.into(),
+        source,
+    };
+
+    let mut entries = vec![];
+    for entry in read_dir(dir).map_err(read_error)? {
+        let entry = entry.map_err(read_error)?;
+        let path = entry.path();
+
+        entries.push(path.clone());
+        if entry.file_type().map_err(read_error)?.is_dir() {
+            entries.extend(walk_dir(&path)?);
+        }
+    }
+
+    entries.sort();
+    Ok(entries)
 }
 
 #[derive(Error, Debug)]
@@ -48,6 +197,16 @@ pub enum AetherError {
     #[error("file already exists: {0}")]
     AlreadyExists(String),
 
+    #[error("unable to build package from {path}: {note}")]
+    BuildError { path: PathBuf, note: String },
+
+    #[error("checksum mismatch for {file}: expected {expected}, found {found}")]
+    ChecksumError {
+        file: PathBuf,
+        expected: String,
+        found: String,
+    },
+
     #[error("unable to copy '{from}' -> '{to}'")]
     CopyError {
         from: PathBuf,
@@ -55,6 +214,30 @@ pub enum AetherError {
         source: fs_extra::error::Error,
     },
 
+    #[error("refusing to replace or remove {0}, which aether didn't create")]
+    ForeignFile(PathBuf),
+
+    #[error("hook {hook} failed ({status:?}): {stderr}")]
+    HookError {
+        hook: String,
+        status: Option<i32>,
+        stderr: String,
+    },
+
+    #[error("{pkg} is built for '{arch}', which isn't one of '{accepted}'")]
+    IncompatibleArch {
+        pkg: String,
+        arch: String,
+        accepted: String,
+    },
+
+    #[error("error in {file} on line {line}")]
+    InfoLineError {
+        file: PathBuf,
+        line: usize,
+        source: Box<AetherError>,
+    },
+
     #[error("invalid key name for {kind}: '{key}'")]
     InfoKeyError { kind: String, key: String },
 
@@ -77,6 +260,12 @@ pub enum AetherError {
         source: std::io::Error,
     },
 
+    #[error("environment not found: {0}")]
+    MissingEnv(String),
+
+    #[error("generation not found: {0}")]
+    MissingGeneration(u64),
+
     #[error("missing package execs: {0:?}")]
     MissingExec(Vec<PathBuf>),
 
@@ -95,12 +284,35 @@ pub enum AetherError {
     #[error("error executing process")]
     ProcessError(#[from] std::io::Error),
 
+    #[error("{function} scriptlet of {pkg} failed ({status:?}): {stderr}")]
+    ScriptletError {
+        pkg: String,
+        function: String,
+        status: Option<i32>,
+        stderr: String,
+    },
+
+    #[error("unable to sandbox process: {note}")]
+    SandboxError {
+        note: String,
+        source: std::io::Error,
+    },
+
     #[error("unable to read file/directory: {file}")]
     ReadError {
         file: PathBuf,
         source: std::io::Error,
     },
 
+    #[error("unable to parse {file}")]
+    TomlError {
+        file: PathBuf,
+        source: toml::de::Error,
+    },
+
+    #[error("unable to satisfy dependency {depend}: {note}")]
+    UnsatisfiedDepend { depend: String, note: String },
+
     #[error("unknown error")]
     Unknown,
 
@@ -114,48 +326,153 @@ pub enum AetherError {
     },
 }
 
+/// how the .PKGINFO and .BUILDINFO parsers treat keys they don't know
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ParseMode {
+    /// fail with [`AetherError::InfoKeyError`]
+    Strict,
+    /// skip the line, so files from newer makepkg versions still parse
+    #[default]
+    Lenient,
+}
+
+/**
+split a `key = value` line, returning `None` for blank and comment lines
+
+Only the first ` = ` separates the key, so values may contain it, and a
+trailing ` =` with nothing after it is an empty value.
+*/
+fn split_info_line(line: &str) -> Result<Option<(&str, &str)>, AetherError> {
+    if line.trim().is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+
+    let pair = line
+        .split_once(" = ")
+        .or_else(|| line.strip_suffix(" =").map(|key| (key, "")));
+
+    match pair {
+        Some((key, value)) if !key.is_empty() && !key.contains(char::is_whitespace) => {
+            Ok(Some((key, value)))
+        }
+        _ => Err(AetherError::InfoParseError {
+            field: "key = value".into(),
+            line: line.into(),
+        }),
+    }
+}
+
+fn parse_info_num<T: std::str::FromStr>(key: &str, value: &str) -> Result<T, AetherError> {
+    value.trim().parse().map_err(|_| AetherError::InvalidValue {
+        key: key.into(),
+        value: value.into(),
+    })
+}
+
+/// the kind of package a .PKGINFO describes, from its `pkgtype` xdata
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum PkgType {
+    /// a package built from a PKGBUILD with a single package
+    Pkg,
+    /// one of several packages built from the same PKGBUILD
+    Split,
+    /// debug symbols split out of another package
+    Debug,
+    /// a source package
+    Src,
+}
+
+impl PkgType {
+    #[must_use]
+    pub fn parse(s: &str) -> Option<PkgType> {
+        match s {
+            "pkg" => Some(PkgType::Pkg),
+            "split" => Some(PkgType::Split),
+            "debug" => Some(PkgType::Debug),
+            "src" => Some(PkgType::Src),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for PkgType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let pkgtype = match self {
+            PkgType::Pkg => "pkg",
+            PkgType::Split => "split",
+            PkgType::Debug => "debug",
+            PkgType::Src => "src",
+        };
+
+        write!(f, "{}", pkgtype)
+    }
+}
+
 /**
 Contains data parsed from a .PKGINFO file
 
 # Public fields:
-```
+```text
 pkgname: String
 pkgbase: String
 pkgver: String
 pkgdesc: String
 url: String
-builddate: i32
+builddate: i64
 packager: String
-size: i32
+size: u64
 arch: Vec<String>
-license: String
+license: Vec<String>
+replaces: Vec<String>
 conflict: Vec<String>
 provides: Vec<String>
 depend: Vec<String>
 optdepend: Vec<String>
+makedepend: Vec<String>
+checkdepend: Vec<String>
+backup: Vec<String>
+group: Vec<String>
+xdata: Vec<String>
 ```
 
 # Public methods:
-```
+```text
 // return an intialized PkgInfo instance
 PkgInfo::new() : pub fn new() -> PkgInfo
 
 // parse a file and return a PkgInfo instance
 PkgInfo::parse() : pub fn parse(file: &str) -> Result<PkgInfo>
+
+// parse a file, choosing how unknown keys are handled
+PkgInfo::parse_with() : pub fn parse_with(file: &str, mode: ParseMode) -> Result<PkgInfo>
+
+// write a PkgInfo instance to a file
+PkgInfo::write_to() : pub fn write_to(&self, file: &str) -> Result<()>
+
+// return the value of an xdata key
+PkgInfo::xdata_value() : pub fn xdata_value(&self, key: &str) -> Option<&str>
+
+// return the package type from xdata
+PkgInfo::pkgtype() : pub fn pkgtype(&self) -> Option<PkgType>
 ```
 */
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct PkgInfo {
     pub pkgname: String,
     pub pkgbase: String,
     pub pkgver: String,
     pub pkgdesc: String,
     pub url: String,
-    pub builddate: i32,
+    pub builddate: i64,
     pub packager: String,
-    pub size: i32,
+    pub size: u64,
     pub arch: Vec<String>,
-    pub license: String,
+    pub license: Vec<String>,
+    pub replaces: Vec<String>,
     pub conflict: Vec<String>,
     pub provides: Vec<String>,
     pub depend: Vec<String>,
@@ -164,6 +481,8 @@ pub struct PkgInfo {
     pub checkdepend: Vec<String>,
     pub backup: Vec<String>,
     pub group: Vec<String>,
+    /// extra `key=value` data, such as `pkgtype=debug`
+    pub xdata: Vec<String>,
 }
 
 impl PkgInfo {
@@ -180,7 +499,8 @@ impl PkgInfo {
             packager: String::new(),
             size: 0,
             arch: vec![],
-            license: String::new(),
+            license: vec![],
+            replaces: vec![],
             conflict: vec![],
             provides: vec![],
             depend: vec![],
@@ -189,11 +509,18 @@ impl PkgInfo {
             checkdepend: vec![],
             backup: vec![],
             group: vec![],
+            xdata: vec![],
         }
     }
 
-    /// parse a file and return a `PkgInfo` instance
+    /// parse a file and return a `PkgInfo` instance, skipping unknown keys
     pub fn parse(file: &dyn AsRef<Path>) -> Result<PkgInfo, AetherError> {
+        Self::parse_with(file, ParseMode::Lenient)
+    }
+
+    /// parse a file and return a `PkgInfo` instance, handling unknown keys
+    /// according to `mode`
+    pub fn parse_with(file: &dyn AsRef<Path>, mode: ParseMode) -> Result<PkgInfo, AetherError> {
         let file = &file.as_ref();
 
         let pkginfo_raw = read(file).map_err(|source| AetherError::ReadError {
@@ -206,14 +533,17 @@ impl PkgInfo {
             .lines();
 
         let mut pkginfo = PkgInfo::new();
-        for line in pkginfo_lines {
-            if line.starts_with('#') {
-                continue;
-            }
-
-            let key = line.split(" = ").next().unwrap();
+        for (i, line) in pkginfo_lines.enumerate() {
+            let at_line = |source| AetherError::InfoLineError {
+                file: file.to_path_buf(),
+                line: i + 1,
+                source: Box::new(source),
+            };
 
-            let value = line.split(" = ").nth(1).unwrap();
+            let (key, value) = match split_info_line(line).map_err(at_line)? {
+                Some(pair) => pair,
+                None => continue,
+            };
 
             match key {
                 "pkgname" => pkginfo.pkgname = value.to_string(),
@@ -221,11 +551,12 @@ impl PkgInfo {
                 "pkgver" => pkginfo.pkgver = value.to_string(),
                 "pkgdesc" => pkginfo.pkgdesc = value.to_string(),
                 "url" => pkginfo.url = value.to_string(),
-                "builddate" => pkginfo.builddate = value.parse().unwrap(),
+                "builddate" => pkginfo.builddate = parse_info_num(key, value).map_err(at_line)?,
                 "packager" => pkginfo.packager = value.to_string(),
-                "size" => pkginfo.size = value.parse().unwrap(),
+                "size" => pkginfo.size = parse_info_num(key, value).map_err(at_line)?,
                 "arch" => pkginfo.arch.push(value.to_string()),
-                "license" => pkginfo.license = value.to_string(),
+                "license" => pkginfo.license.push(value.to_string()),
+                "replaces" => pkginfo.replaces.push(value.to_string()),
                 "conflict" => pkginfo.conflict.push(value.to_string()),
                 "provides" => pkginfo.provides.push(value.to_string()),
                 "depend" => pkginfo.depend.push(value.to_string()),
@@ -234,11 +565,22 @@ impl PkgInfo {
                 "checkdepend" => pkginfo.checkdepend.push(value.to_string()),
                 "backup" => pkginfo.backup.push(value.to_string()),
                 "group" => pkginfo.group.push(value.to_string()),
-                &_ => {
-                    return Err(AetherError::InfoKeyError {
+                "xdata" => {
+                    if !value.contains('=') {
+                        return Err(at_line(AetherError::InvalidValue {
+                            key: key.into(),
+                            value: value.into(),
+                        }));
+                    }
+
+                    pkginfo.xdata.push(value.to_string());
+                }
+                _ if mode == ParseMode::Lenient => {}
+                _ => {
+                    return Err(at_line(AetherError::InfoKeyError {
                         kind: "PkgInfo".into(),
                         key: key.into(),
-                    })
+                    }))
                 }
             }
         }
@@ -247,18 +589,200 @@ impl PkgInfo {
     }
 }
 
+impl PkgInfo {
+    /// return the value of the first `key=value` entry in `xdata` for `key`
+    #[must_use]
+    pub fn xdata_value(&self, key: &str) -> Option<&str> {
+        self.xdata.iter().find_map(|entry| {
+            entry
+                .split_once('=')
+                .filter(|(k, _)| *k == key)
+                .map(|(_, value)| value)
+        })
+    }
+
+    /// return the package type recorded in `xdata`, if it's a known one
+    #[must_use]
+    pub fn pkgtype(&self) -> Option<PkgType> {
+        self.xdata_value("pkgtype").and_then(PkgType::parse)
+    }
+
+    /// write this `PkgInfo` to a file in the .PKGINFO format
+    pub fn write_to(&self, file: &dyn AsRef<Path>) -> Result<(), AetherError> {
+        let file = file.as_ref();
+
+        std::fs::write(file, self.to_string()).map_err(|source| AetherError::WriteError {
+            file: file.into(),
+            source,
+        })
+    }
+}
+
+/// write `key = value`, skipping empty values
+fn write_info_pair(f: &mut fmt::Formatter, key: &str, value: &str) -> fmt::Result {
+    if value.is_empty() {
+        return Ok(());
+    }
+
+    writeln!(f, "{} = {}", key, value)
+}
+
+/// write `key = value` once for every value
+fn write_info_pairs(f: &mut fmt::Formatter, key: &str, values: &[String]) -> fmt::Result {
+    for value in values {
+        writeln!(f, "{} = {}", key, value)?;
+    }
+
+    Ok(())
+}
+
+/// formats a `PkgInfo` as a .PKGINFO file, in the order makepkg writes it
+impl fmt::Display for PkgInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "# Generated by {} {}",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION")
+        )?;
+
+        write_info_pair(f, "pkgname", &self.pkgname)?;
+        write_info_pair(f, "pkgbase", &self.pkgbase)?;
+        write_info_pairs(f, "xdata", &self.xdata)?;
+        write_info_pair(f, "pkgver", &self.pkgver)?;
+        write_info_pair(f, "pkgdesc", &self.pkgdesc)?;
+        write_info_pair(f, "url", &self.url)?;
+        write_info_pair(f, "builddate", &self.builddate.to_string())?;
+        write_info_pair(f, "packager", &self.packager)?;
+        write_info_pair(f, "size", &self.size.to_string())?;
+        write_info_pairs(f, "arch", &self.arch)?;
+        write_info_pairs(f, "license", &self.license)?;
+        write_info_pairs(f, "replaces", &self.replaces)?;
+        write_info_pairs(f, "group", &self.group)?;
+        write_info_pairs(f, "conflict", &self.conflict)?;
+        write_info_pairs(f, "provides", &self.provides)?;
+        write_info_pairs(f, "backup", &self.backup)?;
+        write_info_pairs(f, "depend", &self.depend)?;
+        write_info_pairs(f, "optdepend", &self.optdepend)?;
+        write_info_pairs(f, "makedepend", &self.makedepend)?;
+        write_info_pairs(f, "checkdepend", &self.checkdepend)
+    }
+}
+
 impl Default for PkgInfo {
     fn default() -> Self {
         Self::new()
     }
 }
 
+/**
+the version of the .BUILDINFO format; version 2 adds `startdir`, `buildtool`
+and `buildtoolver`
+*/
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(into = "u8", try_from = "u8"))]
+pub enum BuildInfoFormat {
+    V1,
+    #[default]
+    V2,
+}
+
+impl BuildInfoFormat {
+    #[must_use]
+    pub fn parse(s: &str) -> Option<BuildInfoFormat> {
+        match s.trim() {
+            "1" => Some(BuildInfoFormat::V1),
+            "2" => Some(BuildInfoFormat::V2),
+            _ => None,
+        }
+    }
+}
+
+impl From<BuildInfoFormat> for u8 {
+    fn from(format: BuildInfoFormat) -> u8 {
+        match format {
+            BuildInfoFormat::V1 => 1,
+            BuildInfoFormat::V2 => 2,
+        }
+    }
+}
+
+impl TryFrom<u8> for BuildInfoFormat {
+    type Error = AetherError;
+
+    fn try_from(format: u8) -> Result<BuildInfoFormat, AetherError> {
+        BuildInfoFormat::parse(&format.to_string()).ok_or(AetherError::InvalidValue {
+            key: "format".into(),
+            value: format.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for BuildInfoFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BuildInfoFormat::V1 => write!(f, "1"),
+            BuildInfoFormat::V2 => write!(f, "2"),
+        }
+    }
+}
+
+/// a package from the `installed` list of a .BUILDINFO, written as
+/// `name-pkgver-pkgrel-arch`
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InstalledPkg {
+    pub name: String,
+    /// `pkgver-pkgrel`, with the epoch if there is one
+    pub version: String,
+    pub arch: String,
+}
+
+impl InstalledPkg {
+    /// parse an `installed` entry like `glibc-2.38-7-x86_64`
+    pub fn parse(s: &str) -> Result<InstalledPkg, AetherError> {
+        let mut parts = s.rsplitn(4, '-');
+        let (arch, pkgrel, pkgver, name) = (parts.next(), parts.next(), parts.next(), parts.next());
+
+        match (name, pkgver, pkgrel, arch) {
+            (Some(name), Some(pkgver), Some(pkgrel), Some(arch))
+                if [name, pkgver, pkgrel, arch]
+                    .iter()
+                    .all(|part| !part.is_empty()) =>
+            {
+                Ok(InstalledPkg {
+                    name: name.into(),
+                    version: format!("{}-{}", pkgver, pkgrel),
+                    arch: arch.into(),
+                })
+            }
+            _ => Err(AetherError::InfoParseError {
+                field: "installed".into(),
+                line: s.into(),
+            }),
+        }
+    }
+
+    /// return `name-version`, the same as [`Pkg::get_refstr`]
+    #[must_use]
+    pub fn get_refstr(&self) -> String {
+        format!("{}-{}", self.name, self.version)
+    }
+}
+
+impl fmt::Display for InstalledPkg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-{}-{}", self.name, self.version, self.arch)
+    }
+}
+
 /**
 Contains data parsed from a .BUILDINFO file
 
 # Public fields:
-```
-format: i32
+```text
+format: BuildInfoFormat
 pkgname: String
 pkgbase: String
 pkgver: String
@@ -267,28 +791,39 @@ pkgbuild_sha256sum: String
 pkgbuild_md5sum: String
 pkgbuild_sha1sum: String
 packager: String
-builddate: i32
+builddate: i64
 builddir: String
 startdir: String
 buildtool: String
 buildtoolver: String
 buildenv: Vec<String>
 options: Vec<String>
-installed: Vec<String>
+installed: Vec<InstalledPkg>
 ```
 
 # Public methods:
-```
+```text
 // return an intialized BuildInfo instance
 BuildInfo::new() : pub fn new() -> BuildInfo
 
 // parse a file and return a BuildInfo instance
 BuildInfo::parse() : pub fn parse(file: &str) -> Result<BuildInfo>
+
+// parse a file, choosing how unknown keys are handled
+BuildInfo::parse_with() : pub fn parse_with(file: &str, mode: ParseMode) -> Result<BuildInfo>
+
+// write a BuildInfo instance to a file
+BuildInfo::write_to() : pub fn write_to(&self, file: &str) -> Result<()>
+
+// list where to get every package needed to rebuild in the recorded environment
+BuildInfo::reproduce_plan() : pub fn reproduce_plan(&self, ...) -> ReproducePlan
 ```
 */
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct BuildInfo {
-    pub format: i32,
+    pub format: BuildInfoFormat,
     pub pkgname: String,
     pub pkgbase: String,
     pub pkgver: String,
@@ -297,14 +832,14 @@ pub struct BuildInfo {
     pub pkgbuild_md5sum: String,
     pub pkgbuild_sha1sum: String,
     pub packager: String,
-    pub builddate: i32,
+    pub builddate: i64,
     pub builddir: String,
     pub startdir: String,
     pub buildtool: String,
     pub buildtoolver: String,
     pub buildenv: Vec<String>,
     pub options: Vec<String>,
-    pub installed: Vec<String>,
+    pub installed: Vec<InstalledPkg>,
 }
 
 impl BuildInfo {
@@ -312,7 +847,7 @@ impl BuildInfo {
     #[must_use]
     pub fn new() -> BuildInfo {
         BuildInfo {
-            format: 0,
+            format: BuildInfoFormat::V2,
             pkgname: String::new(),
             pkgbase: String::new(),
             pkgver: String::new(),
@@ -332,8 +867,14 @@ impl BuildInfo {
         }
     }
 
-    /// parse a file and return a `BuildInfo` instance
+    /// parse a file and return a `BuildInfo` instance, skipping unknown keys
     pub fn parse(file: &dyn AsRef<Path>) -> Result<BuildInfo, AetherError> {
+        Self::parse_with(file, ParseMode::Lenient)
+    }
+
+    /// parse a file and return a `BuildInfo` instance, handling unknown keys
+    /// according to `mode`
+    pub fn parse_with(file: &dyn AsRef<Path>, mode: ParseMode) -> Result<BuildInfo, AetherError> {
         let file = &file.as_ref();
 
         let buildinfo_raw = read(file).map_err(|source| AetherError::ReadError {
@@ -346,32 +887,25 @@ impl BuildInfo {
             .lines();
 
         let mut buildinfo = BuildInfo::new();
-        for line in buildinfo_lines {
-            let key = match line.split(" = ").next() {
-                Some(v) => v,
-                None => {
-                    return Err(AetherError::InfoParseError {
-                        field: "key".into(),
-                        line: line.into(),
-                    })
-                }
+        for (i, line) in buildinfo_lines.enumerate() {
+            let at_line = |source| AetherError::InfoLineError {
+                file: file.to_path_buf(),
+                line: i + 1,
+                source: Box::new(source),
             };
 
-            let value = match line.split(" = ").nth(1) {
-                Some(v) => v,
-                None => {
-                    return Err(AetherError::InfoParseError {
-                        field: "value".into(),
-                        line: line.into(),
-                    })
-                }
+            let (key, value) = match split_info_line(line).map_err(at_line)? {
+                Some(pair) => pair,
+                None => continue,
             };
 
             match key {
                 "format" => {
-                    buildinfo.format = value.parse().map_err(|_| AetherError::InvalidValue {
-                        key: key.into(),
-                        value: value.into(),
+                    buildinfo.format = BuildInfoFormat::parse(value).ok_or_else(|| {
+                        at_line(AetherError::InvalidValue {
+                            key: key.into(),
+                            value: value.into(),
+                        })
                     })?;
                 }
                 "pkgname" => buildinfo.pkgname = value.into(),
@@ -382,24 +916,30 @@ impl BuildInfo {
                 "pkgbuild_md5sum" => buildinfo.pkgbuild_md5sum = value.into(),
                 "pkgbuild_sha1sum" => buildinfo.pkgbuild_sha1sum = value.into(),
                 "packager" => buildinfo.packager = value.into(),
-                "builddate" => {
-                    buildinfo.builddate = value.parse().map_err(|_| AetherError::InvalidValue {
+                "builddate" => buildinfo.builddate = parse_info_num(key, value).map_err(at_line)?,
+                "builddir" => buildinfo.builddir = value.into(),
+                "startdir" | "buildtool" | "buildtoolver"
+                    if mode == ParseMode::Strict && buildinfo.format == BuildInfoFormat::V1 =>
+                {
+                    return Err(at_line(AetherError::InfoKeyError {
+                        kind: "BuildInfo format 1".into(),
                         key: key.into(),
-                        value: value.into(),
-                    })?;
+                    }));
                 }
-                "builddir" => buildinfo.builddir = value.into(),
                 "startdir" => buildinfo.startdir = value.into(),
                 "buildtool" => buildinfo.buildtool = value.into(),
                 "buildtoolver" => buildinfo.buildtoolver = value.into(),
                 "buildenv" => buildinfo.buildenv.push(value.into()),
                 "options" => buildinfo.options.push(value.into()),
-                "installed" => buildinfo.installed.push(value.into()),
-                &_ => {
-                    return Err(AetherError::InfoKeyError {
+                "installed" => buildinfo
+                    .installed
+                    .push(InstalledPkg::parse(value).map_err(at_line)?),
+                _ if mode == ParseMode::Lenient => {}
+                _ => {
+                    return Err(at_line(AetherError::InfoKeyError {
                         kind: "BuildInfo".into(),
                         key: key.into(),
-                    })
+                    }))
                 }
             }
         }
@@ -408,6 +948,45 @@ impl BuildInfo {
     }
 }
 
+impl BuildInfo {
+    /// write this `BuildInfo` to a file in the .BUILDINFO format
+    pub fn write_to(&self, file: &dyn AsRef<Path>) -> Result<(), AetherError> {
+        let file = file.as_ref();
+
+        std::fs::write(file, self.to_string()).map_err(|source| AetherError::WriteError {
+            file: file.into(),
+            source,
+        })
+    }
+}
+
+/// formats a `BuildInfo` as a .BUILDINFO file, in the order makepkg writes it;
+/// keys that format 1 doesn't have are left out of it
+impl fmt::Display for BuildInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_info_pair(f, "format", &self.format.to_string())?;
+        write_info_pair(f, "pkgname", &self.pkgname)?;
+        write_info_pair(f, "pkgbase", &self.pkgbase)?;
+        write_info_pair(f, "pkgver", &self.pkgver)?;
+        write_info_pairs(f, "pkgarch", &self.pkgarch)?;
+        write_info_pair(f, "pkgbuild_sha256sum", &self.pkgbuild_sha256sum)?;
+        write_info_pair(f, "pkgbuild_md5sum", &self.pkgbuild_md5sum)?;
+        write_info_pair(f, "pkgbuild_sha1sum", &self.pkgbuild_sha1sum)?;
+        write_info_pair(f, "packager", &self.packager)?;
+        write_info_pair(f, "builddate", &self.builddate.to_string())?;
+        write_info_pair(f, "builddir", &self.builddir)?;
+        if self.format == BuildInfoFormat::V2 {
+            write_info_pair(f, "startdir", &self.startdir)?;
+            write_info_pair(f, "buildtool", &self.buildtool)?;
+            write_info_pair(f, "buildtoolver", &self.buildtoolver)?;
+        }
+        write_info_pairs(f, "buildenv", &self.buildenv)?;
+        write_info_pairs(f, "options", &self.options)?;
+        let installed: Vec<String> = self.installed.iter().map(ToString::to_string).collect();
+        write_info_pairs(f, "installed", &installed)
+    }
+}
+
 impl Default for BuildInfo {
     fn default() -> Self {
         Self::new()
@@ -419,7 +998,7 @@ Contains data read from a .MTREE file
 - This struct provides a wrapper for the [`mtree::MTree`] struct
 
 # Public methods:
-```
+```text
 /// parse an mtree::MTree from this struct
 fn get(&self) -> mtree::MTree<Cursor<Vec<u8>>>
 
@@ -438,6 +1017,18 @@ impl MTree {
         mtree::MTree::from_reader(Cursor::new(self.raw.clone()))
     }
 
+    /// parse every entry of the mtree
+    pub fn entries(&self) -> Result<Vec<mtree::Entry>, AetherError> {
+        self.get()
+            .map(|entry| {
+                entry.map_err(|err| AetherError::InfoParseError {
+                    field: "mtree entry".into(),
+                    line: err.to_string(),
+                })
+            })
+            .collect()
+    }
+
     /// read a file into an `MTree` instance
     fn parse(file: &dyn AsRef<Path>) -> Result<MTree, AetherError> {
         let file = &file.as_ref();
@@ -474,15 +1065,17 @@ impl MTree {
 Contains all information related to a single Aether or ALPM compatible package
 
 # Public fields:
-```
+```text
 files: Vec<String>
 buildinfo: Option<BuildInfo>
 mtree: MTree
 pkginfo: PkgInfo
+path: PathBuf
+scriptlet: Option<Scriptlet>
 ```
 
 # Public methods:
-```
+```text
 // parse the specified directory and return a Pkg from its contents
 Pkg::from_dir() : pub fn from_dir(dir: &str) -> Result<Pkg>
 
@@ -496,12 +1089,14 @@ Pkg::show() : pub fn show(&mut self)
 ```
 */
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Pkg {
     pub files: Vec<PathBuf>,
     pub buildinfo: Option<BuildInfo>,
     pub mtree: MTree,
     pub pkginfo: PkgInfo,
     pub path: PathBuf,
+    pub scriptlet: Option<Scriptlet>,
 }
 
 impl Pkg {
@@ -515,6 +1110,7 @@ impl Pkg {
         let buildinfo_path = Path::join(path, ".BUILDINFO");
         let mtree_path = Path::join(path, ".MTREE");
         let pkginfo_path = Path::join(path, ".PKGINFO");
+        let install_path = Path::join(path, ".INSTALL");
 
         ScanDir::all()
             .walk(path, |iter| {
@@ -527,6 +1123,11 @@ impl Pkg {
         let buildinfo = BuildInfo::parse(&buildinfo_path).ok();
         let mtree = MTree::parse(&Path::new(&mtree_path))?;
         let pkginfo = PkgInfo::parse(&Path::new(&pkginfo_path))?;
+        let scriptlet = if install_path.exists() {
+            Some(Scriptlet::parse(&install_path)?)
+        } else {
+            None
+        };
 
         let pkg = Pkg {
             files,
@@ -534,11 +1135,43 @@ impl Pkg {
             mtree,
             pkginfo,
             path: PathBuf::from(path),
+            scriptlet,
         };
 
         Ok(pkg)
     }
 
+    /// extract a package archive into `dest` and return a Pkg from its contents
+    pub fn from_archive(
+        archive: &dyn AsRef<Path>,
+        dest: &dyn AsRef<Path>,
+    ) -> Result<Pkg, AetherError> {
+        let archive = archive.as_ref();
+        let dest = dest.as_ref();
+
+        std::fs::create_dir_all(dest).map_err(|source| AetherError::WriteError {
+            file: dest.into(),
+            source,
+        })?;
+
+        let status = Command::new("tar")
+            .arg("-xf")
+            .arg(archive)
+            .arg("-C")
+            .arg(dest)
+            .status()
+            .map_err(AetherError::ProcessError)?;
+
+        if !status.success() {
+            return Err(AetherError::InvalidPkg {
+                path: archive.into(),
+                note: "unable to extract archive".into(),
+            });
+        }
+
+        Pkg::from_dir(&dest)
+    }
+
     /// parse the specified directory and return a Result<()> of whether or not
     /// it's a valid package
     pub fn is_valid_dir(dir: &dyn AsRef<Path>) -> Result<(), AetherError> {
@@ -579,6 +1212,14 @@ impl Pkg {
             note: "invalid .PKGINFO file".into(),
         })?;
 
+        let install_path = Path::join(path, ".INSTALL");
+        if install_path.exists() {
+            Scriptlet::parse(&install_path).map_err(|_| AetherError::InvalidPkg {
+                path: path.into(),
+                note: "invalid .INSTALL file".into(),
+            })?;
+        }
+
         Ok(())
     }
 
@@ -587,6 +1228,12 @@ impl Pkg {
     }
 
     pub fn check_files(&self) -> Result<Vec<PathBuf>, AetherError> {
+        self.check_files_in(&pkg_dir())
+    }
+
+    pub fn check_files_in(&self, dir: &dyn AsRef<Path>) -> Result<Vec<PathBuf>, AetherError> {
+        let dir = dir.as_ref();
+
         let mut checked = vec![];
         let mut missing = vec![];
 
@@ -596,7 +1243,7 @@ impl Pkg {
                 None => return Err(AetherError::Unknown),
             };
 
-            let path = pkg_dir().join(name);
+            let path = dir.join(name);
 
             if Path::exists(&path) {
                 checked.push(path);
@@ -613,13 +1260,18 @@ impl Pkg {
     }
 
     pub fn check_execs(&self) -> Result<Vec<PathBuf>, AetherError> {
+        self.check_execs_in(&bin_dir())
+    }
+
+    pub fn check_execs_in(&self, bin: &dyn AsRef<Path>) -> Result<Vec<PathBuf>, AetherError> {
+        let bin = bin.as_ref();
         let execs = self.list_execs()?;
 
         let mut checked = vec![];
         let mut missing = vec![];
 
         for exec in execs {
-            let path = bin_dir().join(exec.file_name());
+            let path = bin.join(exec.file_name());
             if Path::exists(&path) {
                 checked.push(path);
             } else {
@@ -689,45 +1341,36 @@ impl Pkg {
     }
 
     pub fn symlink_execs(&self) -> Result<Vec<PathBuf>, AetherError> {
-        let files = self.list_execs()?;
-        let mut symlinked: Vec<PathBuf> = vec![];
-
-        for file in files {
-            let path = bin_dir().join(file.file_name());
-
-            fs::symlink(file.path(), &path).map_err(|source| AetherError::LinkError {
-                from: file.path(),
-                to: path.clone(),
-                source,
-            })?;
-
-            symlinked.push(path);
-        }
-
-        Ok(symlinked)
+        self.symlink_execs_to(&bin_dir())
     }
 
-    fn remove_files(&self) -> Result<Vec<PathBuf>, AetherError> {
-        let files = &self.files;
+    pub fn symlink_execs_to(&self, bin: &dyn AsRef<Path>) -> Result<Vec<PathBuf>, AetherError> {
+        self.export_execs_to(bin, ExportMode::Symlink)
+    }
 
-        println!("files: {:#?}", files);
+    /// delete the package as installed at `dir`: every file, link and
+    /// directory under it, deepest first, and then `dir` itself
+    fn remove_files_from(&self, dir: &Path) -> Result<Vec<PathBuf>, AetherError> {
+        // a directory sorts before everything in it, so going backwards
+        // empties each one before it's removed
+        let mut entries = walk_dir(dir)?;
+        entries.insert(0, dir.into());
 
         let mut removed = vec![];
-
-        for file in files {
-            let name = match file.file_name() {
-                Some(name) => name,
-                None => return Err(AetherError::Unknown),
-            };
-
-            let path = pkg_dir().join(file);
-
-            println!("removing: {}", &path.display());
-
-            std::fs::remove_file(&path).map_err(|source| AetherError::WriteError {
+        for path in entries.into_iter().rev() {
+            let write_error = |source| AetherError::WriteError {
                 file: path.clone(),
                 source,
-            })?;
+            };
+
+            if std::fs::symlink_metadata(&path)
+                .map_err(write_error)?
+                .is_dir()
+            {
+                std::fs::remove_dir(&path).map_err(write_error)?;
+            } else {
+                std::fs::remove_file(&path).map_err(write_error)?;
+            }
 
             removed.push(path);
         }
@@ -736,11 +1379,23 @@ impl Pkg {
     }
 
     pub fn unlink_execs(&self) -> Result<Vec<PathBuf>, AetherError> {
+        self.unlink_execs_from(&bin_dir())
+    }
+
+    /// remove the entries in `bin` exporting the package's executables,
+    /// leaving those of other packages alone
+    pub fn unlink_execs_from(&self, bin: &dyn AsRef<Path>) -> Result<Vec<PathBuf>, AetherError> {
+        let bin = bin.as_ref();
         let files = self.list_execs()?;
         let mut unlinked: Vec<PathBuf> = vec![];
 
         for file in files {
-            let path = bin_dir().join(file.file_name());
+            let path = bin.join(file.file_name());
+
+            // another package may provide the executable instead
+            if export::exported_exec(&path) != Some(file.path()) {
+                continue;
+            }
 
             std::fs::remove_file(&path).map_err(|source| AetherError::WriteError {
                 file: path.clone(),
@@ -779,9 +1434,33 @@ impl fmt::Display for Pkg {
 //     }
 // }
 
+/// what removing a package from a [`PkgList`] deleted
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RemovalReport {
+    pub pkg: String,
+    /// where the package was installed
+    pub path: PathBuf,
+    /// the entries removed from the bin directory
+    pub unexported: Vec<PathBuf>,
+    /// every file, link and directory deleted, deepest first
+    pub files: Vec<PathBuf>,
+    /// the package directory, if it was left for rolling back to until
+    /// [`Environment::delete_generations`] finds no generation needs it
+    pub kept: Option<PathBuf>,
+}
+
 #[derive(Clone, Debug)]
 pub struct PkgList {
     pkgs: Vec<Pkg>,
+    env: Environment,
+    architectures: Architectures,
+    relocation: Relocation,
+    exports: Exports,
+    scriptlet_policy: ScriptletPolicy,
+    scriptlet_output: Vec<ScriptletOutput>,
+    hook_output: Vec<HookOutput>,
+    relocation_output: Vec<RelocationReport>,
 }
 
 impl PkgList {
@@ -795,6 +1474,8 @@ impl PkgList {
         Ok(result)
     }
 
+    /// return the executable names more than one package provides, which
+    /// are exported as alternatives
     pub fn exec_conflicts(&self) -> Result<Option<Vec<PathBuf>>, AetherError> {
         let pkgs = &self.pkgs;
 
@@ -820,17 +1501,19 @@ impl PkgList {
     }
 
     pub fn install(&mut self, pkg: Pkg) -> Result<u64, AetherError> {
-        let path = &pkg_dir();
-        let to = &path.join(&pkg.get_refstr());
+        let path = self.env.pkg_dir();
+        let to = &path.join(pkg.get_refstr());
 
         self.install_to(pkg, to)
     }
 
     pub fn install_to(&mut self, pkg: Pkg, path: &dyn AsRef<Path>) -> Result<u64, AetherError> {
+        self.architectures.check(&pkg)?;
+
         if self
             .pkgs()
             .iter()
-            .any(|x| (x.get_refstr() == pkg.get_refstr()))
+            .any(|x| x.get_refstr() == pkg.get_refstr())
         {
             return Err(AetherError::AlreadyExists(format!(
                 "{} already exists in PkgList",
@@ -838,33 +1521,74 @@ impl PkgList {
             )));
         }
 
-        self.pkgs.push(pkg.clone());
+        let ver = pkg.pkginfo.pkgver.clone();
+        self.run_hooks(HookWhen::PreTransaction, &[(HookOperation::Install, &pkg)])?;
+        self.run_scriptlet(&pkg, ScriptletStage::PreInstall, &[&ver], &pkg.path)?;
 
-        if let Some(conflicts) = self.exec_conflicts()? {
-            return Err(AetherError::AlreadyExists(format!(
-                "conflicts found in /bin or /usr/bin: {:?}",
-                conflicts
-            )));
-        }
+        let (result, installed) = self.install_files(&pkg, path)?;
+        self.env.record_generation(&self.pkgs)?;
+
+        self.run_scriptlet(
+            &installed,
+            ScriptletStage::PostInstall,
+            &[&ver],
+            &installed.path,
+        )?;
+        self.run_hooks(
+            HookWhen::PostTransaction,
+            &[(HookOperation::Install, &installed)],
+        )?;
 
-        let from: &Path = pkg.path.as_ref();
+        Ok(result)
+    }
+
+    /// copy a package in, relocate it and export its executables, without
+    /// running scriptlets or recording a generation
+    fn install_files(
+        &mut self,
+        pkg: &Pkg,
+        path: &dyn AsRef<Path>,
+    ) -> Result<(u64, Pkg), AetherError> {
         let to: &Path = path.as_ref();
-        let mut options = dir::CopyOptions::new();
-        options.content_only = true;
+        self.check_foreign_exports(pkg)?;
 
-        let result = dir::copy(from, to, &options).map_err(|source| AetherError::CopyError {
-            from: from.into(),
-            to: to.into(),
-            source,
-        })?;
+        let imported = Store::new()
+            .import(pkg, &to)
+            .and_then(|bytes| self.relocate_installed(pkg, to).map(|()| bytes));
+        let result = match imported {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                // don't leave a half-installed package behind
+                let _ = std::fs::remove_dir_all(to);
+                return Err(err);
+            }
+        };
 
-        pkg.symlink_execs()?;
+        let installed = Pkg::from_dir(&to)?;
+        self.pkgs.push(installed.clone());
+        self.export_alternatives(&installed.list_execs()?)?;
 
-        Ok(result)
+        Ok((result, installed))
     }
 
     pub fn new() -> Result<Self, AetherError> {
-        Self::new_from(&pkg_dir())
+        Self::new_in(&Environment::global())
+    }
+
+    /// read the packages installed in `env`, installing and removing there;
+    /// once `env` has generations, only those in the active one count
+    pub fn new_in(env: &Environment) -> Result<Self, AetherError> {
+        let mut pkglist = Self::new_from(&env.pkg_dir())?;
+        pkglist.env = env.clone();
+
+        if let Some(current) = env.current_generation()? {
+            let generation = env.generation(current)?;
+            pkglist
+                .pkgs
+                .retain(|pkg| generation.pkgs.contains(&pkg.get_refstr()));
+        }
+
+        Ok(pkglist)
     }
 
     pub fn new_from(path: &dyn AsRef<Path>) -> Result<Self, AetherError> {
@@ -885,7 +1609,108 @@ impl PkgList {
             }
         }
 
-        Ok(Self { pkgs })
+        Ok(Self {
+            pkgs,
+            env: Environment::global(),
+            architectures: Architectures::host(),
+            relocation: Relocation::default(),
+            exports: Exports::default(),
+            scriptlet_policy: ScriptletPolicy::default(),
+            scriptlet_output: vec![],
+            hook_output: vec![],
+            relocation_output: vec![],
+        })
+    }
+
+    #[must_use]
+    pub fn env(&self) -> &Environment {
+        &self.env
+    }
+
+    #[must_use]
+    pub fn scriptlet_policy(&self) -> ScriptletPolicy {
+        self.scriptlet_policy
+    }
+
+    /// set how scriptlets and hooks are run
+    pub fn set_scriptlet_policy(&mut self, policy: ScriptletPolicy) {
+        self.scriptlet_policy = policy;
+    }
+
+    /// the output of every scriptlet run by this `PkgList` so far
+    #[must_use]
+    pub fn scriptlet_output(&self) -> &Vec<ScriptletOutput> {
+        &self.scriptlet_output
+    }
+
+    /// the output of every hook run by this `PkgList` so far, including
+    /// failed ones that didn't abort the transaction
+    #[must_use]
+    pub fn hook_output(&self) -> &Vec<HookOutput> {
+        &self.hook_output
+    }
+
+    /// run the hooks for `when` that `changes` trigger
+    fn run_hooks(
+        &mut self,
+        when: HookWhen,
+        changes: &[(HookOperation, &Pkg)],
+    ) -> Result<(), AetherError> {
+        if self.scriptlet_policy == ScriptletPolicy::Skip {
+            return Ok(());
+        }
+
+        let hooks = Hook::load(&self.env.hook_dirs(&self.pkgs))?;
+        for hook in hooks.iter().filter(|hook| hook.when == when) {
+            let targets = hook.matches(changes);
+            if targets.is_empty() {
+                continue;
+            }
+
+            if let Err(err) = hook.check_depends(&self.pkgs) {
+                if hook.abort_on_fail {
+                    return Err(err);
+                }
+
+                self.hook_output.push(HookOutput {
+                    hook: hook.name.clone(),
+                    status: None,
+                    stdout: String::new(),
+                    stderr: err.to_string(),
+                    skipped: None,
+                });
+                continue;
+            }
+
+            let output = hook.run(&targets, &self.env, self.scriptlet_policy)?;
+            self.hook_output.extend(output);
+        }
+
+        Ok(())
+    }
+
+    fn run_scriptlet(
+        &mut self,
+        pkg: &Pkg,
+        stage: ScriptletStage,
+        versions: &[&str],
+        dir: &dyn AsRef<Path>,
+    ) -> Result<(), AetherError> {
+        let scriptlet = match &pkg.scriptlet {
+            Some(scriptlet) => scriptlet,
+            None => return Ok(()),
+        };
+
+        let output = scriptlet.run(
+            &pkg.get_refstr(),
+            stage,
+            versions,
+            dir,
+            self.scriptlet_policy,
+        )?;
+        self.scriptlet_output.extend(output);
+
+        Ok(())
     }
 
     pub fn pkg_exists(&self, pkg: &Pkg) -> bool {
@@ -898,48 +1723,205 @@ impl PkgList {
         &self.pkgs
     }
 
-    pub fn remove(&mut self, pkg: &Pkg) -> Result<(), AetherError> {
-        let path = &pkg_dir();
+    pub fn remove(&mut self, pkg: &Pkg) -> Result<RemovalReport, AetherError> {
+        let path = self.env.pkg_dir();
         let from = &path.join(pkg.get_refstr());
 
         self.remove_from(pkg, from)
     }
 
-    pub fn remove_from(&mut self, pkg: &Pkg, _path: &dyn AsRef<Path>) -> Result<(), AetherError> {
+    /**
+    remove `pkg`, installed at `path`, running its remove scriptlets and
+    recording a generation
+
+    The package's executables are unexported. If it's installed in the
+    environment's package directory and a generation lists it, its directory
+    is kept for rolling back to, the same way upgrading keeps the old
+    version's, until [`Environment::delete_generations`] finds no generation
+    needs it; otherwise it's deleted.
+    */
+    pub fn remove_from(
+        &mut self,
+        pkg: &Pkg,
+        path: &dyn AsRef<Path>,
+    ) -> Result<RemovalReport, AetherError> {
         let pkg = pkg.clone();
 
         if !self
             .pkgs()
             .iter()
-            .any(|x| (x.get_refstr() == pkg.get_refstr()))
+            .any(|x| x.get_refstr() == pkg.get_refstr())
         {
             let name = pkg.pkginfo.pkgname;
             let ver = pkg.pkginfo.pkgver;
             return Err(AetherError::MissingPkg { name, ver });
         }
 
-        match pkg.check_execs() {
-            Ok(_) => {
-                pkg.unlink_execs()?;
-            }
-            Err(AetherError::MissingExec(_)) => {
-                // TODO: figure out how to communicate that a package is missing some executables
-            }
-            Err(err) => return Err(err),
+        let ver = pkg.pkginfo.pkgver.clone();
+        self.run_hooks(HookWhen::PreTransaction, &[(HookOperation::Remove, &pkg)])?;
+        self.run_scriptlet(&pkg, ScriptletStage::PreRemove, &[&ver], &pkg.path)?;
+
+        let execs = pkg.list_execs()?;
+        let refstr = pkg.get_refstr();
+        let path = path.as_ref();
+        let keep = path == self.env.pkg_dir().join(&refstr)
+            && self
+                .env
+                .list_generations()?
+                .iter()
+                .any(|generation| generation.pkgs.contains(&refstr));
+
+        let mut report = self.remove_files(&pkg, Some(path).filter(|_| !keep))?;
+        if keep {
+            report.path = path.into();
+            report.kept = Some(path.into());
         }
+        self.export_alternatives(&execs)?;
+        self.env.record_generation(&self.pkgs)?;
+        self.env
+            .set_install_reason(&pkg.pkginfo.pkgname, InstallReason::Explicit)?;
 
-        match pkg.check_files() {
-            Ok(_) => {
-                println!("removing files");
-                pkg.remove_files()?;
+        let pkg_dir = self.env.pkg_dir().to_path_buf();
+        self.run_scriptlet(&pkg, ScriptletStage::PostRemove, &[&ver], &pkg_dir)?;
+        self.run_hooks(HookWhen::PostTransaction, &[(HookOperation::Remove, &pkg)])?;
+
+        Ok(report)
+    }
+
+    /// unexport a package's executables and delete its directory at `path`
+    /// if given, without running scriptlets or recording a generation
+    fn remove_files(
+        &mut self,
+        pkg: &Pkg,
+        path: Option<&Path>,
+    ) -> Result<RemovalReport, AetherError> {
+        let mut report = RemovalReport {
+            pkg: pkg.get_refstr(),
+            path: path.unwrap_or(&pkg.path).into(),
+            unexported: vec![],
+            files: vec![],
+            kept: None,
+        };
+
+        for exec in pkg.list_execs()? {
+            let name = exec.file_name().to_string_lossy().to_string();
+            let link = self.env.bin_dir().join(&name);
+
+            // another package may provide the executable instead
+            if export::exported_exec(&link) == Some(exec.path()) {
+                self.env.unexport(&name)?;
+                report.unexported.push(link);
             }
-            Err(AetherError::MissingFile(_)) => {
-                // TODO: figure out how to communicate that a package is missing some files
+        }
+
+        // a package directory that's already gone has nothing left to delete,
+        // but anything else has to be one before it's deleted
+        if let Some(path) = path.filter(|path| std::fs::symlink_metadata(path).is_ok()) {
+            Pkg::is_valid_dir(&path)?;
+            report.files = pkg.remove_files_from(path)?;
+        }
+
+        self.pkgs.retain(|x| x.get_refstr() != pkg.get_refstr());
+
+        Ok(report)
+    }
+
+    /// replace the installed package `old` with `new`, running the upgrade
+    /// scriptlets of `new` instead of the install and remove ones
+    pub fn upgrade(&mut self, old: &Pkg, new: Pkg) -> Result<u64, AetherError> {
+        let path = self.env.pkg_dir();
+        let to = &path.join(new.get_refstr());
+
+        self.upgrade_to(old, new, to)
+    }
+
+    pub fn upgrade_to(
+        &mut self,
+        old: &Pkg,
+        new: Pkg,
+        path: &dyn AsRef<Path>,
+    ) -> Result<u64, AetherError> {
+        if !self.pkg_exists(old) {
+            let name = old.pkginfo.pkgname.clone();
+            let ver = old.pkginfo.pkgver.clone();
+            return Err(AetherError::MissingPkg { name, ver });
+        }
+        self.architectures.check(&new)?;
+        self.check_soname_upgrade(old, &new)?;
+        self.check_foreign_exports(&new)?;
+
+        let new_ver = new.pkginfo.pkgver.clone();
+        let old_ver = old.pkginfo.pkgver.clone();
+        let versions = [new_ver.as_str(), old_ver.as_str()];
+
+        self.run_hooks(HookWhen::PreTransaction, &[(HookOperation::Upgrade, &new)])?;
+        self.run_scriptlet(&new, ScriptletStage::PreUpgrade, &versions, &new.path)?;
+
+        // the old version's directory stays for rolling back to, until
+        // Environment::delete_generations finds no generation needs it
+        let old_execs = old.list_execs()?;
+        let index = self
+            .pkgs
+            .iter()
+            .position(|x| x.get_refstr() == old.get_refstr())
+            .unwrap();
+        let old = self.pkgs[index].clone();
+        let path = path.as_ref();
+        let existed = std::fs::symlink_metadata(path).is_ok();
+        self.remove_files(&old, None)?;
+
+        let switched = self.install_files(&new, &path).and_then(|installed| {
+            // commands only the old version provided fall to other providers
+            self.export_alternatives(&old_execs)?;
+            self.env.record_generation(&self.pkgs)?;
+
+            Ok(installed)
+        });
+        let (result, installed) = match switched {
+            Ok(switched) => switched,
+            Err(err) => {
+                // an error restoring the old version would hide the cause
+                let _ = self.restore_upgraded(&old, index, path, existed);
+                return Err(err);
             }
-            Err(err) => return Err(err),
+        };
+
+        self.run_scriptlet(
+            &installed,
+            ScriptletStage::PostUpgrade,
+            &versions,
+            &installed.path,
+        )?;
+        self.run_hooks(
+            HookWhen::PostTransaction,
+            &[(HookOperation::Upgrade, &installed)],
+        )?;
+
+        Ok(result)
+    }
+
+    /// undo a failed upgrade from `old`, which was at `index` in the list:
+    /// unexport and delete the new version installed at `path` unless
+    /// something was there before, and make `old` installed again
+    fn restore_upgraded(
+        &mut self,
+        old: &Pkg,
+        index: usize,
+        path: &Path,
+        existed: bool,
+    ) -> Result<(), AetherError> {
+        if let Some(new) = self.pkgs.iter().find(|x| x.path == path).cloned() {
+            self.remove_files(&new, None)?;
+        }
+        if !existed && std::fs::symlink_metadata(path).is_ok() {
+            std::fs::remove_dir_all(path).map_err(|source| AetherError::WriteError {
+                file: path.into(),
+                source,
+            })?;
         }
 
-        Ok(())
+        self.pkgs.insert(index.min(self.pkgs.len()), old.clone());
+        self.export_alternatives(&old.list_execs()?)
     }
 
     pub fn show_all(&mut self) {
@@ -973,7 +1955,7 @@ impl IntoIterator for PkgList {
     }
 }
 
-impl<'a> IntoIterator for &'a PkgList {
+impl IntoIterator for &PkgList {
     type Item = Pkg;
     type IntoIter = <Vec<Self::Item> as IntoIterator>::IntoIter;
 
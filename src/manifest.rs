@@ -0,0 +1,270 @@
+use crate::{AetherError, Architectures, Constraint, Depend, Repo, RepoPkg};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::{read_to_string, write};
+use std::path::{Path, PathBuf};
+
+/// file name of a project's environment manifest
+pub const MANIFEST_FILE: &str = "aether.toml";
+
+/// file name of a project's lockfile
+pub const LOCK_FILE: &str = "aether.lock";
+
+/// a sync repository as listed in a manifest or lockfile
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RepoSource {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+impl RepoSource {
+    /// load the repository database this entry points to
+    pub fn load(&self) -> Result<Repo, AetherError> {
+        Repo::load(&self.name, &self.path)
+    }
+}
+
+/**
+Contains data parsed from a project's aether.toml
+
+```toml
+arch = ["x86_64_v3"]
+
+[[repo]]
+name = "core"
+path = "/srv/aether/core"
+
+[packages]
+hello = ">=1.0"
+bash = "*"
+```
+
+Repositories are searched in the order they're listed. `arch` lists the
+architectures to accept packages for, best first, and defaults to the host's.
+
+# Public methods:
+```text
+// parse a manifest file
+Manifest::parse() : pub fn parse(file: &dyn AsRef<Path>) -> Result<Manifest>
+
+// resolve the manifest against its repositories into a lockfile
+Manifest::resolve() : pub fn resolve(&self) -> Result<Lockfile>
+```
+*/
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub arch: Vec<String>,
+    #[serde(default, rename = "repo")]
+    pub repos: Vec<RepoSource>,
+    #[serde(default)]
+    pub packages: BTreeMap<String, String>,
+}
+
+impl Manifest {
+    /// parse a manifest file
+    pub fn parse(file: &dyn AsRef<Path>) -> Result<Manifest, AetherError> {
+        let file = file.as_ref();
+
+        let raw = read_to_string(file).map_err(|source| AetherError::ReadError {
+            file: file.into(),
+            source,
+        })?;
+
+        toml::from_str(&raw).map_err(|source| AetherError::TomlError {
+            file: file.into(),
+            source,
+        })
+    }
+
+    /// parse the aether.toml in `dir`
+    pub fn from_dir(dir: &dyn AsRef<Path>) -> Result<Manifest, AetherError> {
+        Self::parse(&dir.as_ref().join(MANIFEST_FILE))
+    }
+
+    /// return the requested packages as dependencies
+    pub fn depends(&self) -> Result<Vec<Depend>, AetherError> {
+        self.packages
+            .iter()
+            .map(|(name, constraint)| {
+                Ok(Depend {
+                    name: name.clone(),
+                    constraint: Constraint::parse(constraint)?,
+                })
+            })
+            .collect()
+    }
+
+    /// return the architectures to accept packages for
+    #[must_use]
+    pub fn architectures(&self) -> Architectures {
+        if self.arch.is_empty() {
+            Architectures::host()
+        } else {
+            Architectures::new(&self.arch)
+        }
+    }
+
+    /// resolve the manifest against its repositories into a lockfile
+    pub fn resolve(&self) -> Result<Lockfile, AetherError> {
+        let repos = self
+            .repos
+            .iter()
+            .map(RepoSource::load)
+            .collect::<Result<Vec<Repo>, AetherError>>()?;
+
+        self.resolve_with(&repos)
+    }
+
+    /// resolve the manifest against already loaded repositories, pulling in
+    /// every package's dependencies
+    pub fn resolve_with(&self, repos: &[Repo]) -> Result<Lockfile, AetherError> {
+        let arches = self.architectures();
+        let mut resolved: Vec<RepoPkg> = vec![];
+        let mut queue: VecDeque<Depend> = self.depends()?.into();
+
+        while let Some(depend) = queue.pop_front() {
+            if resolved.iter().any(|pkg| pkg.satisfies(&depend)) {
+                continue;
+            }
+
+            // a package with the right name but the wrong version is a
+            // conflict rather than something to install alongside
+            if let Some(pkg) = resolved
+                .iter()
+                .find(|pkg| pkg.pkginfo.pkgname == depend.name)
+            {
+                return Err(AetherError::UnsatisfiedDepend {
+                    depend: depend.to_string(),
+                    note: format!("{} is already locked", pkg.get_refstr()),
+                });
+            }
+
+            let pkg = repos
+                .iter()
+                .find_map(|repo| repo.find_for(&depend, &arches))
+                .ok_or_else(|| AetherError::UnsatisfiedDepend {
+                    depend: depend.to_string(),
+                    note: format!(
+                        "no repository provides it for '{}'",
+                        arches.accepted().join(" ")
+                    ),
+                })?;
+
+            for dep in &pkg.pkginfo.depend {
+                queue.push_back(Depend::parse(dep));
+            }
+
+            resolved.push(pkg.clone());
+        }
+
+        let mut packages: Vec<LockedPkg> = resolved
+            .into_iter()
+            .map(|pkg| LockedPkg {
+                name: pkg.pkginfo.pkgname,
+                version: pkg.pkginfo.pkgver,
+                repo: pkg.repo,
+                filename: pkg.filename,
+                sha256: pkg.sha256sum,
+            })
+            .collect();
+        packages.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(Lockfile {
+            arch: self.arch.clone(),
+            repos: self.repos.clone(),
+            packages,
+        })
+    }
+}
+
+/// a single exactly pinned package in a lockfile
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockedPkg {
+    pub name: String,
+    pub version: String,
+    pub repo: String,
+    pub filename: String,
+    pub sha256: String,
+}
+
+impl LockedPkg {
+    #[must_use]
+    pub fn get_refstr(&self) -> String {
+        format!("{}-{}", self.name, self.version)
+    }
+}
+
+/**
+Contains data parsed from a project's aether.lock, pinning the exact version
+and sha256 sum of every package an environment needs
+
+# Public methods:
+```text
+// parse a lockfile
+Lockfile::parse() : pub fn parse(file: &dyn AsRef<Path>) -> Result<Lockfile>
+
+// write the lockfile to disk
+Lockfile::write_to() : pub fn write_to(&self, file: &dyn AsRef<Path>) -> Result<()>
+```
+*/
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lockfile {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub arch: Vec<String>,
+    #[serde(default, rename = "repo")]
+    pub repos: Vec<RepoSource>,
+    #[serde(default, rename = "package")]
+    pub packages: Vec<LockedPkg>,
+}
+
+impl Lockfile {
+    /// parse a lockfile
+    pub fn parse(file: &dyn AsRef<Path>) -> Result<Lockfile, AetherError> {
+        let file = file.as_ref();
+
+        let raw = read_to_string(file).map_err(|source| AetherError::ReadError {
+            file: file.into(),
+            source,
+        })?;
+
+        toml::from_str(&raw).map_err(|source| AetherError::TomlError {
+            file: file.into(),
+            source,
+        })
+    }
+
+    /// parse the aether.lock in `dir`
+    pub fn from_dir(dir: &dyn AsRef<Path>) -> Result<Lockfile, AetherError> {
+        Self::parse(&dir.as_ref().join(LOCK_FILE))
+    }
+
+    /// write the lockfile to disk
+    pub fn write_to(&self, file: &dyn AsRef<Path>) -> Result<(), AetherError> {
+        let file = file.as_ref();
+
+        // serializing plain strings and tables can't fail
+        let raw = toml::to_string(self).unwrap();
+
+        write(file, raw).map_err(|source| AetherError::WriteError {
+            file: file.into(),
+            source,
+        })
+    }
+
+    /// return the architectures the lockfile was resolved for
+    #[must_use]
+    pub fn architectures(&self) -> Architectures {
+        if self.arch.is_empty() {
+            Architectures::host()
+        } else {
+            Architectures::new(&self.arch)
+        }
+    }
+
+    /// return the locked package named `name`
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&LockedPkg> {
+        self.packages.iter().find(|pkg| pkg.name == name)
+    }
+}
@@ -0,0 +1,249 @@
+use crate::{AetherError, Depend, Environment, Pkg, PkgInfo, PkgList, Soname};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{read_to_string, write};
+use std::path::{Path, PathBuf};
+
+/// file in an environment's db directory recording why packages were installed
+const REASONS_FILE: &str = "reasons.toml";
+
+/**
+A parsed optional dependency such as `bash-completion: for tab completion`
+
+# Public methods:
+```text
+// parse an optdepend string
+OptDepend::parse() : pub fn parse(s: &str) -> OptDepend
+```
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OptDepend {
+    pub depend: Depend,
+    /// what installing the dependency enables, which may be empty
+    pub reason: String,
+}
+
+impl OptDepend {
+    /// parse an optdepend string; the reason follows the first `: `, since a
+    /// version constraint may contain an epoch like `foo>=1:2.0`
+    #[must_use]
+    pub fn parse(s: &str) -> OptDepend {
+        let s = s.trim();
+
+        let (depend, reason) = match s.split_once(": ") {
+            Some((depend, reason)) => (depend, reason.trim()),
+            None => (s.strip_suffix(':').unwrap_or(s), ""),
+        };
+
+        OptDepend {
+            depend: Depend::parse(depend),
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for OptDepend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.reason.is_empty() {
+            write!(f, "{}", self.depend)
+        } else {
+            write!(f, "{}: {}", self.depend, self.reason)
+        }
+    }
+}
+
+impl PkgInfo {
+    /// return the package's `optdepend` entries, parsed
+    #[must_use]
+    pub fn optdepends(&self) -> Vec<OptDepend> {
+        self.optdepend.iter().map(|s| OptDepend::parse(s)).collect()
+    }
+}
+
+/// why a package is in an environment
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum InstallReason {
+    /// asked for by name
+    #[default]
+    Explicit,
+    /// installed for the package `of`, and an orphan once nothing installed
+    /// depends on it anymore
+    Dependency { of: String },
+}
+
+/// the contents of [`REASONS_FILE`]; explicitly installed packages aren't
+/// listed
+#[derive(Default, Serialize, Deserialize)]
+struct Reasons {
+    /// package name -> the package it was installed for
+    #[serde(default)]
+    dependencies: BTreeMap<String, String>,
+}
+
+impl Environment {
+    fn reasons_file(&self) -> PathBuf {
+        self.db_dir().join(REASONS_FILE)
+    }
+
+    fn read_reasons(&self) -> Result<Reasons, AetherError> {
+        let file = self.reasons_file();
+
+        match read_to_string(&file) {
+            Ok(raw) => {
+                toml::from_str(&raw).map_err(|source| AetherError::TomlError { file, source })
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Reasons::default()),
+            Err(source) => Err(AetherError::ReadError { file, source }),
+        }
+    }
+
+    /// return why the package `name` was installed
+    pub fn install_reason(&self, name: &str) -> Result<InstallReason, AetherError> {
+        let reason = match self.read_reasons()?.dependencies.remove(name) {
+            Some(of) => InstallReason::Dependency { of },
+            None => InstallReason::Explicit,
+        };
+
+        Ok(reason)
+    }
+
+    /// record why the package `name` was installed
+    pub fn set_install_reason(&self, name: &str, reason: InstallReason) -> Result<(), AetherError> {
+        let mut reasons = self.read_reasons()?;
+
+        let old = match reason {
+            InstallReason::Explicit => reasons.dependencies.remove(name),
+            InstallReason::Dependency { of } => reasons.dependencies.insert(name.into(), of),
+        };
+        if old.as_ref() == reasons.dependencies.get(name) {
+            return Ok(());
+        }
+
+        let file = self.reasons_file();
+        // a table of strings always serializes
+        let raw = toml::to_string(&reasons).unwrap();
+        write(&file, raw).map_err(|source| AetherError::WriteError { file, source })
+    }
+}
+
+impl PkgList {
+    /// return whether an installed package satisfies `depend`, by name,
+    /// through its provides, or for a soname, by containing the library
+    #[must_use]
+    pub fn is_satisfied(&self, depend: &Depend) -> bool {
+        let declared = self.pkgs().iter().any(|pkg| {
+            let info = &pkg.pkginfo;
+
+            depend.satisfied_by(&info.pkgname, &info.pkgver)
+                || depend.satisfied_by_provides(&info.provides)
+        });
+
+        declared
+            || Soname::parse(&depend.to_string()).is_some_and(|soname| {
+                self.soname_providers(&soname)
+                    .is_ok_and(|providers| !providers.is_empty())
+            })
+    }
+
+    /// return the optional dependencies of `pkg` that nothing installed
+    /// satisfies, i.e. the features it's missing
+    #[must_use]
+    pub fn missing_optdepends(&self, pkg: &Pkg) -> Vec<OptDepend> {
+        pkg.pkginfo
+            .optdepends()
+            .into_iter()
+            .filter(|optdepend| !self.is_satisfied(&optdepend.depend))
+            .collect()
+    }
+
+    /// return the installed packages that would gain a feature if the
+    /// package described by `info` were installed, with the optdepend it
+    /// satisfies
+    #[must_use]
+    pub fn enabled_by(&self, info: &PkgInfo) -> Vec<(&Pkg, OptDepend)> {
+        let mut enabled = vec![];
+
+        for pkg in self.pkgs() {
+            for optdepend in pkg.pkginfo.optdepends() {
+                let depend = &optdepend.depend;
+                if depend.satisfied_by(&info.pkgname, &info.pkgver)
+                    || depend.satisfied_by_provides(&info.provides)
+                {
+                    enabled.push((pkg, optdepend));
+                }
+            }
+        }
+
+        enabled
+    }
+
+    /// install `pkg` as a dependency of the installed package `of`, so it
+    /// becomes an orphan once nothing needs it anymore
+    pub fn install_as_dependency(&mut self, pkg: Pkg, of: &str) -> Result<u64, AetherError> {
+        let to = self.env().pkg_dir().join(pkg.get_refstr());
+
+        self.install_as_dependency_to(pkg, of, &to)
+    }
+
+    pub fn install_as_dependency_to(
+        &mut self,
+        pkg: Pkg,
+        of: &str,
+        path: &dyn AsRef<Path>,
+    ) -> Result<u64, AetherError> {
+        if !self
+            .pkgs()
+            .iter()
+            .any(|parent| parent.pkginfo.pkgname == of)
+        {
+            return Err(AetherError::MissingPkg {
+                name: of.into(),
+                ver: "*".into(),
+            });
+        }
+
+        let name = pkg.pkginfo.pkgname.clone();
+        let bytes = self.install_to(pkg, path)?;
+        self.env()
+            .set_install_reason(&name, InstallReason::Dependency { of: of.into() })?;
+
+        Ok(bytes)
+    }
+
+    /**
+    return the packages installed as dependencies that no installed package
+    depends on or optionally depends on anymore
+
+    Removing an orphan can make others orphans in turn.
+    */
+    pub fn orphans(&self) -> Result<Vec<&Pkg>, AetherError> {
+        let reasons = self.env().read_reasons()?;
+
+        let needed = |pkg: &Pkg| {
+            self.pkgs().iter().any(|other| {
+                let info = &other.pkginfo;
+                let depends = info.depend.iter().map(|depend| Depend::parse(depend));
+                let optdepends = info.optdepends().into_iter().map(|opt| opt.depend);
+
+                other.get_refstr() != pkg.get_refstr()
+                    && depends.chain(optdepends).any(|depend| {
+                        depend.satisfied_by(&pkg.pkginfo.pkgname, &pkg.pkginfo.pkgver)
+                            || depend.satisfied_by_provides(&pkg.pkginfo.provides)
+                    })
+            })
+        };
+
+        let orphans = self
+            .pkgs()
+            .iter()
+            .filter(|pkg| reasons.dependencies.contains_key(&pkg.pkginfo.pkgname))
+            .filter(|pkg| !needed(pkg))
+            .collect();
+
+        Ok(orphans)
+    }
+}
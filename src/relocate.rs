@@ -0,0 +1,423 @@
+use crate::{sha256sum, AetherError, ElfInfo, Pkg, PkgInfo, PkgList};
+use regex::{Captures, Regex};
+use scan_dir::ScanDir;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs::{
+    copy, metadata, read, read_to_string, rename, set_permissions, write, File, Permissions,
+};
+use std::io::Read;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// file in an installed package's directory listing the files relocated
+pub const RELOCATED_FILE: &str = ".RELOCATED";
+
+/// why a file was considered for relocation
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RelocationKind {
+    /// the interpreter line of an executable script
+    Shebang,
+    /// a pkg-config `.pc` file
+    PkgConfig,
+    /// a libtool `.la` archive
+    Libtool,
+    /// a text file under etc/
+    Config,
+    /// the `DT_RUNPATH` of an ELF file
+    Runpath,
+}
+
+/// a file whose `/usr` paths were rewritten, relative to the package root
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RelocatedFile {
+    pub path: PathBuf,
+    pub kind: RelocationKind,
+    /// how many paths were rewritten, or added to the runpath
+    pub replaced: usize,
+    /// the sha256 of the relocated contents, which differs from the .MTREE's
+    pub sha256: String,
+}
+
+/// the files [`PkgList`] relocated while installing a package
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RelocationReport {
+    pub pkg: String,
+    /// what `/usr` was rewritten to start with
+    pub prefix: PathBuf,
+    pub files: Vec<RelocatedFile>,
+}
+
+/// the contents of [`RELOCATED_FILE`]
+#[derive(Default, Serialize, Deserialize)]
+struct Relocated {
+    #[serde(default, rename = "file")]
+    files: Vec<RelocatedFile>,
+}
+
+/**
+Whether to rewrite hard-coded `/usr` paths when installing packages
+
+Packages are installed under their own directory rather than /, so scripts,
+pkg-config and libtool files, and config files that name `/usr/...` point at
+the host's files instead of the package's. When enabled, such paths are
+rewritten to point into the package, as long as the package actually has the
+file they name; paths into other packages are left alone. ELF files that
+need libraries from the package's usr/lib can also have their `DT_RUNPATH`
+pointed there with `patchelf`, so they work without `LD_LIBRARY_PATH`.
+Relocation is off by default.
+
+# Public methods:
+```text
+// relocate every package
+Relocation::enabled() : pub fn enabled() -> Relocation
+
+// also point the runpath of ELF files at the package's usr/lib
+Relocation::patch_runpath() : pub fn patch_runpath(&mut self)
+
+// never relocate
+Relocation::disabled() : pub fn disabled() -> Relocation
+
+// leave the package `name` alone
+Relocation::skip() : pub fn skip(&mut self, name: &str)
+
+// return whether a package is relocated
+Relocation::applies_to() : pub fn applies_to(&self, info: &PkgInfo) -> bool
+```
+*/
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Relocation {
+    paths: bool,
+    runpath: bool,
+    skip: BTreeSet<String>,
+}
+
+impl Relocation {
+    /// relocate every package
+    #[must_use]
+    pub fn enabled() -> Relocation {
+        Relocation {
+            paths: true,
+            ..Relocation::default()
+        }
+    }
+
+    /// never relocate
+    #[must_use]
+    pub fn disabled() -> Relocation {
+        Relocation::default()
+    }
+
+    /// also point the runpath of ELF files at the package's usr/lib, which
+    /// needs `patchelf`
+    pub fn patch_runpath(&mut self) {
+        self.runpath = true;
+    }
+
+    /// leave the package `name` alone
+    pub fn skip(&mut self, name: &str) {
+        self.skip.insert(name.into());
+    }
+
+    /// return whether the package described by `info` is relocated
+    #[must_use]
+    pub fn applies_to(&self, info: &PkgInfo) -> bool {
+        (self.paths || self.runpath) && !self.skip.contains(&info.pkgname)
+    }
+}
+
+/// return how `file` (relative to the package root) may be relocated
+fn classify(root: &Path, rel: &Path) -> Result<Option<RelocationKind>, AetherError> {
+    let file = root.join(rel);
+    let read_error = |source| AetherError::ReadError {
+        file: file.clone(),
+        source,
+    };
+
+    let meta = std::fs::symlink_metadata(&file).map_err(read_error)?;
+    if !meta.is_file() {
+        return Ok(None);
+    }
+
+    let kind = match rel.extension().and_then(|ext| ext.to_str()) {
+        Some("pc") => Some(RelocationKind::PkgConfig),
+        Some("la") => Some(RelocationKind::Libtool),
+        _ if rel.starts_with("etc") => Some(RelocationKind::Config),
+        _ if meta.permissions().mode() & 0o111 != 0 => {
+            let mut magic = [0; 2];
+            let mut reader = File::open(&file).map_err(read_error)?;
+            match reader.read_exact(&mut magic) {
+                Ok(()) if &magic == b"#!" => Some(RelocationKind::Shebang),
+                _ => None,
+            }
+        }
+        _ => None,
+    };
+
+    Ok(kind)
+}
+
+/// rewrite the `/usr` paths in `text` that exist under `root`, returning how
+/// many were
+fn rewrite(pattern: &Regex, text: &str, root: &Path) -> (String, usize) {
+    let mut replaced = 0;
+
+    let rewritten = pattern.replace_all(text, |caps: &Captures| {
+        let path = &caps[2];
+        if root.join(path.trim_start_matches('/')).exists() {
+            replaced += 1;
+            format!("{}{}{}", &caps[1], root.display(), path)
+        } else {
+            caps[0].to_string()
+        }
+    });
+
+    (rewritten.into_owned(), replaced)
+}
+
+/**
+replace `file`, which is a read-only link into the store, with a copy that
+`edit` changes in place, keeping its mode
+*/
+fn replace_file(
+    file: &Path,
+    edit: impl FnOnce(&Path) -> Result<(), AetherError>,
+) -> Result<(), AetherError> {
+    let write_error = |source| AetherError::WriteError {
+        file: file.into(),
+        source,
+    };
+
+    let mode = metadata(file)
+        .map_err(|source| AetherError::ReadError {
+            file: file.into(),
+            source,
+        })?
+        .permissions()
+        .mode();
+
+    let partial = file.with_file_name(format!(
+        ".{}.relocating",
+        file.file_name().unwrap().to_string_lossy()
+    ));
+    copy(file, &partial).map_err(write_error)?;
+    set_permissions(&partial, Permissions::from_mode(mode | 0o200)).map_err(write_error)?;
+
+    if let Err(err) = edit(&partial) {
+        let _ = std::fs::remove_file(&partial);
+        return Err(err);
+    }
+
+    set_permissions(&partial, Permissions::from_mode(mode)).map_err(write_error)?;
+    rename(&partial, file).map_err(write_error)
+}
+
+/// rewrite the `/usr` paths of `file`, returning how many were
+fn relocate_paths(
+    pattern: &Regex,
+    root: &Path,
+    file: &Path,
+    kind: RelocationKind,
+) -> Result<usize, AetherError> {
+    let raw = read(file).map_err(|source| AetherError::ReadError {
+        file: file.into(),
+        source,
+    })?;
+    // binary files can't be rewritten without changing their layout
+    let text = match std::str::from_utf8(&raw) {
+        Ok(text) if !text.contains('\0') => text,
+        _ => return Ok(0),
+    };
+
+    let (rewritten, replaced) = match kind {
+        RelocationKind::Shebang => {
+            let (line, rest) = text.split_at(text.find('\n').unwrap_or(text.len()));
+            let (line, replaced) = rewrite(pattern, line, root);
+            (line + rest, replaced)
+        }
+        _ => rewrite(pattern, text, root),
+    };
+
+    if replaced > 0 {
+        replace_file(file, |partial| {
+            write(partial, &rewritten).map_err(|source| AetherError::WriteError {
+                file: partial.into(),
+                source,
+            })
+        })?;
+    }
+
+    Ok(replaced)
+}
+
+/// put the package's usr/lib first in the runpath of `elf` if it needs a
+/// library from there, returning whether it did
+fn relocate_runpath(root: &Path, elf: &ElfInfo) -> Result<bool, AetherError> {
+    let lib_dir = root.join("usr/lib");
+    let lib_dir = lib_dir.to_string_lossy();
+
+    let needs_own = elf
+        .needed
+        .iter()
+        .any(|needed| Path::new(&*lib_dir).join(needed).exists());
+    let current = if elf.runpath.is_empty() {
+        &elf.rpath
+    } else {
+        &elf.runpath
+    };
+    if !needs_own || current.iter().any(|path| *path == lib_dir) {
+        return Ok(false);
+    }
+
+    let runpath = std::iter::once(lib_dir.to_string())
+        .chain(current.iter().cloned())
+        .collect::<Vec<String>>()
+        .join(":");
+
+    replace_file(&elf.path, |partial| {
+        let output = Command::new("patchelf")
+            .arg("--set-rpath")
+            .arg(&runpath)
+            .arg(partial)
+            .output()
+            .map_err(AetherError::ProcessError)?;
+
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(AetherError::InvalidValue {
+                key: "patchelf".into(),
+                value: String::from_utf8_lossy(&output.stderr).trim().into(),
+            })
+        }
+    })?;
+
+    Ok(true)
+}
+
+/**
+relocate the package installed at `root` as `relocation` says
+
+Only the interpreter line of scripts is touched. Relocated files replace the
+links into the store with plain files of the same mode.
+*/
+fn relocate(root: &Path, relocation: &Relocation) -> Result<Vec<RelocatedFile>, AetherError> {
+    // a path starts at the beginning of the text or after something that
+    // can't be part of one
+    let pattern = Regex::new(r"(^|[^A-Za-z0-9_.+@/-])(/usr(?:/[A-Za-z0-9_.+@-]+)*)").unwrap();
+
+    let mut files = vec![];
+    ScanDir::files()
+        .walk(root, |iter| {
+            for (entry, _) in iter {
+                files.push(entry.path());
+            }
+        })
+        .map_err(|_| AetherError::InvalidPkg {
+            path: root.into(),
+            note: "unable to walk package directory".into(),
+        })?;
+    files.sort();
+
+    let mut relocated = vec![];
+    for file in files {
+        let rel = file.strip_prefix(root).unwrap().to_path_buf();
+        if rel.to_string_lossy().starts_with('.') || file.is_symlink() {
+            continue;
+        }
+
+        let (kind, replaced) = match ElfInfo::parse(&file)? {
+            Some(elf) if relocation.runpath => (
+                RelocationKind::Runpath,
+                usize::from(relocate_runpath(root, &elf)?),
+            ),
+            Some(_) => continue,
+            None if relocation.paths => match classify(root, &rel)? {
+                Some(kind) => (kind, relocate_paths(&pattern, root, &file, kind)?),
+                None => continue,
+            },
+            None => continue,
+        };
+        if replaced == 0 {
+            continue;
+        }
+
+        relocated.push(RelocatedFile {
+            path: rel,
+            kind,
+            replaced,
+            sha256: sha256sum(&file)?,
+        });
+    }
+
+    if !relocated.is_empty() {
+        let file = root.join(RELOCATED_FILE);
+        let list = Relocated {
+            files: relocated.clone(),
+        };
+
+        // a list of plain tables always serializes
+        write(&file, toml::to_string(&list).unwrap())
+            .map_err(|source| AetherError::WriteError { file, source })?;
+    }
+
+    Ok(relocated)
+}
+
+impl Pkg {
+    /// return the files relocated when this package was installed
+    pub fn relocated(&self) -> Result<Vec<RelocatedFile>, AetherError> {
+        let file = self.path.join(RELOCATED_FILE);
+        if !file.exists() {
+            return Ok(vec![]);
+        }
+
+        let raw = read_to_string(&file).map_err(|source| AetherError::ReadError {
+            file: file.clone(),
+            source,
+        })?;
+        let relocated: Relocated =
+            toml::from_str(&raw).map_err(|source| AetherError::TomlError { file, source })?;
+
+        Ok(relocated.files)
+    }
+}
+
+impl PkgList {
+    #[must_use]
+    pub fn relocation(&self) -> &Relocation {
+        &self.relocation
+    }
+
+    /// set whether hard-coded `/usr` paths are rewritten on install
+    pub fn set_relocation(&mut self, relocation: Relocation) {
+        self.relocation = relocation;
+    }
+
+    /// what every install by this `PkgList` so far relocated
+    #[must_use]
+    pub fn relocation_output(&self) -> &Vec<RelocationReport> {
+        &self.relocation_output
+    }
+
+    /// relocate `pkg`, freshly installed at `root`, if it should be
+    pub(crate) fn relocate_installed(&mut self, pkg: &Pkg, root: &Path) -> Result<(), AetherError> {
+        if !self.relocation.applies_to(&pkg.pkginfo) {
+            return Ok(());
+        }
+
+        let files = relocate(root, &self.relocation)?;
+        if !files.is_empty() {
+            self.relocation_output.push(RelocationReport {
+                pkg: pkg.get_refstr(),
+                prefix: root.join("usr"),
+                files,
+            });
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,256 @@
+use crate::{cache_dir, sha256sum, vercmp, AetherError, Depend, Pkg, PkgInfo};
+use std::fs::{create_dir_all, read, read_dir, remove_dir_all};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::{from_utf8, FromStr};
+
+fn parse_num<T: FromStr>(key: &str, value: &str) -> Result<T, AetherError> {
+    value.parse().map_err(|_| AetherError::InvalidValue {
+        key: key.into(),
+        value: value.into(),
+    })
+}
+
+/**
+A single package entry from a sync repository database
+
+# Public fields:
+```text
+repo: String
+filename: String
+sha256sum: String
+csize: u64
+pkginfo: PkgInfo
+```
+*/
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RepoPkg {
+    pub repo: String,
+    pub filename: String,
+    pub sha256sum: String,
+    pub csize: u64,
+    pub pkginfo: PkgInfo,
+}
+
+impl RepoPkg {
+    /// parse the `desc` file of a sync database entry
+    pub fn parse_desc(repo: &str, file: &dyn AsRef<Path>) -> Result<RepoPkg, AetherError> {
+        let file = file.as_ref();
+
+        let desc_raw = read(file).map_err(|source| AetherError::ReadError {
+            file: file.into(),
+            source,
+        })?;
+        let desc = from_utf8(&desc_raw).map_err(AetherError::Utf8Error)?;
+
+        let mut repo_pkg = RepoPkg {
+            repo: repo.into(),
+            filename: String::new(),
+            sha256sum: String::new(),
+            csize: 0,
+            pkginfo: PkgInfo::new(),
+        };
+        let info = &mut repo_pkg.pkginfo;
+
+        for section in desc.split("\n\n") {
+            let mut lines = section.lines().filter(|line| !line.is_empty());
+            let key = match lines.next() {
+                Some(key) => key,
+                None => continue,
+            };
+            let values: Vec<String> = lines.map(String::from).collect();
+            let first = values.first().cloned().unwrap_or_default();
+
+            match key {
+                "%FILENAME%" => repo_pkg.filename = first,
+                "%SHA256SUM%" => repo_pkg.sha256sum = first,
+                "%CSIZE%" => repo_pkg.csize = parse_num(key, &first)?,
+                "%NAME%" => info.pkgname = first,
+                "%BASE%" => info.pkgbase = first,
+                "%VERSION%" => info.pkgver = first,
+                "%DESC%" => info.pkgdesc = first,
+                "%URL%" => info.url = first,
+                "%BUILDDATE%" => info.builddate = parse_num(key, &first)?,
+                "%PACKAGER%" => info.packager = first,
+                "%ISIZE%" => info.size = parse_num(key, &first)?,
+                "%ARCH%" => info.arch = values,
+                "%LICENSE%" => info.license = values,
+                "%REPLACES%" => info.replaces = values,
+                "%GROUPS%" => info.group = values,
+                "%CONFLICTS%" => info.conflict = values,
+                "%PROVIDES%" => info.provides = values,
+                "%DEPENDS%" => info.depend = values,
+                "%OPTDEPENDS%" => info.optdepend = values,
+                "%MAKEDEPENDS%" => info.makedepend = values,
+                "%CHECKDEPENDS%" => info.checkdepend = values,
+                // the database format grows new keys over time, and none of
+                // the others are needed to install a package
+                _ => {}
+            }
+        }
+
+        if info.pkgname.is_empty() || info.pkgver.is_empty() || repo_pkg.filename.is_empty() {
+            return Err(AetherError::InfoParseError {
+                field: "%NAME%, %VERSION% or %FILENAME%".into(),
+                line: file.display().to_string(),
+            });
+        }
+
+        Ok(repo_pkg)
+    }
+
+    #[must_use]
+    pub fn get_refstr(&self) -> String {
+        format!("{}-{}", self.pkginfo.pkgname, self.pkginfo.pkgver)
+    }
+
+    /// return whether this package satisfies `depend` by name or provides
+    #[must_use]
+    pub fn satisfies(&self, depend: &Depend) -> bool {
+        depend.satisfied_by(&self.pkginfo.pkgname, &self.pkginfo.pkgver)
+            || depend.satisfied_by_provides(&self.pkginfo.provides)
+    }
+}
+
+/**
+A sync repository: a local directory holding `<name>.db` and the package
+archives it lists, like a pacman mirror or cache
+
+# Public methods:
+```text
+// load the database of the repository `name` stored in `path`
+Repo::load() : pub fn load(name: &str, path: &dyn AsRef<Path>) -> Result<Repo>
+
+// find the newest package satisfying a dependency
+Repo::find() : pub fn find(&self, depend: &Depend) -> Option<&RepoPkg>
+
+// extract a package from this repository into a directory
+Repo::extract() : pub fn extract(&self, pkg: &RepoPkg, dest: &dyn AsRef<Path>) -> Result<Pkg>
+```
+*/
+#[derive(Clone, Debug)]
+pub struct Repo {
+    pub name: String,
+    pub path: PathBuf,
+    pkgs: Vec<RepoPkg>,
+}
+
+impl Repo {
+    /// load the database of the repository `name` stored in `path`
+    pub fn load(name: &str, path: &dyn AsRef<Path>) -> Result<Repo, AetherError> {
+        let path = path.as_ref();
+        let db = path.join(format!("{}.db", name));
+        let extracted = cache_dir().join("sync").join(name);
+
+        if extracted.exists() {
+            remove_dir_all(&extracted).map_err(|source| AetherError::WriteError {
+                file: extracted.clone(),
+                source,
+            })?;
+        }
+        create_dir_all(&extracted).map_err(|source| AetherError::WriteError {
+            file: extracted.clone(),
+            source,
+        })?;
+
+        let status = Command::new("tar")
+            .arg("-xf")
+            .arg(&db)
+            .arg("-C")
+            .arg(&extracted)
+            .status()
+            .map_err(AetherError::ProcessError)?;
+
+        if !status.success() {
+            return Err(AetherError::InvalidValue {
+                key: "repository database".into(),
+                value: db.display().to_string(),
+            });
+        }
+
+        let entries = read_dir(&extracted).map_err(|source| AetherError::ReadError {
+            file: extracted.clone(),
+            source,
+        })?;
+
+        let mut pkgs = vec![];
+        for entry in entries {
+            let entry = entry?;
+            let desc = entry.path().join("desc");
+
+            if desc.is_file() {
+                pkgs.push(RepoPkg::parse_desc(name, &desc)?);
+            }
+        }
+
+        pkgs.sort_by_key(RepoPkg::get_refstr);
+
+        Ok(Repo {
+            name: name.into(),
+            path: path.into(),
+            pkgs,
+        })
+    }
+
+    #[must_use]
+    pub fn pkgs(&self) -> &Vec<RepoPkg> {
+        &self.pkgs
+    }
+
+    /// find the newest package named `name` exactly, ignoring provides
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&RepoPkg> {
+        self.pkgs
+            .iter()
+            .filter(|pkg| pkg.pkginfo.pkgname == name)
+            .max_by(|a, b| vercmp(&a.pkginfo.pkgver, &b.pkginfo.pkgver))
+    }
+
+    /// find the newest package satisfying `depend`, preferring packages
+    /// named after it over ones that only provide it
+    #[must_use]
+    pub fn find(&self, depend: &Depend) -> Option<&RepoPkg> {
+        let newest = |a: &&RepoPkg, b: &&RepoPkg| vercmp(&a.pkginfo.pkgver, &b.pkginfo.pkgver);
+
+        self.pkgs
+            .iter()
+            .filter(|pkg| depend.satisfied_by(&pkg.pkginfo.pkgname, &pkg.pkginfo.pkgver))
+            .max_by(newest)
+            .or_else(|| {
+                self.pkgs
+                    .iter()
+                    .filter(|pkg| depend.satisfied_by_provides(&pkg.pkginfo.provides))
+                    .max_by(newest)
+            })
+    }
+
+    /// return the path of a package's archive in this repository
+    #[must_use]
+    pub fn archive(&self, pkg: &RepoPkg) -> PathBuf {
+        self.path.join(&pkg.filename)
+    }
+
+    /// check a package's archive against the sha256 sum in the database
+    pub fn verify(&self, pkg: &RepoPkg) -> Result<PathBuf, AetherError> {
+        let archive = self.archive(pkg);
+        let sum = sha256sum(&archive)?;
+
+        if !pkg.sha256sum.is_empty() && sum != pkg.sha256sum {
+            return Err(AetherError::ChecksumError {
+                file: archive,
+                expected: pkg.sha256sum.clone(),
+                found: sum,
+            });
+        }
+
+        Ok(archive)
+    }
+
+    /// verify and extract a package from this repository into `dest`
+    pub fn extract(&self, pkg: &RepoPkg, dest: &dyn AsRef<Path>) -> Result<Pkg, AetherError> {
+        let archive = self.verify(pkg)?;
+
+        Pkg::from_archive(&archive, dest)
+    }
+}
@@ -0,0 +1,127 @@
+use crate::{BuildInfo, InstalledPkg, Repo};
+use std::path::PathBuf;
+
+/// the extensions makepkg may give a package archive, most common first
+const PKG_EXTENSIONS: [&str; 5] = [
+    ".pkg.tar.zst",
+    ".pkg.tar.xz",
+    ".pkg.tar.gz",
+    ".pkg.tar.bz2",
+    ".pkg.tar",
+];
+
+/// where a package needed to reproduce a build can be had from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ReproduceSource {
+    /// an archive already in one of the cache directories
+    Cache(PathBuf),
+    /// the exact version is in a sync repository
+    Repo { repo: String, archive: PathBuf },
+    /// no known source has this exact version, so it has to be fetched from
+    /// an archive of old packages
+    Missing,
+}
+
+/// one package of the recorded build environment and where to get it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReproduceStep {
+    pub pkg: InstalledPkg,
+    pub source: ReproduceSource,
+}
+
+/**
+The packages that have to be installed to rebuild a package in the
+environment recorded in its .BUILDINFO
+
+# Public fields:
+```text
+steps: Vec<ReproduceStep>
+```
+
+# Public methods:
+```text
+// return whether every package has a local source
+ReproducePlan::is_complete() : pub fn is_complete(&self) -> bool
+
+// return the packages that no local source has
+ReproducePlan::missing() : pub fn missing(&self) -> Vec<&InstalledPkg>
+```
+*/
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ReproducePlan {
+    /// in the order of the .BUILDINFO's `installed` list
+    pub steps: Vec<ReproduceStep>,
+}
+
+impl ReproducePlan {
+    /// return whether every package is in a cache or repository
+    #[must_use]
+    pub fn is_complete(&self) -> bool {
+        self.steps
+            .iter()
+            .all(|step| step.source != ReproduceSource::Missing)
+    }
+
+    /// return the packages that no cache or repository has
+    #[must_use]
+    pub fn missing(&self) -> Vec<&InstalledPkg> {
+        self.steps
+            .iter()
+            .filter(|step| step.source == ReproduceSource::Missing)
+            .map(|step| &step.pkg)
+            .collect()
+    }
+}
+
+impl BuildInfo {
+    /**
+    list where to get the exact version of every package installed when this
+    package was built
+
+    Archives already in `cache_dirs` are preferred; otherwise a package must
+    match name, version and architecture in one of `repos`.
+    */
+    #[must_use]
+    pub fn reproduce_plan(&self, cache_dirs: &[PathBuf], repos: &[Repo]) -> ReproducePlan {
+        let steps = self
+            .installed
+            .iter()
+            .map(|pkg| ReproduceStep {
+                pkg: pkg.clone(),
+                source: find_cached(pkg, cache_dirs)
+                    .or_else(|| find_in_repos(pkg, repos))
+                    .unwrap_or(ReproduceSource::Missing),
+            })
+            .collect();
+
+        ReproducePlan { steps }
+    }
+}
+
+fn find_cached(pkg: &InstalledPkg, cache_dirs: &[PathBuf]) -> Option<ReproduceSource> {
+    cache_dirs.iter().find_map(|dir| {
+        PKG_EXTENSIONS
+            .iter()
+            .map(|ext| dir.join(format!("{}{}", pkg, ext)))
+            .find(|archive| archive.is_file())
+            .map(ReproduceSource::Cache)
+    })
+}
+
+fn find_in_repos(pkg: &InstalledPkg, repos: &[Repo]) -> Option<ReproduceSource> {
+    repos.iter().find_map(|repo| {
+        repo.pkgs()
+            .iter()
+            .find(|repo_pkg| {
+                let info = &repo_pkg.pkginfo;
+
+                info.pkgname == pkg.name
+                    && info.pkgver == pkg.version
+                    && (info.arch.is_empty() || info.arch.contains(&pkg.arch))
+            })
+            .map(|repo_pkg| ReproduceSource::Repo {
+                repo: repo.name.clone(),
+                archive: repo.archive(repo_pkg),
+            })
+    })
+}
@@ -0,0 +1,217 @@
+use crate::AetherError;
+use std::ffi::CString;
+use std::fs::read_to_string;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Child, Command, ExitStatus};
+
+/// check whether unprivileged user namespaces can be created on this system
+pub(crate) fn namespaces_available() -> Result<(), String> {
+    if !Path::new("/proc/self/ns/user").exists() {
+        return Err("kernel does not support user namespaces".into());
+    }
+
+    let sysctls = [
+        "/proc/sys/kernel/unprivileged_userns_clone",
+        "/proc/sys/user/max_user_namespaces",
+    ];
+
+    for sysctl in sysctls {
+        if let Ok(value) = read_to_string(sysctl) {
+            if value.trim() == "0" {
+                return Err(format!("user namespaces are disabled by {}", sysctl));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn cstring(bytes: &[u8]) -> Result<CString, AetherError> {
+    CString::new(bytes).map_err(|_| AetherError::InvalidValue {
+        key: "path".into(),
+        value: String::from_utf8_lossy(bytes).into(),
+    })
+}
+
+/// write `data` to the procfs file `path`; only async-signal-safe calls, for
+/// use between fork and exec
+unsafe fn write_proc(path: &CString, data: &[u8]) -> io::Result<()> {
+    let fd = libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let written = libc::write(fd, data.as_ptr().cast(), data.len());
+    libc::close(fd);
+
+    if written < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// how the filesystem should look inside the sandbox
+pub(crate) enum Layout<'a> {
+    /// bind-mount this directory over /usr
+    Usr(&'a Path),
+    /// make the whole filesystem read-only except these directories and /tmp
+    ReadOnly(&'a [&'a Path]),
+}
+
+// from linux/mount.h, which libc doesn't expose for every target
+const MOUNT_ATTR_RDONLY: u64 = 0x1;
+
+#[repr(C)]
+struct MountAttr {
+    attr_set: u64,
+    attr_clr: u64,
+    propagation: u64,
+    userns_fd: u64,
+}
+
+unsafe fn mount(
+    source: *const libc::c_char,
+    target: &CString,
+    fstype: *const libc::c_char,
+    flags: libc::c_ulong,
+) -> io::Result<()> {
+    if libc::mount(source, target.as_ptr(), fstype, flags, std::ptr::null()) != 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+unsafe fn set_readonly(target: &CString, readonly: bool, recursive: bool) -> io::Result<()> {
+    let attr = MountAttr {
+        attr_set: if readonly { MOUNT_ATTR_RDONLY } else { 0 },
+        attr_clr: if readonly { 0 } else { MOUNT_ATTR_RDONLY },
+        propagation: 0,
+        userns_fd: 0,
+    };
+    let flags = if recursive { libc::AT_RECURSIVE } else { 0 };
+
+    let ret = libc::syscall(
+        libc::SYS_mount_setattr,
+        libc::AT_FDCWD,
+        target.as_ptr(),
+        flags,
+        &attr as *const MountAttr,
+        std::mem::size_of::<MountAttr>(),
+    );
+
+    if ret != 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// spawn `cmd` in new user and mount namespaces with the given filesystem
+/// layout, mapping the calling user to itself
+pub(crate) fn spawn(cmd: &mut Command, layout: Layout) -> Result<Child, AetherError> {
+    namespaces_available().map_err(|note| AetherError::SandboxError {
+        note,
+        source: io::Error::from(io::ErrorKind::Unsupported),
+    })?;
+
+    let (dirs, usr) = match layout {
+        Layout::Usr(usr) => (vec![usr], true),
+        Layout::ReadOnly(writable) => (writable.to_vec(), false),
+    };
+
+    let dirs_c = dirs
+        .iter()
+        .map(|dir| cstring(dir.as_os_str().as_bytes()))
+        .collect::<Result<Vec<CString>, AetherError>>()?;
+    let usr_c = cstring(b"/usr")?;
+    let tmp_c = cstring(b"/tmp")?;
+    let root_c = cstring(b"/")?;
+    let setgroups_c = cstring(b"/proc/self/setgroups")?;
+    let uid_map_c = cstring(b"/proc/self/uid_map")?;
+    let gid_map_c = cstring(b"/proc/self/gid_map")?;
+    // the child changes into its working directory before pre_exec runs, so
+    // it has to change into it again to see the new mounts
+    let cwd_c = match cmd.get_current_dir() {
+        Some(cwd) => Some(cstring(cwd.as_os_str().as_bytes())?),
+        None => None,
+    };
+
+    // SAFETY: getuid and getgid can't fail
+    let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+    let uid_map = format!("{} {} 1\n", uid, uid).into_bytes();
+    let gid_map = format!("{} {} 1\n", gid, gid).into_bytes();
+
+    let pre_exec = move || -> io::Result<()> {
+        // SAFETY: everything here is a raw syscall on data prepared before
+        // the fork, so nothing allocates or takes locks in the child
+        unsafe {
+            if libc::unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNS) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            write_proc(&setgroups_c, b"deny")?;
+            write_proc(&uid_map_c, &uid_map)?;
+            write_proc(&gid_map_c, &gid_map)?;
+
+            let null = std::ptr::null();
+            mount(null, &root_c, null, libc::MS_REC | libc::MS_PRIVATE)?;
+
+            if usr {
+                mount(
+                    dirs_c[0].as_ptr(),
+                    &usr_c,
+                    null,
+                    libc::MS_BIND | libc::MS_REC,
+                )?;
+            } else {
+                // /tmp first, in case a writable directory is inside it
+                mount(tmp_c.as_ptr(), &tmp_c, null, libc::MS_BIND | libc::MS_REC)?;
+                for dir_c in &dirs_c {
+                    mount(dir_c.as_ptr(), dir_c, null, libc::MS_BIND | libc::MS_REC)?;
+                }
+
+                set_readonly(&root_c, true, true)?;
+                set_readonly(&tmp_c, false, false)?;
+                for dir_c in &dirs_c {
+                    set_readonly(dir_c, false, false)?;
+                }
+            }
+
+            if let Some(cwd) = &cwd_c {
+                if libc::chdir(cwd.as_ptr()) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+        }
+
+        Ok(())
+    };
+
+    // SAFETY: the closure only performs async-signal-safe syscalls
+    unsafe {
+        cmd.pre_exec(pre_exec);
+    }
+
+    cmd.spawn().map_err(|source| match source.raw_os_error() {
+        Some(libc::EPERM | libc::EINVAL | libc::ENOSPC | libc::EUSERS | libc::ENOSYS) => {
+            AetherError::SandboxError {
+                note: "unable to set up user and mount namespaces".into(),
+                source,
+            }
+        }
+        _ => AetherError::ProcessError(source),
+    })
+}
+
+/// run `cmd` in new user and mount namespaces with `usr` bind-mounted over
+/// /usr
+pub(crate) fn run_with_usr(usr: &Path, cmd: &mut Command) -> Result<ExitStatus, AetherError> {
+    spawn(cmd, Layout::Usr(usr))?
+        .wait()
+        .map_err(AetherError::ProcessError)
+}
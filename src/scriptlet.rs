@@ -0,0 +1,226 @@
+use crate::sandbox::{self, Layout};
+use crate::{cache_dir, AetherError};
+use std::fs::{create_dir_all, read, write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::str::from_utf8;
+
+/// a point in a transaction at which a scriptlet function may run
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ScriptletStage {
+    PreInstall,
+    PostInstall,
+    PreUpgrade,
+    PostUpgrade,
+    PreRemove,
+    PostRemove,
+}
+
+impl ScriptletStage {
+    pub const ALL: [ScriptletStage; 6] = [
+        ScriptletStage::PreInstall,
+        ScriptletStage::PostInstall,
+        ScriptletStage::PreUpgrade,
+        ScriptletStage::PostUpgrade,
+        ScriptletStage::PreRemove,
+        ScriptletStage::PostRemove,
+    ];
+
+    /// the name of the shell function run at this stage
+    #[must_use]
+    pub fn function(self) -> &'static str {
+        match self {
+            ScriptletStage::PreInstall => "pre_install",
+            ScriptletStage::PostInstall => "post_install",
+            ScriptletStage::PreUpgrade => "pre_upgrade",
+            ScriptletStage::PostUpgrade => "post_upgrade",
+            ScriptletStage::PreRemove => "pre_remove",
+            ScriptletStage::PostRemove => "post_remove",
+        }
+    }
+}
+
+/// whether and how scriptlets and hooks are run during a transaction
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ScriptletPolicy {
+    /// run directly, with full access to the user's files
+    Run,
+    /// run in a user namespace where everything but the package's own
+    /// directory (or for hooks, the environment and the user's XDG cache and
+    /// data directories) and /tmp is read-only
+    ///
+    /// This is the default, so that installing a package can't touch the
+    /// user's files. Where the kernel doesn't allow unprivileged user
+    /// namespaces, scriptlets and hooks are skipped instead, with the reason
+    /// in [`ScriptletOutput::skipped`] or
+    /// [`HookOutput::skipped`](crate::HookOutput::skipped).
+    #[default]
+    Sandbox,
+    /// never run anything
+    Skip,
+}
+
+/// the captured output of one scriptlet function
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScriptletOutput {
+    pub pkg: String,
+    pub stage: ScriptletStage,
+    pub stdout: String,
+    pub stderr: String,
+    /// why the function didn't run, when the sandbox couldn't be set up
+    pub skipped: Option<String>,
+}
+
+/**
+Contains an install scriptlet parsed from a package's .INSTALL file
+
+# Public methods:
+```text
+// parse a .INSTALL file
+Scriptlet::parse() : pub fn parse(file: &dyn AsRef<Path>) -> Result<Scriptlet>
+
+// return whether the scriptlet defines the function for a stage
+Scriptlet::has() : pub fn has(&self, stage: ScriptletStage) -> bool
+
+// run the function for a stage, capturing its output
+Scriptlet::run() : pub fn run(&self, ...) -> Result<Option<ScriptletOutput>>
+```
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Scriptlet {
+    source: String,
+    stages: Vec<ScriptletStage>,
+}
+
+impl Scriptlet {
+    /// parse a .INSTALL file
+    pub fn parse(file: &dyn AsRef<Path>) -> Result<Scriptlet, AetherError> {
+        let file = file.as_ref();
+
+        let raw = read(file).map_err(|source| AetherError::ReadError {
+            file: file.into(),
+            source,
+        })?;
+        let source = from_utf8(&raw).map_err(AetherError::Utf8Error)?.to_string();
+
+        let mut stages = vec![];
+        for line in source.lines() {
+            let line = line.trim_start();
+            let line = line.strip_prefix("function ").unwrap_or(line).trim_start();
+
+            for stage in ScriptletStage::ALL {
+                let rest = match line.strip_prefix(stage.function()) {
+                    Some(rest) => rest.trim_start(),
+                    None => continue,
+                };
+
+                if (rest.starts_with("()") || rest.starts_with('{')) && !stages.contains(&stage) {
+                    stages.push(stage);
+                }
+            }
+        }
+
+        Ok(Scriptlet { source, stages })
+    }
+
+    /// return whether the scriptlet defines the function for `stage`
+    #[must_use]
+    pub fn has(&self, stage: ScriptletStage) -> bool {
+        self.stages.contains(&stage)
+    }
+
+    #[must_use]
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /**
+    run the function for `stage` of the package `pkg`, capturing its output
+
+    `versions` are passed as arguments the way ALPM does: the new version for
+    install stages, the new then old version for upgrades and the old version
+    for removals. The function runs in `dir`, which is also the only place a
+    sandboxed scriptlet may write besides /tmp. Returns `None` when nothing
+    ran, and an output with `skipped` set when the sandbox is unavailable.
+    */
+    pub fn run(
+        &self,
+        pkg: &str,
+        stage: ScriptletStage,
+        versions: &[&str],
+        dir: &dyn AsRef<Path>,
+        policy: ScriptletPolicy,
+    ) -> Result<Option<ScriptletOutput>, AetherError> {
+        let dir = dir.as_ref();
+
+        if policy == ScriptletPolicy::Skip || !self.has(stage) {
+            return Ok(None);
+        }
+
+        // the package may be gone by the time post_remove runs, so run a copy
+        let scriptlets = cache_dir().join("scriptlets");
+        create_dir_all(&scriptlets).map_err(|source| AetherError::WriteError {
+            file: scriptlets.clone(),
+            source,
+        })?;
+        let file = scriptlets.join(format!("{}.INSTALL", pkg));
+        write(&file, &self.source).map_err(|source| AetherError::WriteError {
+            file: file.clone(),
+            source,
+        })?;
+
+        let mut cmd = Command::new("bash");
+        cmd.arg("-c")
+            .arg(r#". "$0" && "$@""#)
+            .arg(&file)
+            .arg(stage.function())
+            .args(versions)
+            .current_dir(dir)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        let output = match policy {
+            ScriptletPolicy::Sandbox => match sandbox::spawn(&mut cmd, Layout::ReadOnly(&[dir])) {
+                Ok(child) => child
+                    .wait_with_output()
+                    .map_err(AetherError::ProcessError)?,
+                Err(AetherError::SandboxError { note, .. }) => {
+                    return Ok(Some(ScriptletOutput {
+                        pkg: pkg.into(),
+                        stage,
+                        stdout: String::new(),
+                        stderr: String::new(),
+                        skipped: Some(note),
+                    }));
+                }
+                Err(err) => return Err(err),
+            },
+            _ => cmd.output().map_err(AetherError::ProcessError)?,
+        };
+
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+
+        if !output.status.success() {
+            return Err(AetherError::ScriptletError {
+                pkg: pkg.into(),
+                function: stage.function().into(),
+                status: output.status.code(),
+                stderr,
+            });
+        }
+
+        Ok(Some(ScriptletOutput {
+            pkg: pkg.into(),
+            stage,
+            stdout,
+            stderr,
+            skipped: None,
+        }))
+    }
+}
@@ -0,0 +1,178 @@
+use crate::{AetherError, Depend, PkgInfo, PkgList, Repo};
+use regex::{Regex, RegexBuilder};
+
+/// the part of a package a [`SearchQuery`] matched, best first
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum SearchField {
+    /// the query is the whole package name
+    Exact,
+    Name,
+    Provides,
+    Group,
+    Description,
+}
+
+/**
+What to look for with [`PkgList::search`]
+
+Substring queries ignore case; regular expressions are used as written, so
+`(?i)` makes them case-insensitive.
+
+# Public methods:
+```text
+// match a case-insensitive substring
+SearchQuery::substring() : pub fn substring(s: &str) -> SearchQuery
+
+// match a regular expression
+SearchQuery::regex() : pub fn regex(s: &str) -> Result<SearchQuery>
+```
+*/
+#[derive(Clone, Debug)]
+pub struct SearchQuery {
+    text: String,
+    regex: Regex,
+}
+
+impl SearchQuery {
+    /// match a case-insensitive substring
+    #[must_use]
+    pub fn substring(s: &str) -> SearchQuery {
+        let regex = RegexBuilder::new(&regex::escape(s))
+            .case_insensitive(true)
+            .build()
+            .unwrap();
+
+        SearchQuery {
+            text: s.to_lowercase(),
+            regex,
+        }
+    }
+
+    /// match a regular expression
+    pub fn regex(s: &str) -> Result<SearchQuery, AetherError> {
+        let regex = Regex::new(s).map_err(|_| AetherError::InvalidValue {
+            key: "search pattern".into(),
+            value: s.into(),
+        })?;
+
+        Ok(SearchQuery {
+            text: s.to_lowercase(),
+            regex,
+        })
+    }
+
+    /// return the best field of `info` this query matches
+    fn best_match(&self, info: &PkgInfo) -> Option<SearchField> {
+        let name = &info.pkgname;
+
+        if name.to_lowercase() == self.text {
+            Some(SearchField::Exact)
+        } else if self.regex.is_match(name) {
+            Some(SearchField::Name)
+        } else if info
+            .provides
+            .iter()
+            .any(|provide| self.regex.is_match(&Depend::parse(provide).name))
+        {
+            Some(SearchField::Provides)
+        } else if info.group.iter().any(|group| self.regex.is_match(group)) {
+            Some(SearchField::Group)
+        } else if self.regex.is_match(&info.pkgdesc) {
+            Some(SearchField::Description)
+        } else {
+            None
+        }
+    }
+}
+
+/**
+A package found by [`PkgList::search`]
+
+# Public fields:
+```text
+pkginfo: PkgInfo
+repo: Option<String>
+installed: Option<String>
+matched: SearchField
+```
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SearchResult {
+    pub pkginfo: PkgInfo,
+    /// the sync repository the package is from, or `None` for an installed
+    /// package no repository has
+    pub repo: Option<String>,
+    /// the version installed in the environment, if any
+    pub installed: Option<String>,
+    pub matched: SearchField,
+}
+
+impl SearchResult {
+    /// return whether exactly this version is installed
+    #[must_use]
+    pub fn is_installed(&self) -> bool {
+        self.installed.as_deref() == Some(self.pkginfo.pkgver.as_str())
+    }
+}
+
+impl PkgList {
+    /**
+    search the packages of `repos` and the installed ones by name, provides,
+    groups and description
+
+    Results are ranked by the best field they matched, then by name. A
+    package in several repositories is listed once per repository, in the
+    order of `repos`, and installed packages that no repository has are
+    listed with no `repo`.
+    */
+    #[must_use]
+    pub fn search(&self, query: &SearchQuery, repos: &[Repo]) -> Vec<SearchResult> {
+        let installed = |name: &str| {
+            self.pkgs()
+                .iter()
+                .find(|pkg| pkg.pkginfo.pkgname == name)
+                .map(|pkg| pkg.pkginfo.pkgver.clone())
+        };
+
+        let mut results = vec![];
+        for repo in repos {
+            for repo_pkg in repo.pkgs() {
+                if let Some(matched) = query.best_match(&repo_pkg.pkginfo) {
+                    results.push(SearchResult {
+                        pkginfo: repo_pkg.pkginfo.clone(),
+                        repo: Some(repo.name.clone()),
+                        installed: installed(&repo_pkg.pkginfo.pkgname),
+                        matched,
+                    });
+                }
+            }
+        }
+
+        for pkg in self.pkgs() {
+            let in_repo = repos
+                .iter()
+                .any(|repo| repo.get(&pkg.pkginfo.pkgname).is_some());
+            if in_repo {
+                continue;
+            }
+
+            if let Some(matched) = query.best_match(&pkg.pkginfo) {
+                results.push(SearchResult {
+                    pkginfo: pkg.pkginfo.clone(),
+                    repo: None,
+                    installed: Some(pkg.pkginfo.pkgver.clone()),
+                    matched,
+                });
+            }
+        }
+
+        // stable, so repository order is kept within a name
+        results
+            .sort_by(|a, b| (a.matched, &a.pkginfo.pkgname).cmp(&(b.matched, &b.pkginfo.pkgname)));
+
+        results
+    }
+}
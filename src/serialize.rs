@@ -0,0 +1,245 @@
+use crate::{AetherError, MTree, PkgList};
+use serde::de::Deserializer;
+use serde::ser::{Error as _, SerializeStruct, Serializer};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::ffi::OsString;
+use std::fmt::Write as _;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+use std::str::from_utf8;
+use std::time::UNIX_EPOCH;
+
+/**
+A single entry of a package's .MTREE, as it is serialized
+
+Digests are lowercase hex, `mode` is the numeric permission bits and `time` is
+in whole seconds since the unix epoch. Keywords the entry doesn't have are
+`null`.
+
+# Public fields:
+```text
+path: PathBuf
+file_type: Option<String>
+mode: Option<u32>
+uid: Option<u64>
+gid: Option<u64>
+size: Option<u64>
+time: Option<u64>
+md5: Option<String>
+sha256: Option<String>
+link: Option<PathBuf>
+```
+*/
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MTreeEntry {
+    /// relative to the package root, starting with `./`
+    pub path: PathBuf,
+    /// `file`, `dir`, `link`, `block`, `char`, `fifo` or `socket`
+    pub file_type: Option<String>,
+    pub mode: Option<u32>,
+    pub uid: Option<u64>,
+    pub gid: Option<u64>,
+    pub size: Option<u64>,
+    pub time: Option<u64>,
+    pub md5: Option<String>,
+    pub sha256: Option<String>,
+    pub link: Option<PathBuf>,
+}
+
+impl MTreeEntry {
+    fn from_entry(entry: &mtree::Entry) -> MTreeEntry {
+        let hex = |bytes: &[u8]| {
+            bytes.iter().fold(String::new(), |mut hex, b| {
+                // writing to a String can't fail
+                write!(hex, "{:02x}", b).unwrap();
+                hex
+            })
+        };
+
+        MTreeEntry {
+            path: decode(entry.path()),
+            file_type: entry.file_type().map(|file_type| file_type.to_string()),
+            mode: entry.mode().map(|mode| {
+                let perms = u32::from_str_radix(&format!("{:o}", mode), 8).unwrap_or(0);
+                let setuid = if mode.setuid { 0o4000 } else { 0 };
+                let setgid = if mode.setgid { 0o2000 } else { 0 };
+
+                perms | setuid | setgid
+            }),
+            uid: entry.uid(),
+            gid: entry.gid(),
+            size: entry.size(),
+            time: entry
+                .time()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|since| since.as_secs()),
+            md5: entry.md5().map(|md5| format!("{:032x}", md5)),
+            sha256: entry.sha256().map(|sha256| hex(sha256)),
+            link: entry.link().map(decode),
+        }
+    }
+
+    /// format the entry as a line of an mtree file
+    fn to_line(&self) -> String {
+        let mut line = encode(self.path.as_os_str().as_encoded_bytes());
+
+        let mut keyword = |key: &str, value: Option<String>| {
+            if let Some(value) = value {
+                write!(line, " {}={}", key, value).unwrap();
+            }
+        };
+
+        keyword("type", self.file_type.clone());
+        keyword("mode", self.mode.map(|mode| format!("{:o}", mode)));
+        keyword("uid", self.uid.map(|uid| uid.to_string()));
+        keyword("gid", self.gid.map(|gid| gid.to_string()));
+        keyword("size", self.size.map(|size| size.to_string()));
+        keyword("time", self.time.map(|time| format!("{}.0", time)));
+        keyword("md5digest", self.md5.clone());
+        keyword("sha256digest", self.sha256.clone());
+        keyword(
+            "link",
+            self.link
+                .as_ref()
+                .map(|link| encode(link.as_os_str().as_encoded_bytes())),
+        );
+
+        line
+    }
+}
+
+/// escape a path the way mtree(5) expects, as octal for anything but
+/// printable ASCII
+fn encode(bytes: &[u8]) -> String {
+    let mut encoded = String::new();
+
+    for b in bytes {
+        if b.is_ascii_graphic() && !matches!(b, b'\\' | b'#') {
+            encoded.push(char::from(*b));
+        } else {
+            write!(encoded, "\\{:03o}", b).unwrap();
+        }
+    }
+
+    encoded
+}
+
+/// undo [`encode`], which the mtree crate leaves to its callers
+fn decode(path: &Path) -> PathBuf {
+    let bytes = path.as_os_str().as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+
+    let mut i = 0;
+    while i < bytes.len() {
+        let octal = bytes
+            .get(i + 1..i + 4)
+            .filter(|digits| digits.iter().all(|d| (b'0'..=b'7').contains(d)))
+            .and_then(|digits| u8::from_str_radix(from_utf8(digits).ok()?, 8).ok());
+
+        match octal {
+            Some(b) if bytes[i] == b'\\' => {
+                decoded.push(b);
+                i += 4;
+            }
+            _ => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    OsString::from_vec(decoded).into()
+}
+
+/// serialized as a list of [`MTreeEntry`]; an entry that fails to parse is
+/// an error rather than being left out
+impl Serialize for MTree {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let entries: Vec<MTreeEntry> = self
+            .entries()
+            .map_err(S::Error::custom)?
+            .iter()
+            .map(MTreeEntry::from_entry)
+            .collect();
+
+        entries.serialize(serializer)
+    }
+}
+
+/// rebuilt from a list of [`MTreeEntry`] into an mtree file
+impl<'de> Deserialize<'de> for MTree {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let entries = Vec::<MTreeEntry>::deserialize(deserializer)?;
+
+        let mut raw = String::from("#mtree\n");
+        for entry in entries {
+            raw.push_str(&entry.to_line());
+            raw.push('\n');
+        }
+
+        Ok(MTree {
+            raw: raw.into_bytes(),
+        })
+    }
+}
+
+/// serialized as `{ "env": name, "pkgs": [...] }`
+impl Serialize for PkgList {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("PkgList", 2)?;
+        state.serialize_field("env", self.env().name())?;
+        state.serialize_field("pkgs", self.pkgs())?;
+        state.end()
+    }
+}
+
+/// serialized as `{ "kind": variant, "message": ..., "source": ... }`, where
+/// `source` is the message of the underlying error, if any
+impl Serialize for AetherError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("AetherError", 3)?;
+        state.serialize_field("kind", self.kind())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.serialize_field("source", &self.source().map(ToString::to_string))?;
+        state.end()
+    }
+}
+
+impl AetherError {
+    /// the name of the variant, which stays the same across releases
+    fn kind(&self) -> &'static str {
+        match self {
+            AetherError::AlreadyExists(_) => "AlreadyExists",
+            AetherError::BuildError { .. } => "BuildError",
+            AetherError::ChecksumError { .. } => "ChecksumError",
+            AetherError::CopyError { .. } => "CopyError",
+            AetherError::ForeignFile(_) => "ForeignFile",
+            AetherError::HookError { .. } => "HookError",
+            AetherError::IncompatibleArch { .. } => "IncompatibleArch",
+            AetherError::InfoLineError { .. } => "InfoLineError",
+            AetherError::InfoKeyError { .. } => "InfoKeyError",
+            AetherError::InfoParseError { .. } => "InfoParseError",
+            AetherError::InfoValueError { .. } => "InfoValueError",
+            AetherError::InvalidPkg { .. } => "InvalidPkg",
+            AetherError::InvalidValue { .. } => "InvalidValue",
+            AetherError::LinkError { .. } => "LinkError",
+            AetherError::MissingEnv(_) => "MissingEnv",
+            AetherError::MissingGeneration(_) => "MissingGeneration",
+            AetherError::MissingExec(_) => "MissingExec",
+            AetherError::MissingFile(_) => "MissingFile",
+            AetherError::MissingPkg { .. } => "MissingPkg",
+            AetherError::NotFound { .. } => "NotFound",
+            AetherError::ProcessError(_) => "ProcessError",
+            AetherError::ScriptletError { .. } => "ScriptletError",
+            AetherError::SandboxError { .. } => "SandboxError",
+            AetherError::ReadError { .. } => "ReadError",
+            AetherError::TomlError { .. } => "TomlError",
+            AetherError::UnsatisfiedDepend { .. } => "UnsatisfiedDepend",
+            AetherError::Unknown => "Unknown",
+            AetherError::Utf8Error(_) => "Utf8Error",
+            AetherError::WriteError { .. } => "WriteError",
+        }
+    }
+}
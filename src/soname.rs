@@ -0,0 +1,298 @@
+use crate::{AetherError, Depend, ElfInfo, Pkg, PkgList};
+use std::fmt;
+
+/**
+A shared library as packages provide and depend on it, like `libfoo.so=1-64`
+for the soname `libfoo.so.1` in a 64-bit ELF file
+
+# Public methods:
+```text
+// return the soname of an ELF file as a Soname
+Soname::from_elf() : pub fn from_elf(soname: &str, bits: u8) -> Option<Soname>
+
+// parse a provides or depend entry like "libfoo.so=1-64"
+Soname::parse() : pub fn parse(s: &str) -> Option<Soname>
+```
+*/
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(into = "String", try_from = "String"))]
+pub struct Soname {
+    /// the soname up to and including `.so`
+    pub name: String,
+    /// what follows `.so.` in the soname, which may be empty
+    pub version: String,
+    /// 32 or 64
+    pub bits: u8,
+}
+
+impl Soname {
+    /// return the `DT_SONAME` or `DT_NEEDED` entry `soname` of an ELF file
+    /// with `bits` as a `Soname`, or `None` if it doesn't name a `.so`
+    #[must_use]
+    pub fn from_elf(soname: &str, bits: u8) -> Option<Soname> {
+        let end = soname.find(".so")? + ".so".len();
+        let version = match &soname[end..] {
+            "" => "",
+            rest => rest.strip_prefix('.')?,
+        };
+
+        Some(Soname {
+            name: soname[..end].into(),
+            version: version.into(),
+            bits,
+        })
+    }
+
+    /// parse a provides or depend entry like `libfoo.so=1-64`, or return
+    /// `None` if it isn't one
+    #[must_use]
+    pub fn parse(s: &str) -> Option<Soname> {
+        let (name, rest) = s.trim().split_once('=')?;
+        let (version, bits) = rest.rsplit_once('-')?;
+
+        if !name.ends_with(".so") {
+            return None;
+        }
+
+        Some(Soname {
+            name: name.into(),
+            version: version.into(),
+            bits: bits.parse().ok().filter(|bits| matches!(bits, 32 | 64))?,
+        })
+    }
+
+    /// return this soname as an exact dependency
+    #[must_use]
+    pub fn to_depend(&self) -> Depend {
+        Depend::parse(&self.to_string())
+    }
+
+    /// return whether `depend` asks for this soname
+    #[must_use]
+    pub fn satisfies(&self, depend: &Depend) -> bool {
+        depend.satisfied_by(&self.name, &format!("{}-{}", self.version, self.bits))
+    }
+}
+
+impl fmt::Display for Soname {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}={}-{}", self.name, self.version, self.bits)
+    }
+}
+
+impl From<Soname> for String {
+    fn from(soname: Soname) -> String {
+        soname.to_string()
+    }
+}
+
+impl TryFrom<String> for Soname {
+    type Error = AetherError;
+
+    fn try_from(s: String) -> Result<Soname, AetherError> {
+        Soname::parse(&s).ok_or(AetherError::InvalidValue {
+            key: "soname".into(),
+            value: s,
+        })
+    }
+}
+
+/**
+How the sonames a package's ELF files provide and need compare with its
+declared `provides` and `depend`
+
+# Public fields:
+```text
+provides: Vec<Soname>
+needs: Vec<Soname>
+undeclared_provides: Vec<Soname>
+undeclared_depends: Vec<Soname>
+stale_provides: Vec<String>
+```
+*/
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SonameCheck {
+    /// the sonames of the package's shared libraries
+    pub provides: Vec<Soname>,
+    /// the sonames its ELF files need that it doesn't provide itself
+    pub needs: Vec<Soname>,
+    /// provided sonames missing from `provides`
+    pub undeclared_provides: Vec<Soname>,
+    /// needed sonames missing from `depend`
+    pub undeclared_depends: Vec<Soname>,
+    /// soname entries in `provides` that no library in the package has
+    pub stale_provides: Vec<String>,
+}
+
+impl SonameCheck {
+    /// return whether the declarations match the ELF files
+    #[must_use]
+    pub fn is_consistent(&self) -> bool {
+        self.undeclared_provides.is_empty()
+            && self.undeclared_depends.is_empty()
+            && self.stale_provides.is_empty()
+    }
+}
+
+/// return the sonames `elfs` provide and need, each sorted and deduplicated,
+/// leaving out needs the files satisfy themselves
+fn sonames_of(elfs: &[ElfInfo]) -> (Vec<Soname>, Vec<Soname>) {
+    let mut provides: Vec<Soname> = elfs
+        .iter()
+        .filter_map(|elf| Soname::from_elf(elf.soname.as_deref()?, elf.bits))
+        .collect();
+    provides.sort();
+    provides.dedup();
+
+    let mut needs: Vec<Soname> = elfs
+        .iter()
+        .flat_map(|elf| {
+            elf.needed
+                .iter()
+                .filter_map(|needed| Soname::from_elf(needed, elf.bits))
+        })
+        .filter(|soname| !provides.contains(soname))
+        .collect();
+    needs.sort();
+    needs.dedup();
+
+    (provides, needs)
+}
+
+impl Pkg {
+    /// return the sonames the package's shared libraries provide
+    pub fn soname_provides(&self) -> Result<Vec<Soname>, AetherError> {
+        Ok(sonames_of(&self.elf_files()?).0)
+    }
+
+    /// compare the sonames the package's ELF files provide and need with its
+    /// declared `provides` and `depend`
+    pub fn check_sonames(&self) -> Result<SonameCheck, AetherError> {
+        let (provides, needs) = sonames_of(&self.elf_files()?);
+        let info = &self.pkginfo;
+
+        let undeclared_provides = provides
+            .iter()
+            .filter(|soname| !soname.to_depend().satisfied_by_provides(&info.provides))
+            .cloned()
+            .collect();
+
+        let undeclared_depends = needs
+            .iter()
+            .filter(|soname| {
+                !info
+                    .depend
+                    .iter()
+                    .any(|depend| soname.satisfies(&Depend::parse(depend)))
+            })
+            .cloned()
+            .collect();
+
+        let stale_provides = info
+            .provides
+            .iter()
+            .filter(|provide| {
+                Soname::parse(provide).is_some_and(|declared| !provides.contains(&declared))
+            })
+            .cloned()
+            .collect();
+
+        Ok(SonameCheck {
+            provides,
+            needs,
+            undeclared_provides,
+            undeclared_depends,
+            stale_provides,
+        })
+    }
+
+    /// return whether the package needs `soname`, by its declared `depend`
+    /// or `needs`, the sonames its ELF files need
+    fn needs_soname(&self, soname: &Soname, needs: &[Soname]) -> bool {
+        needs.contains(soname)
+            || self
+                .pkginfo
+                .depend
+                .iter()
+                .any(|depend| soname.satisfies(&Depend::parse(depend)))
+    }
+}
+
+impl PkgList {
+    /**
+    return the installed packages providing `soname`, by their declared
+    `provides` or the libraries they contain
+
+    This lets soname dependencies be satisfied by packages whose .PKGINFO
+    doesn't list their libraries.
+    */
+    pub fn soname_providers(&self, soname: &Soname) -> Result<Vec<&Pkg>, AetherError> {
+        let depend = soname.to_depend();
+        let mut providers = vec![];
+
+        for pkg in self.pkgs() {
+            if depend.satisfied_by_provides(&pkg.pkginfo.provides)
+                || pkg.soname_provides()?.contains(soname)
+            {
+                providers.push(pkg);
+            }
+        }
+
+        Ok(providers)
+    }
+
+    /**
+    return an error if replacing `old` with `new` would take away a soname
+    that another installed package needs and nothing else provides
+
+    This catches library upgrades that bump a soname before the packages
+    linked against the old one are rebuilt.
+    */
+    pub fn check_soname_upgrade(&self, old: &Pkg, new: &Pkg) -> Result<(), AetherError> {
+        let kept = new.soname_provides()?;
+        let dropped: Vec<Soname> = old
+            .soname_provides()?
+            .into_iter()
+            .filter(|soname| !kept.contains(soname))
+            .collect();
+        if dropped.is_empty() {
+            return Ok(());
+        }
+
+        // reading ELF files is the slow part, so each package's are read once
+        let mut others = vec![];
+        for pkg in self.pkgs() {
+            if pkg.get_refstr() != old.get_refstr() {
+                others.push((pkg, sonames_of(&pkg.elf_files()?)));
+            }
+        }
+
+        for soname in dropped {
+            let depend = soname.to_depend();
+            let provided = others.iter().any(|(pkg, (provides, _))| {
+                depend.satisfied_by_provides(&pkg.pkginfo.provides) || provides.contains(&soname)
+            });
+            if provided {
+                continue;
+            }
+
+            if let Some((pkg, _)) = others
+                .iter()
+                .find(|(pkg, (_, needs))| pkg.needs_soname(&soname, needs))
+            {
+                return Err(AetherError::UnsatisfiedDepend {
+                    depend: soname.to_string(),
+                    note: format!(
+                        "{} needs it, and {} doesn't provide it",
+                        pkg.get_refstr(),
+                        new.get_refstr()
+                    ),
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,286 @@
+use crate::{sha256sum, store_dir, AetherError, Pkg};
+use scan_dir::ScanDir;
+use std::collections::HashMap;
+use std::fs::{
+    copy, create_dir_all, hard_link, metadata, read_link, remove_file, rename, set_permissions,
+    symlink_metadata, File, Permissions,
+};
+use std::os::unix::fs::{symlink, MetadataExt, PermissionsExt};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+/// what [`Store::gc`] removed
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GcReport {
+    pub objects: usize,
+    pub bytes: u64,
+}
+
+/**
+A content-addressed store of package files, shared by every environment
+
+Each distinct file is stored once, keyed by its sha256 digest and mode, and
+hardlinked into package directories; when hardlinking isn't possible (e.g.
+across filesystems) it is reflinked, or copied as a last resort. Stored
+objects are read-only, so editing an installed file can't corrupt other
+packages.
+
+# Public methods:
+```text
+// materialize a package's files into a directory through the store
+Store::import() : pub fn import(&self, pkg: &Pkg, dest: &dyn AsRef<Path>) -> Result<u64>
+
+// delete objects that no package directory links to anymore
+Store::gc() : pub fn gc(&self) -> Result<GcReport>
+```
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Store {
+    path: PathBuf,
+}
+
+impl Store {
+    /// return the store at [`store_dir`]
+    #[must_use]
+    pub fn new() -> Store {
+        Self::at(&store_dir())
+    }
+
+    /// return the store rooted at `path`
+    pub fn at(path: &dyn AsRef<Path>) -> Store {
+        Store {
+            path: path.as_ref().into(),
+        }
+    }
+
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// return where the object with this digest and mode is (or would be)
+    /// stored
+    #[must_use]
+    pub fn object_path(&self, sha256: &str, mode: u32) -> PathBuf {
+        let prefix = sha256.get(..2).unwrap_or("00");
+
+        self.path
+            .join(prefix)
+            .join(format!("{}-{:o}", sha256, mode & 0o7555))
+    }
+
+    /// add a file to the store if it isn't there yet and return its object
+    pub fn add(&self, file: &dyn AsRef<Path>, sha256: &str) -> Result<PathBuf, AetherError> {
+        let file = file.as_ref();
+
+        let mode = metadata(file)
+            .map_err(|source| AetherError::ReadError {
+                file: file.into(),
+                source,
+            })?
+            .mode();
+        let object = self.object_path(sha256, mode);
+
+        if object.exists() {
+            return Ok(object);
+        }
+
+        let parent = object.parent().unwrap();
+        create_dir_all(parent).map_err(|source| AetherError::WriteError {
+            file: parent.into(),
+            source,
+        })?;
+
+        // copy next to the object first so a partial copy never looks valid
+        let partial = object.with_extension("partial");
+        copy(file, &partial).map_err(|source| AetherError::WriteError {
+            file: partial.clone(),
+            source,
+        })?;
+        set_permissions(&partial, Permissions::from_mode(mode & 0o7555)).map_err(|source| {
+            AetherError::WriteError {
+                file: partial.clone(),
+                source,
+            }
+        })?;
+        rename(&partial, &object).map_err(|source| AetherError::WriteError {
+            file: object.clone(),
+            source,
+        })?;
+
+        Ok(object)
+    }
+
+    /**
+    materialize a package's files into `dest` through the store, returning
+    the number of bytes the package's files take up
+
+    Regular files are checked against the sha256 digests in the package's
+    .MTREE where it has one.
+    */
+    pub fn import(&self, pkg: &Pkg, dest: &dyn AsRef<Path>) -> Result<u64, AetherError> {
+        let dest = dest.as_ref();
+
+        let digests: HashMap<PathBuf, String> = pkg
+            .mtree
+            .entries()?
+            .iter()
+            .filter_map(|entry| {
+                let digest = entry.sha256()?;
+                let path = entry.path().strip_prefix("./").unwrap_or(entry.path());
+                let hex = digest.iter().map(|b| format!("{:02x}", b)).collect();
+
+                Some((path.to_path_buf(), hex))
+            })
+            .collect();
+
+        create_dir_all(dest).map_err(|source| AetherError::WriteError {
+            file: dest.into(),
+            source,
+        })?;
+
+        let mut files = vec![];
+        ScanDir::all()
+            .walk(&pkg.path, |iter| {
+                for (entry, _) in iter {
+                    files.push(entry.path());
+                }
+            })
+            .map_err(|_| AetherError::InvalidPkg {
+                path: pkg.path.clone(),
+                note: "unable to walk package directory".into(),
+            })?;
+        files.sort();
+
+        let mut bytes = 0;
+        for file in files {
+            let rel = file.strip_prefix(&pkg.path).unwrap();
+            let to = dest.join(rel);
+
+            let file_type = symlink_metadata(&file)
+                .map_err(|source| AetherError::ReadError {
+                    file: file.clone(),
+                    source,
+                })?
+                .file_type();
+
+            if file_type.is_dir() {
+                create_dir_all(&to).map_err(|source| AetherError::WriteError {
+                    file: to.clone(),
+                    source,
+                })?;
+            } else if file_type.is_symlink() {
+                let target = read_link(&file).map_err(|source| AetherError::ReadError {
+                    file: file.clone(),
+                    source,
+                })?;
+
+                symlink(&target, &to).map_err(|source| AetherError::LinkError {
+                    from: target,
+                    to: to.clone(),
+                    source,
+                })?;
+            } else {
+                let sum = sha256sum(&file)?;
+                if let Some(expected) = digests.get(rel) {
+                    if *expected != sum {
+                        return Err(AetherError::ChecksumError {
+                            file,
+                            expected: expected.clone(),
+                            found: sum,
+                        });
+                    }
+                }
+
+                let object = self.add(&file, &sum)?;
+                link_object(&object, &to)?;
+
+                bytes += metadata(&object).map(|meta| meta.len()).unwrap_or(0);
+            }
+        }
+
+        Ok(bytes)
+    }
+
+    /// delete objects that no package directory links to anymore
+    pub fn gc(&self) -> Result<GcReport, AetherError> {
+        if !self.path.exists() {
+            return Ok(GcReport::default());
+        }
+
+        let mut objects = vec![];
+        ScanDir::files()
+            .walk(&self.path, |iter| {
+                for (entry, _) in iter {
+                    objects.push(entry.path());
+                }
+            })
+            .map_err(|_| AetherError::InvalidValue {
+                key: "store".into(),
+                value: self.path.display().to_string(),
+            })?;
+
+        let mut report = GcReport::default();
+        for object in objects {
+            let meta = symlink_metadata(&object).map_err(|source| AetherError::ReadError {
+                file: object.clone(),
+                source,
+            })?;
+
+            // reflinked and copied files don't hold a link to the object, and
+            // never depend on it either
+            if meta.nlink() > 1 {
+                continue;
+            }
+
+            remove_file(&object).map_err(|source| AetherError::WriteError {
+                file: object.clone(),
+                source,
+            })?;
+
+            report.objects += 1;
+            report.bytes += meta.len();
+        }
+
+        Ok(report)
+    }
+}
+
+impl Default for Store {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// hardlink `object` to `to`, falling back to a reflink and then a copy
+fn link_object(object: &Path, to: &Path) -> Result<(), AetherError> {
+    let link_error = |source| AetherError::LinkError {
+        from: object.into(),
+        to: to.into(),
+        source,
+    };
+
+    match hard_link(object, to) {
+        Ok(()) => return Ok(()),
+        // `to` may be a link to the object itself, which truncating below
+        // would destroy
+        Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => return Err(link_error(err)),
+        Err(_) => {}
+    }
+
+    let from = File::open(object).map_err(link_error)?;
+    let dest = File::create(to).map_err(link_error)?;
+
+    // SAFETY: both descriptors stay open for the duration of the call
+    let cloned = unsafe { libc::ioctl(dest.as_raw_fd(), libc::FICLONE, from.as_raw_fd()) };
+    if cloned != 0 {
+        drop(dest);
+        copy(object, to).map_err(link_error)?;
+    }
+
+    let mode = metadata(object).map_err(link_error)?.mode();
+    set_permissions(to, Permissions::from_mode(mode)).map_err(link_error)?;
+
+    Ok(())
+}
@@ -0,0 +1,398 @@
+use crate::AetherError;
+use std::cmp::Ordering;
+use std::fmt;
+
+/// compare two version segments the way rpm and ALPM do
+fn rpmvercmp(a: &str, b: &str) -> Ordering {
+    if a == b {
+        return Ordering::Equal;
+    }
+
+    let a = a.as_bytes();
+    let b = b.as_bytes();
+    let (mut one, mut two) = (0, 0);
+
+    while one < a.len() && two < b.len() {
+        let (sep1, sep2) = (one, two);
+        while one < a.len() && !a[one].is_ascii_alphanumeric() {
+            one += 1;
+        }
+        while two < b.len() && !b[two].is_ascii_alphanumeric() {
+            two += 1;
+        }
+
+        if one >= a.len() || two >= b.len() {
+            break;
+        }
+
+        // a longer run of separators is the newer version
+        if one - sep1 != two - sep2 {
+            return (one - sep1).cmp(&(two - sep2));
+        }
+
+        let (mut end1, mut end2) = (one, two);
+        let is_num = a[one].is_ascii_digit();
+        if is_num {
+            while end1 < a.len() && a[end1].is_ascii_digit() {
+                end1 += 1;
+            }
+            while end2 < b.len() && b[end2].is_ascii_digit() {
+                end2 += 1;
+            }
+        } else {
+            while end1 < a.len() && a[end1].is_ascii_alphabetic() {
+                end1 += 1;
+            }
+            while end2 < b.len() && b[end2].is_ascii_alphabetic() {
+                end2 += 1;
+            }
+        }
+
+        let mut seg1 = &a[one..end1];
+        let mut seg2 = &b[two..end2];
+
+        // segments of different types: numeric is always newer than alpha
+        if seg2.is_empty() {
+            return if is_num {
+                Ordering::Greater
+            } else {
+                Ordering::Less
+            };
+        }
+
+        if is_num {
+            while seg1.len() > 1 && seg1[0] == b'0' {
+                seg1 = &seg1[1..];
+            }
+            while seg2.len() > 1 && seg2[0] == b'0' {
+                seg2 = &seg2[1..];
+            }
+
+            match seg1.len().cmp(&seg2.len()) {
+                Ordering::Equal => {}
+                ordering => return ordering,
+            }
+        }
+
+        match seg1.cmp(seg2) {
+            Ordering::Equal => {}
+            ordering => return ordering,
+        }
+
+        one = end1;
+        two = end2;
+    }
+
+    let rest1 = &a[one.min(a.len())..];
+    let rest2 = &b[two.min(b.len())..];
+
+    if rest1.is_empty() && rest2.is_empty() {
+        return Ordering::Equal;
+    }
+
+    // a remaining alpha segment never beats an empty one
+    if (rest1.is_empty() && !rest2[0].is_ascii_alphabetic())
+        || (!rest1.is_empty() && rest1[0].is_ascii_alphabetic())
+    {
+        Ordering::Less
+    } else {
+        Ordering::Greater
+    }
+}
+
+/// split a full `[epoch:]version[-release]` string into its parts
+fn parse_evr(evr: &str) -> (&str, &str, Option<&str>) {
+    let (epoch, rest) = match evr.split_once(':') {
+        Some((epoch, rest)) if epoch.bytes().all(|b| b.is_ascii_digit()) => {
+            (if epoch.is_empty() { "0" } else { epoch }, rest)
+        }
+        _ => ("0", evr),
+    };
+
+    match rest.rsplit_once('-') {
+        Some((version, release)) => (epoch, version, Some(release)),
+        None => (epoch, rest, None),
+    }
+}
+
+/**
+compare two package versions like ALPM's `vercmp`
+
+The release is only compared when both versions have one, so `1.0` matches
+both `1.0-1` and `1.0-2`.
+*/
+#[must_use]
+pub fn vercmp(a: &str, b: &str) -> Ordering {
+    if a == b {
+        return Ordering::Equal;
+    }
+
+    let (epoch1, ver1, rel1) = parse_evr(a);
+    let (epoch2, ver2, rel2) = parse_evr(b);
+
+    rpmvercmp(epoch1, epoch2)
+        .then_with(|| rpmvercmp(ver1, ver2))
+        .then_with(|| match (rel1, rel2) {
+            (Some(rel1), Some(rel2)) => rpmvercmp(rel1, rel2),
+            _ => Ordering::Equal,
+        })
+}
+
+/// a comparison operator in a dependency or constraint string
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VersionOp {
+    Lt,
+    Le,
+    Eq,
+    Ge,
+    Gt,
+}
+
+impl VersionOp {
+    /// return whether `ordering` (of a version against the constraint's
+    /// version) satisfies this operator
+    #[must_use]
+    pub fn matches(self, ordering: Ordering) -> bool {
+        match self {
+            VersionOp::Lt => ordering == Ordering::Less,
+            VersionOp::Le => ordering != Ordering::Greater,
+            VersionOp::Eq => ordering == Ordering::Equal,
+            VersionOp::Ge => ordering != Ordering::Less,
+            VersionOp::Gt => ordering == Ordering::Greater,
+        }
+    }
+}
+
+impl fmt::Display for VersionOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let op = match self {
+            VersionOp::Lt => "<",
+            VersionOp::Le => "<=",
+            VersionOp::Eq => "=",
+            VersionOp::Ge => ">=",
+            VersionOp::Gt => ">",
+        };
+
+        write!(f, "{}", op)
+    }
+}
+
+/**
+A version requirement such as `>=1.2` or `=3:4.5-1`; `*` or an empty string
+matches every version
+
+# Public methods:
+```text
+// parse a constraint like ">=1.2"
+Constraint::parse() : pub fn parse(s: &str) -> Result<Constraint>
+
+// keep a malformed constraint as one no version satisfies
+Constraint::unsatisfiable() : pub fn unsatisfiable(s: &str) -> Constraint
+
+// return whether a version satisfies this constraint
+Constraint::matches() : pub fn matches(&self, version: &str) -> bool
+```
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Constraint {
+    pub op: Option<VersionOp>,
+    pub version: String,
+}
+
+impl Constraint {
+    /// a constraint that every version satisfies
+    #[must_use]
+    pub fn any() -> Constraint {
+        Constraint {
+            op: None,
+            version: String::new(),
+        }
+    }
+
+    /// split a constraint into its operator, `=` if there's none, and the
+    /// trimmed version after it
+    fn split(s: &str) -> (VersionOp, &str) {
+        let (op, version) = if let Some(v) = s.strip_prefix(">=") {
+            (VersionOp::Ge, v)
+        } else if let Some(v) = s.strip_prefix("<=") {
+            (VersionOp::Le, v)
+        } else if let Some(v) = s.strip_prefix('>') {
+            (VersionOp::Gt, v)
+        } else if let Some(v) = s.strip_prefix('<') {
+            (VersionOp::Lt, v)
+        } else if let Some(v) = s.strip_prefix('=') {
+            (VersionOp::Eq, v)
+        } else {
+            (VersionOp::Eq, s)
+        };
+
+        (op, version.trim())
+    }
+
+    /// return whether `version` can be compared against at all
+    fn valid_version(version: &str) -> bool {
+        !version.is_empty() && !version.contains(char::is_whitespace)
+    }
+
+    /// parse a constraint like ">=1.2"
+    pub fn parse(s: &str) -> Result<Constraint, AetherError> {
+        let s = s.trim();
+        if s.is_empty() || s == "*" {
+            return Ok(Self::any());
+        }
+
+        let (op, version) = Self::split(s);
+        if !Self::valid_version(version) {
+            return Err(AetherError::InvalidValue {
+                key: "version constraint".into(),
+                value: s.into(),
+            });
+        }
+
+        Ok(Constraint {
+            op: Some(op),
+            version: version.into(),
+        })
+    }
+
+    /// return a constraint no version satisfies for the malformed constraint
+    /// `s`, such as `>=` or `>= 1 2`, which still displays as written
+    #[must_use]
+    pub fn unsatisfiable(s: &str) -> Constraint {
+        let (op, version) = Self::split(s.trim());
+
+        Constraint {
+            op: Some(op),
+            version: version.into(),
+        }
+    }
+
+    /// return whether `version` satisfies this constraint
+    #[must_use]
+    pub fn matches(&self, version: &str) -> bool {
+        match self.op {
+            None => true,
+            Some(_) if !Self::valid_version(&self.version) => false,
+            Some(op) => op.matches(vercmp(version, &self.version)),
+        }
+    }
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.op {
+            None => write!(f, "*"),
+            Some(op) => write!(f, "{}{}", op, self.version),
+        }
+    }
+}
+
+/**
+A parsed dependency string such as `glibc>=2.35` or `libfoo.so=1-64`
+
+The same format is used by `depend`, `provides` and `conflict` entries.
+
+# Public methods:
+```text
+// parse a dependency string
+Depend::parse() : pub fn parse(s: &str) -> Depend
+
+// parse a dependency string, failing on a malformed constraint
+Depend::try_parse() : pub fn try_parse(s: &str) -> Result<Depend>
+
+// return whether a package with this name and version satisfies the dependency
+Depend::satisfied_by() : pub fn satisfied_by(&self, name: &str, version: &str) -> bool
+
+// return whether any of a package's provides satisfies the dependency
+Depend::satisfied_by_provides() : pub fn satisfied_by_provides(&self, provides: &[String]) -> bool
+```
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(into = "String", from = "String"))]
+pub struct Depend {
+    pub name: String,
+    pub constraint: Constraint,
+}
+
+impl Depend {
+    /// parse a dependency string; a malformed constraint like `foo>=` is
+    /// kept as one nothing satisfies, see [`Constraint::unsatisfiable`]
+    #[must_use]
+    pub fn parse(s: &str) -> Depend {
+        Self::try_parse(s).unwrap_or_else(|_| {
+            let s = s.trim();
+            let i = s.find(['<', '>', '=']).unwrap();
+
+            Depend {
+                name: s[..i].into(),
+                constraint: Constraint::unsatisfiable(&s[i..]),
+            }
+        })
+    }
+
+    /// parse a dependency string, failing on a malformed constraint
+    pub fn try_parse(s: &str) -> Result<Depend, AetherError> {
+        let s = s.trim();
+
+        match s.find(['<', '>', '=']) {
+            Some(i) => Ok(Depend {
+                name: s[..i].into(),
+                constraint: Constraint::parse(&s[i..])?,
+            }),
+            None => Ok(Depend {
+                name: s.into(),
+                constraint: Constraint::any(),
+            }),
+        }
+    }
+
+    /// return whether a package with this name and version satisfies the
+    /// dependency
+    #[must_use]
+    pub fn satisfied_by(&self, name: &str, version: &str) -> bool {
+        self.name == name && self.constraint.matches(version)
+    }
+
+    /// return whether any entry of a package's `provides` satisfies the
+    /// dependency; unversioned provides only satisfy unversioned dependencies
+    #[must_use]
+    pub fn satisfied_by_provides(&self, provides: &[String]) -> bool {
+        provides.iter().any(|provide| {
+            let provide = Depend::parse(provide);
+
+            if provide.name != self.name {
+                return false;
+            }
+
+            match (&self.constraint.op, &provide.constraint.op) {
+                (None, _) => true,
+                (Some(_), Some(VersionOp::Eq)) => {
+                    self.constraint.matches(&provide.constraint.version)
+                }
+                (Some(_), _) => false,
+            }
+        })
+    }
+}
+
+impl fmt::Display for Depend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.constraint.op {
+            None => write!(f, "{}", self.name),
+            Some(_) => write!(f, "{}{}", self.name, self.constraint),
+        }
+    }
+}
+
+impl From<Depend> for String {
+    fn from(depend: Depend) -> String {
+        depend.to_string()
+    }
+}
+
+impl From<String> for Depend {
+    fn from(s: String) -> Depend {
+        Depend::parse(&s)
+    }
+}
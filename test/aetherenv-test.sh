@@ -3,12 +3,11 @@
 set -euo pipefail
 export IFS=$'\n'
 
-root=aetherenv-test/
+env=aetherenv-test
+aether=${AETHER:-aether}
+cache=/var/cache/pacman/pkg
 
-proot='proot'
-binds='-b /var/cache/pacman/pkg:/pkgs'
-
-[ $1 = --clean ] && sudo rm -rf $root/* && shift
+[ $1 = --clean ] && { $aether env destroy $env || true; } && shift
 
 all_depends() {
 		current=${to_search[0]:-}
@@ -37,7 +36,8 @@ done
 
 echo 'resolving dependencies...'
 searched=()
-to_search=(${pkg_names[@]})
+# the command run at the end needs a shell from the environment's /usr
+to_search=(bash ${pkg_names[@]})
 depends=($(all_depends | sort -u))
 
 echo -e 'resolving providers...\n'
@@ -53,6 +53,10 @@ for pkg in ${depends[@]}; do
 done
 sudo pacman -Sw --noconfirm --needed ${depend_pkgs[@]}
 
-pacstrap -cN $root pacman
+$aether env list | grep -F -x -q -- $env || $aether env create $env
+for file in $(pacman -Sp --print-format '%f' ${depend_pkgs[@]}); do
+    $aether --env $env install $cache/$file
+done
 
-bash -c "$proot ${binds[@]} -S $root pacman --needed --noconfirm -S ${depend_pkgs[@]}"
\ No newline at end of file
+# the packages are mounted over /usr in a user namespace, no proot or root
+$aether --env $env env run -- sh -c "${AETHERENV_RUN:-ls /usr/bin}"
\ No newline at end of file
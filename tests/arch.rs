@@ -0,0 +1,35 @@
+use libaether::Architectures;
+
+fn arches(accepted: &[&str]) -> Architectures {
+    let accepted: Vec<String> = accepted.iter().map(|arch| arch.to_string()).collect();
+
+    Architectures::new(&accepted)
+}
+
+fn rank(arches: &Architectures, arch: &[&str]) -> Option<usize> {
+    let arch: Vec<String> = arch.iter().map(|arch| arch.to_string()).collect();
+
+    arches.rank(&arch)
+}
+
+#[test]
+fn microarchitecture_levels_fall_back() {
+    let accepted = arches(&["x86_64_v3", "any"]);
+    assert_eq!(accepted.accepted(), ["x86_64_v3", "x86_64_v2", "x86_64"]);
+
+    assert_eq!(rank(&accepted, &["x86_64_v3"]), Some(0));
+    assert_eq!(rank(&accepted, &["x86_64", "x86_64_v2"]), Some(1));
+    assert_eq!(rank(&accepted, &["x86_64"]), Some(2));
+    assert_eq!(rank(&accepted, &["x86_64_v4"]), None);
+    assert_eq!(rank(&accepted, &["aarch64"]), None);
+}
+
+#[test]
+fn any_is_accepted_last() {
+    let accepted = arches(&["aarch64"]);
+
+    assert_eq!(rank(&accepted, &["aarch64"]), Some(0));
+    assert_eq!(rank(&accepted, &["any"]), Some(1));
+    assert_eq!(rank(&accepted, &[]), Some(1));
+    assert_eq!(rank(&accepted, &["x86_64"]), None);
+}
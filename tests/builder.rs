@@ -0,0 +1,172 @@
+mod common;
+
+use libaether::{
+    sha256sum, AetherError, BuildInfo, BuildInfoFormat, ParseMode, Pkg, PkgBuilder, PkgInfo,
+};
+use std::fs::{create_dir_all, remove_dir_all, write};
+use std::os::unix::fs::{symlink, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+/// stage `foo` in `dir/root` with an executable, a data file and a symlink
+fn stage(dir: &Path) -> PathBuf {
+    let root = dir.join("root");
+    create_dir_all(root.join("usr/bin")).unwrap();
+    create_dir_all(root.join("usr/share/foo")).unwrap();
+
+    let exec = root.join("usr/bin/foo");
+    write(&exec, "#!/bin/sh\necho foo\n").unwrap();
+    std::fs::set_permissions(&exec, std::fs::Permissions::from_mode(0o755)).unwrap();
+    write(root.join("usr/share/foo/data"), "data\n").unwrap();
+    symlink("foo", root.join("usr/bin/bar")).unwrap();
+
+    root
+}
+
+fn pkginfo() -> PkgInfo {
+    let mut pkginfo = PkgInfo::new();
+    pkginfo.pkgname = "foo".into();
+    pkginfo.pkgver = "1.0-1".into();
+    pkginfo.pkgdesc = "a test package".into();
+    pkginfo.builddate = 1_700_000_000;
+    pkginfo.arch = vec!["x86_64".into()];
+    pkginfo.license = vec!["MIT".into()];
+    pkginfo.depend = vec!["glibc>=2.35".into()];
+
+    pkginfo
+}
+
+#[test]
+fn build_writes_metadata_that_parses_back() {
+    let dir = common::scratch_dir("builder", "metadata");
+    let root = stage(&dir);
+    let scriptlet = dir.join("foo.install");
+    write(&scriptlet, "post_install() { true; }\n").unwrap();
+
+    let mut builder = PkgBuilder::new(&root, pkginfo());
+    builder.install = Some(scriptlet);
+    let archive = builder.build(&dir).unwrap();
+    assert_eq!(archive, dir.join("foo-1.0-1-x86_64.pkg.tar.zst"));
+
+    // nothing is written that the parsers don't know
+    let parsed = PkgInfo::parse_with(&root.join(".PKGINFO"), ParseMode::Strict).unwrap();
+    let mut expected = pkginfo();
+    // the apparent size of directories depends on the filesystem, but
+    // the executable, the data file and the symlink's target are counted
+    assert!(parsed.size >= 19 + 5 + 3);
+    expected.size = parsed.size;
+    expected.xdata = vec!["pkgtype=pkg".into()];
+    assert_eq!(parsed, expected);
+
+    let buildinfo = BuildInfo::parse_with(&root.join(".BUILDINFO"), ParseMode::Strict).unwrap();
+    assert_eq!(buildinfo.format, BuildInfoFormat::V2);
+    assert_eq!(
+        (buildinfo.pkgname.as_str(), buildinfo.pkgbase.as_str()),
+        ("foo", "foo")
+    );
+    assert_eq!(buildinfo.pkgver, "1.0-1");
+    assert_eq!(buildinfo.pkgarch, ["x86_64"]);
+    assert_eq!(buildinfo.builddate, 1_700_000_000);
+    assert_eq!(buildinfo.startdir, root.display().to_string());
+    assert_eq!(buildinfo.buildtool, "libaether");
+
+    // and the archive is a package the rest of the library can read
+    let pkg = Pkg::from_archive(&archive, &dir.join("extract")).unwrap();
+    assert_eq!(pkg.pkginfo, parsed);
+    assert_eq!(pkg.buildinfo, Some(buildinfo));
+    assert!(pkg.scriptlet.is_some());
+    assert_eq!(
+        std::fs::read_to_string(pkg.path.join("usr/share/foo/data")).unwrap(),
+        "data\n"
+    );
+    assert_eq!(
+        std::fs::read_link(pkg.path.join("usr/bin/bar")).unwrap(),
+        Path::new("foo")
+    );
+
+    let entries = pkg.mtree.entries().unwrap();
+    let paths: Vec<&Path> = entries.iter().map(|entry| entry.path()).collect();
+    assert_eq!(
+        paths,
+        [
+            "./.BUILDINFO",
+            "./.INSTALL",
+            "./.PKGINFO",
+            "./usr",
+            "./usr/bin",
+            "./usr/bin/bar",
+            "./usr/bin/foo",
+            "./usr/share",
+            "./usr/share/foo",
+            "./usr/share/foo/data",
+        ]
+        .map(Path::new)
+    );
+    for entry in &entries {
+        assert_eq!(entry.uid(), Some(0), "{}", entry.path().display());
+        assert_eq!(entry.gid(), Some(0), "{}", entry.path().display());
+    }
+
+    let foo = &entries[6];
+    let hex: String = foo
+        .sha256()
+        .unwrap()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    assert_eq!(hex, sha256sum(&pkg.path.join("usr/bin/foo")).unwrap());
+    assert_eq!(entries[5].link(), Some(Path::new("foo")));
+
+    remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn build_keeps_a_given_buildinfo() {
+    let dir = common::scratch_dir("builder", "buildinfo");
+    let root = stage(&dir);
+
+    let mut buildinfo = BuildInfo::new();
+    buildinfo.pkgname = "foo".into();
+    buildinfo.pkgbase = "foo-base".into();
+    buildinfo.pkgver = "1.0-1".into();
+    buildinfo.packager = "Someone <someone@example.com>".into();
+    buildinfo.buildenv = vec!["!distcc".into(), "color".into()];
+
+    let mut builder = PkgBuilder::new(&root, pkginfo());
+    builder.buildinfo = Some(buildinfo.clone());
+    let archive = builder.build(&dir).unwrap();
+
+    let pkg = Pkg::from_archive(&archive, &dir.join("extract")).unwrap();
+    assert_eq!(pkg.buildinfo, Some(buildinfo));
+    assert!(pkg.scriptlet.is_none());
+
+    remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn build_rejects_invalid_names() {
+    let dir = common::scratch_dir("builder", "names");
+    let root = stage(&dir);
+
+    for (key, value) in [
+        ("pkgname", ""),
+        ("pkgname", "foo bar"),
+        ("pkgver", ""),
+        ("pkgver", "1.0 -1"),
+    ] {
+        let mut pkginfo = pkginfo();
+        match key {
+            "pkgname" => pkginfo.pkgname = value.into(),
+            _ => pkginfo.pkgver = value.into(),
+        }
+
+        match PkgBuilder::new(&root, pkginfo).build(&dir) {
+            Err(AetherError::InvalidValue {
+                key: found,
+                value: rejected,
+            }) => assert_eq!((found.as_str(), rejected.as_str()), (key, value)),
+            other => panic!("{} {:?}: {:?}", key, value, other),
+        }
+    }
+
+    remove_dir_all(&dir).unwrap();
+}
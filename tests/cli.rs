@@ -0,0 +1,695 @@
+#![cfg(feature = "cli")]
+
+use libaether::{PkgBuilder, PkgInfo};
+use serde_json::Value;
+use std::fs::{create_dir_all, remove_dir_all, write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// a scratch directory used as `$HOME`, so nothing outside it is touched
+fn scratch_home(name: &str) -> PathBuf {
+    let home = std::env::temp_dir().join(format!("libaether-cli-{}-{}", name, std::process::id()));
+    let _ = remove_dir_all(&home);
+    create_dir_all(&home).unwrap();
+
+    home
+}
+
+fn aether(home: &Path, args: &[&str]) -> Output {
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_aether"));
+    for (key, _) in std::env::vars() {
+        if key.starts_with("XDG_") {
+            cmd.env_remove(key);
+        }
+    }
+
+    cmd.env("HOME", home)
+        .args(["--root", &home.join("envs").to_string_lossy()])
+        .args(["--env", "test", "--scriptlets", "skip", "--json"])
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn json(output: &Output) -> Value {
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stdout)
+    );
+
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+/// build a package `name` with an executable of the same name into `home`
+fn build_pkg(home: &Path, name: &str, desc: &str, groups: &[&str]) -> PathBuf {
+    let mut pkginfo = PkgInfo::new();
+    pkginfo.pkgname = name.into();
+    pkginfo.pkgver = "1.0-1".into();
+    pkginfo.pkgdesc = desc.into();
+    pkginfo.group = groups.iter().map(|group| group.to_string()).collect();
+
+    build_pkginfo(home, pkginfo)
+}
+
+/// build the package described by `pkginfo`, with an executable named after
+/// it, into `home`
+fn build_pkginfo(home: &Path, pkginfo: PkgInfo) -> PathBuf {
+    let name = pkginfo.pkgname.clone();
+    let root = home.join("stage").join(&name);
+    create_dir_all(root.join("usr/bin")).unwrap();
+
+    let exec = root.join("usr/bin").join(&name);
+    write(&exec, format!("#!/bin/sh\necho {}\n", name)).unwrap();
+    std::fs::set_permissions(&exec, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    PkgBuilder::new(&root, pkginfo).build(&home).unwrap()
+}
+
+fn build_hello(home: &Path) -> PathBuf {
+    build_pkg(home, "hello", "says hello", &[])
+}
+
+/// write a sync database `name.db` into `dir` with entries of
+/// `(name, version, desc, extra desc sections)`
+fn write_repo(dir: &Path, name: &str, pkgs: &[(&str, &str, &str, &str)]) {
+    let entries = dir.join(format!("{}.entries", name));
+    for (pkgname, version, desc, extra) in pkgs {
+        let entry = entries.join(format!("{}-{}", pkgname, version));
+        create_dir_all(&entry).unwrap();
+        write(
+            entry.join("desc"),
+            format!(
+                "%FILENAME%\n{}-{}-any.pkg.tar.zst\n\n%NAME%\n{}\n\n%VERSION%\n{}\n\n\
+                 %DESC%\n{}\n\n{}",
+                pkgname, version, pkgname, version, desc, extra
+            ),
+        )
+        .unwrap();
+    }
+
+    let status = Command::new("tar")
+        .arg("-czf")
+        .arg(dir.join(format!("{}.db", name)))
+        .arg("-C")
+        .arg(&entries)
+        .arg(".")
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+#[test]
+fn install_query_verify_remove() {
+    let home = scratch_home("cycle");
+    let archive = build_hello(&home);
+
+    json(&aether(&home, &["env", "create", "test"]));
+
+    let installed = json(&aether(&home, &["install", &archive.to_string_lossy()]));
+    assert_eq!(installed["installed"][0], "hello-1.0-1");
+
+    let list = json(&aether(&home, &["query", "list"]));
+    assert_eq!(list[0]["pkgname"], "hello");
+    assert_eq!(list[0]["pkgdesc"], "says hello");
+
+    let owner = json(&aether(&home, &["query", "owner", "/usr/bin/hello"]));
+    assert_eq!(owner["pkg"], "hello-1.0-1");
+
+    let verified = json(&aether(&home, &["verify"]));
+    assert_eq!(verified[0]["missing"], Value::Array(vec![]));
+    assert_eq!(verified[0]["modified"], Value::Array(vec![]));
+
+    let again = aether(&home, &["install", &archive.to_string_lossy()]);
+    assert!(!again.status.success());
+    let error: Value = serde_json::from_slice(&again.stdout).unwrap();
+    assert_eq!(error["error"]["kind"], "AlreadyExists");
+
+    let removed = json(&aether(&home, &["remove", "hello"]));
+    assert_eq!(removed["removed"][0], "hello-1.0-1");
+    let deleted = &removed["deleted"][0];
+    let installed = home.join("envs/test/pkg/hello-1.0-1");
+    assert_eq!(deleted["path"], installed.to_string_lossy().as_ref());
+    // the generation before the removal still needs it
+    assert_eq!(deleted["kept"], installed.to_string_lossy().as_ref());
+    assert_eq!(deleted["files"], Value::Array(vec![]));
+    assert!(installed.join("usr/bin/hello").exists());
+    assert!(!home.join("envs/test/bin/hello").is_symlink());
+
+    let list = json(&aether(&home, &["query", "list"]));
+    assert_eq!(list, Value::Array(vec![]));
+
+    let _ = remove_dir_all(&home);
+}
+
+#[test]
+fn install_extracts_into_a_clean_directory_and_cleans_up() {
+    let home = scratch_home("extract");
+    let archive = build_hello(&home);
+    let extract = home.join(".cache/aether/extract");
+
+    // left behind by an install that was interrupted
+    let stale = extract.join("hello-1.0-1-any.pkg.tar.zst/usr/bin");
+    create_dir_all(&stale).unwrap();
+    write(stale.join("stale"), "").unwrap();
+
+    json(&aether(&home, &["env", "create", "test"]));
+    json(&aether(&home, &["install", &archive.to_string_lossy()]));
+    let installed = home.join("envs/test/pkg/hello-1.0-1");
+    assert!(installed.join("usr/bin/hello").exists());
+    assert!(!installed.join("usr/bin/stale").exists());
+    assert!(!extract.join("hello-1.0-1-any.pkg.tar.zst").exists());
+
+    // a repository archive that doesn't extract leaves nothing behind either
+    write_repo(&home, "broken", &[("broken", "1-1", "broken", "")]);
+    write(home.join("broken-1-1-any.pkg.tar.zst"), "not an archive").unwrap();
+    create_dir_all(extract.join("broken-1-1/usr")).unwrap();
+    let repo = format!("broken={}", home.display());
+    let failed = aether(&home, &["--repo", &repo, "install", "broken"]);
+    assert!(!failed.status.success());
+    assert!(!extract.join("broken-1-1").exists());
+
+    let _ = remove_dir_all(&home);
+}
+
+#[test]
+fn search_ranks_and_marks_installed() {
+    let home = scratch_home("search");
+    let archive = build_hello(&home);
+    write_repo(
+        &home,
+        "extra",
+        &[
+            ("misc", "1-1", "also says hello", ""),
+            ("greeter", "2-1", "greets", "%PROVIDES%\nhello=1.0\n"),
+            ("hello", "1.0-1", "says hello", ""),
+            ("hello-extra", "1-1", "more", ""),
+            ("tools", "1-1", "tools", "%GROUPS%\nhello-group\n"),
+        ],
+    );
+    let repo = format!("extra={}", home.display());
+
+    json(&aether(&home, &["env", "create", "test"]));
+    json(&aether(&home, &["install", &archive.to_string_lossy()]));
+
+    let found = json(&aether(&home, &["--repo", &repo, "search", "HELLO"]));
+    let found: Vec<(&str, &str, bool)> = found
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|result| {
+            (
+                result["pkginfo"]["pkgname"].as_str().unwrap(),
+                result["matched"].as_str().unwrap(),
+                !result["installed"].is_null(),
+            )
+        })
+        .collect();
+
+    assert_eq!(
+        found,
+        [
+            ("hello", "exact", true),
+            ("hello-extra", "name", false),
+            ("greeter", "provides", false),
+            ("tools", "group", false),
+            ("misc", "description", false),
+        ]
+    );
+
+    let found = json(&aether(
+        &home,
+        &["--repo", &repo, "search", "--regex", "^hel+o$"],
+    ));
+    assert_eq!(found.as_array().unwrap().len(), 2);
+
+    let _ = remove_dir_all(&home);
+}
+
+#[test]
+fn install_group_with_selection() {
+    let home = scratch_home("group");
+    build_pkg(&home, "a", "a", &["kit"]);
+    build_pkg(&home, "b", "b", &["kit"]);
+    build_pkg(&home, "c", "c", &["kit", "other"]);
+    write_repo(
+        &home,
+        "extra",
+        &[
+            ("a", "1.0-1", "a", "%GROUPS%\nkit\n"),
+            ("b", "1.0-1", "b", "%GROUPS%\nkit\n"),
+            ("c", "1.0-1", "c", "%GROUPS%\nkit\nother\n"),
+        ],
+    );
+    let repo = format!("extra={}", home.display());
+
+    json(&aether(&home, &["env", "create", "test"]));
+
+    let groups = json(&aether(&home, &["--repo", &repo, "groups"]));
+    assert_eq!(groups["kit"], serde_json::json!(["a", "b", "c"]));
+
+    let installed = json(&aether(
+        &home,
+        &["--repo", &repo, "install", "kit", "--select", "^b"],
+    ));
+    assert_eq!(
+        installed["installed"],
+        serde_json::json!(["a-1.0-1", "c-1.0-1"])
+    );
+
+    // installed members are skipped
+    let installed = json(&aether(&home, &["--repo", &repo, "install", "kit"]));
+    assert_eq!(installed["installed"], serde_json::json!(["b-1.0-1"]));
+
+    let groups = json(&aether(&home, &["query", "groups", "kit"]));
+    assert_eq!(groups, serde_json::json!({"kit": ["a", "b", "c"]}));
+
+    let _ = remove_dir_all(&home);
+}
+
+#[test]
+fn optdepends_and_orphans() {
+    let home = scratch_home("optdepends");
+    let mut pkginfo = PkgInfo::new();
+    pkginfo.pkgname = "editor".into();
+    pkginfo.pkgver = "1.0-1".into();
+    pkginfo.optdepend = vec!["spell: spell checking".into(), "lint".into()];
+    let editor = build_pkginfo(&home, pkginfo);
+    let spell = build_pkg(&home, "spell", "checks spelling", &[]);
+
+    json(&aether(&home, &["env", "create", "test"]));
+
+    let installed = json(&aether(&home, &["install", &editor.to_string_lossy()]));
+    let missing = &installed["missing_optdepends"]["editor-1.0-1"];
+    assert_eq!(missing[0]["depend"], "spell");
+    assert_eq!(missing[0]["reason"], "spell checking");
+    assert_eq!(missing[1]["depend"], "lint");
+
+    let enabled = json(&aether(&home, &["query", "enables", "spell"]));
+    assert_eq!(enabled[0]["pkg"], "editor-1.0-1");
+
+    let installed = json(&aether(
+        &home,
+        &[
+            "install",
+            &spell.to_string_lossy(),
+            "--dependency-of",
+            "editor",
+        ],
+    ));
+    assert_eq!(installed["installed"][0], "spell-1.0-1");
+
+    let optdeps = json(&aether(&home, &["query", "optdeps", "editor"]));
+    assert_eq!(optdeps[0]["installed"], true);
+    assert_eq!(optdeps[1]["installed"], false);
+
+    let orphans = json(&aether(&home, &["query", "orphans"]));
+    assert_eq!(orphans, Value::Array(vec![]));
+
+    json(&aether(&home, &["remove", "editor"]));
+    let orphans = json(&aether(&home, &["query", "orphans"]));
+    assert_eq!(orphans, serde_json::json!(["spell-1.0-1"]));
+
+    let _ = remove_dir_all(&home);
+}
+
+#[test]
+fn install_checks_the_architecture() {
+    let home = scratch_home("arch");
+    let foreign = if std::env::consts::ARCH == "aarch64" {
+        "x86_64"
+    } else {
+        "aarch64"
+    };
+    let mut pkginfo = PkgInfo::new();
+    pkginfo.pkgname = "native".into();
+    pkginfo.pkgver = "1.0-1".into();
+    pkginfo.arch = vec![foreign.into()];
+    let archive = build_pkginfo(&home, pkginfo);
+
+    json(&aether(&home, &["env", "create", "test"]));
+
+    let refused = aether(&home, &["install", &archive.to_string_lossy()]);
+    assert!(!refused.status.success());
+    let error: Value = serde_json::from_slice(&refused.stdout).unwrap();
+    assert_eq!(error["error"]["kind"], "IncompatibleArch");
+
+    let installed = json(&aether(
+        &home,
+        &["--arch", foreign, "install", &archive.to_string_lossy()],
+    ));
+    assert_eq!(installed["installed"][0], "native-1.0-1");
+
+    let _ = remove_dir_all(&home);
+}
+
+/// build a package `name` whose files name `/usr` paths, both its own and
+/// ones it doesn't have
+fn build_hardcoded(home: &Path, name: &str) -> PathBuf {
+    let root = home.join("stage").join(name);
+    create_dir_all(root.join("usr/lib/pkgconfig")).unwrap();
+    create_dir_all(root.join("usr/bin")).unwrap();
+
+    write(
+        root.join("usr/lib/pkgconfig").join(format!("{}.pc", name)),
+        "prefix=/usr\nincludedir=/usr/include/missing\n",
+    )
+    .unwrap();
+    let script = root.join("usr/bin").join(format!("{}-run", name));
+    write(&script, format!("#!/usr/bin/{}\n/usr/lib\n", name)).unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    build_pkg(home, name, name, &[])
+}
+
+#[test]
+fn relocate_hardcoded_paths() {
+    let home = scratch_home("relocate");
+    let tool = build_hardcoded(&home, "tool");
+    let kept = build_hardcoded(&home, "kept");
+
+    json(&aether(&home, &["env", "create", "test"]));
+
+    let installed = json(&aether(
+        &home,
+        &[
+            "--relocate",
+            "--no-relocate",
+            "kept",
+            "install",
+            &tool.to_string_lossy(),
+            &kept.to_string_lossy(),
+        ],
+    ));
+    let reports = installed["relocated"].as_array().unwrap();
+    assert_eq!(reports.len(), 1);
+    assert_eq!(reports[0]["pkg"], "tool-1.0-1");
+    let prefix = reports[0]["prefix"].as_str().unwrap();
+
+    let kinds: Vec<&str> = reports[0]["files"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|file| file["kind"].as_str().unwrap())
+        .collect();
+    assert_eq!(kinds, ["shebang", "pkgconfig"]);
+
+    let files = json(&aether(&home, &["query", "files", "tool"]));
+    let read = |suffix: &str| {
+        let file = files
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|file| file.as_str().unwrap())
+            .find(|file| file.ends_with(suffix))
+            .unwrap();
+        std::fs::read_to_string(file).unwrap()
+    };
+    assert_eq!(
+        read("tool-run"),
+        format!("#!{}/bin/tool\n/usr/lib\n", prefix)
+    );
+    assert_eq!(
+        read("tool.pc"),
+        format!("prefix={}\nincludedir=/usr/include/missing\n", prefix)
+    );
+
+    let verified = json(&aether(&home, &["verify"]));
+    for pkg in verified.as_array().unwrap() {
+        assert_eq!(pkg["modified"], Value::Array(vec![]));
+    }
+
+    let _ = remove_dir_all(&home);
+}
+
+/// compile a library with the soname `soname` into `lib` if given, and the
+/// executable `exec` linked against it
+fn compile_foo(soname: &str, lib: Option<&Path>, exec: &Path) {
+    let src = exec.parent().unwrap().join("src");
+    create_dir_all(&src).unwrap();
+    write(src.join("foo.c"), "int foo(void) { return 0; }\n").unwrap();
+    write(
+        src.join("main.c"),
+        "int foo(void);\nint main(void) { return foo(); }\n",
+    )
+    .unwrap();
+
+    let cc = |args: &[&str]| {
+        let status = Command::new("cc").current_dir(&src).args(args).status();
+        assert!(status.unwrap().success());
+    };
+    let soname_flag = format!("-Wl,-soname,{}", soname);
+    cc(&["-shared", "-fPIC", &soname_flag, "-o", "libfoo.so", "foo.c"]);
+    cc(&["-o", "foo-demo", "main.c", "-L.", "-lfoo"]);
+
+    std::fs::rename(src.join("foo-demo"), exec).unwrap();
+    if let Some(lib) = lib {
+        create_dir_all(lib).unwrap();
+        std::fs::rename(src.join("libfoo.so"), lib.join(soname)).unwrap();
+    }
+    remove_dir_all(&src).unwrap();
+}
+
+#[test]
+fn report_missing_shared_libraries() {
+    let home = scratch_home("libs");
+    let stage = home.join("stage");
+    compile_foo(
+        "libfoo.so.1",
+        None,
+        &stage.join("fooapp/usr/bin/fooapp-demo"),
+    );
+    compile_foo(
+        "libfoo.so.1",
+        Some(&stage.join("foolib/usr/lib")),
+        &stage.join("foolib/usr/bin/foolib-demo"),
+    );
+    let app = build_pkg(&home, "fooapp", "fooapp", &[]);
+    let lib = build_pkg(&home, "foolib", "foolib", &[]);
+
+    json(&aether(&home, &["env", "create", "test"]));
+
+    let installed = json(&aether(&home, &["install", &app.to_string_lossy()]));
+    let missing = &installed["missing_libs"]["fooapp-1.0-1"];
+    assert_eq!(missing[0]["needed"], "libfoo.so.1");
+    assert_eq!(missing[0]["file"], "usr/bin/fooapp-demo");
+
+    let installed = json(&aether(&home, &["install", &lib.to_string_lossy()]));
+    assert_eq!(installed["missing_libs"], serde_json::json!({}));
+
+    // the library is in the environment now
+    let missing = json(&aether(&home, &["query", "libs", "fooapp", "--missing"]));
+    assert_eq!(missing, Value::Array(vec![]));
+
+    let libs = json(&aether(&home, &["query", "libs", "fooapp"]));
+    let libs = libs.as_array().unwrap();
+    assert!(libs.iter().any(|lib| lib["needed"] == "libfoo.so.1"
+        && lib["resolved"]
+            .as_str()
+            .unwrap()
+            .contains("foolib-1.0-1/usr/lib")));
+
+    let _ = remove_dir_all(&home);
+}
+
+#[test]
+fn soname_bumps_are_caught_before_upgrading() {
+    let home = scratch_home("sonames");
+    let stage = home.join("stage");
+    let bits = 8 * std::mem::size_of::<usize>();
+    let lib_dir = stage.join("foolib/usr/lib");
+
+    compile_foo(
+        "libfoo.so.1",
+        None,
+        &stage.join("fooapp/usr/bin/fooapp-demo"),
+    );
+    compile_foo(
+        "libfoo.so.1",
+        Some(&lib_dir),
+        &stage.join("foolib/usr/bin/foolib-demo"),
+    );
+    let app = build_pkg(&home, "fooapp", "fooapp", &[]);
+    let lib = build_pkg(&home, "foolib", "foolib", &[]);
+
+    remove_dir_all(&lib_dir).unwrap();
+    compile_foo(
+        "libfoo.so.2",
+        Some(&lib_dir),
+        &stage.join("foolib/usr/bin/foolib-demo"),
+    );
+    let mut pkginfo = PkgInfo::new();
+    pkginfo.pkgname = "foolib".into();
+    pkginfo.pkgver = "2.0-1".into();
+    let bumped = build_pkginfo(&home, pkginfo);
+
+    json(&aether(&home, &["env", "create", "test"]));
+    json(&aether(
+        &home,
+        &["install", &lib.to_string_lossy(), &app.to_string_lossy()],
+    ));
+
+    let provided = format!("libfoo.so=1-{}", bits);
+    let check = json(&aether(&home, &["query", "sonames", "foolib"]));
+    assert_eq!(check["provides"], serde_json::json!([provided]));
+    assert_eq!(check["undeclared_provides"], serde_json::json!([provided]));
+
+    let check = json(&aether(&home, &["query", "sonames", "fooapp"]));
+    assert!(check["needs"]
+        .as_array()
+        .unwrap()
+        .contains(&Value::String(provided.clone())));
+
+    let refused = aether(&home, &["upgrade", &bumped.to_string_lossy()]);
+    assert!(!refused.status.success());
+    let error: Value = serde_json::from_slice(&refused.stdout).unwrap();
+    assert_eq!(error["error"]["kind"], "UnsatisfiedDepend");
+
+    let list = json(&aether(&home, &["query", "list"]));
+    assert!(list
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|pkg| pkg["pkgname"] == "foolib" && pkg["pkgver"] == "1.0-1"));
+
+    let _ = remove_dir_all(&home);
+}
+
+#[test]
+fn wrapper_scripts_find_the_package_libraries() {
+    let home = scratch_home("wrap");
+    let stage = home.join("stage");
+    compile_foo(
+        "libfoo.so.1",
+        Some(&stage.join("foolib/usr/lib")),
+        &stage.join("foolib/usr/bin/foolib-demo"),
+    );
+    let lib = build_pkg(&home, "foolib", "foolib", &[]);
+    let hello = build_hello(&home);
+
+    json(&aether(&home, &["env", "create", "test"]));
+    json(&aether(
+        &home,
+        &[
+            "--wrap-pkg",
+            "foolib",
+            "install",
+            &lib.to_string_lossy(),
+            &hello.to_string_lossy(),
+        ],
+    ));
+
+    let bin = home.join("envs/test/bin");
+    assert!(bin.join("hello").is_symlink());
+
+    let wrapper = bin.join("foolib-demo");
+    assert!(!wrapper.is_symlink());
+    let script = std::fs::read_to_string(&wrapper).unwrap();
+    assert!(script.contains("LD_LIBRARY_PATH="));
+
+    // the library is only found through the wrapper's LD_LIBRARY_PATH
+    let status = Command::new(&wrapper)
+        .env_remove("LD_LIBRARY_PATH")
+        .status();
+    assert!(status.unwrap().success());
+
+    json(&aether(&home, &["remove", "foolib"]));
+    assert!(!wrapper.exists());
+
+    let _ = remove_dir_all(&home);
+}
+
+#[test]
+fn alternatives_pick_one_provider_of_a_command() {
+    let home = scratch_home("alternatives");
+    let mut pkgs = vec![];
+    for name in ["vim", "nvi"] {
+        let bin = home.join("stage").join(name).join("usr/bin");
+        create_dir_all(&bin).unwrap();
+        write(bin.join("vi"), format!("#!/bin/sh\necho {}\n", name)).unwrap();
+        std::fs::set_permissions(bin.join("vi"), std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        pkgs.push(build_pkg(&home, name, name, &[]));
+    }
+
+    json(&aether(&home, &["env", "create", "test"]));
+    for pkg in &pkgs {
+        json(&aether(&home, &["install", &pkg.to_string_lossy()]));
+    }
+
+    let vi = home.join("envs/test/bin/vi");
+    let run_vi = || {
+        let output = Command::new(&vi).output().unwrap();
+        String::from_utf8(output.stdout).unwrap()
+    };
+    // the provider installed first keeps the command
+    assert_eq!(run_vi(), "vim\n");
+
+    let alternatives = json(&aether(&home, &["alternatives", "list"]));
+    assert_eq!(alternatives[0]["name"], "vi");
+    assert_eq!(alternatives[0]["active"], "vim-1.0-1");
+    assert_eq!(alternatives[0]["providers"].as_array().unwrap().len(), 2);
+
+    let chosen = json(&aether(&home, &["alternatives", "set", "vi", "nvi"]));
+    assert_eq!(chosen[0]["choice"], "nvi");
+    assert_eq!(run_vi(), "nvi\n");
+
+    json(&aether(&home, &["alternatives", "auto", "vi"]));
+    json(&aether(&home, &["alternatives", "priority", "vim", "10"]));
+    assert_eq!(run_vi(), "vim\n");
+
+    json(&aether(&home, &["remove", "vim"]));
+    assert_eq!(run_vi(), "nvi\n");
+
+    let _ = remove_dir_all(&home);
+}
+
+#[test]
+fn exports_leave_foreign_files_alone_and_can_be_repaired() {
+    let home = scratch_home("exports");
+    let tool = build_pkg(&home, "tool", "tool", &[]);
+    let hello = build_hello(&home);
+
+    json(&aether(&home, &["env", "create", "test"]));
+    let bin = home.join("envs/test/bin");
+    write(bin.join("tool"), "#!/bin/sh\necho mine\n").unwrap();
+
+    let refused = aether(&home, &["install", &tool.to_string_lossy()]);
+    assert!(!refused.status.success());
+    let error: Value = serde_json::from_slice(&refused.stdout).unwrap();
+    assert_eq!(error["error"]["kind"], "ForeignFile");
+    assert_eq!(
+        std::fs::read_to_string(bin.join("tool")).unwrap(),
+        "#!/bin/sh\necho mine\n"
+    );
+    let list = json(&aether(&home, &["query", "list"]));
+    assert_eq!(list, Value::Array(vec![]));
+
+    let ghost = build_pkg(&home, "ghost", "goes away", &[]);
+    json(&aether(&home, &["install", &hello.to_string_lossy()]));
+    json(&aether(&home, &["install", &ghost.to_string_lossy()]));
+    std::fs::remove_file(bin.join("hello")).unwrap();
+    // deleted behind aether's back, leaving its entry behind
+    remove_dir_all(home.join("envs/test/pkg/ghost-1.0-1")).unwrap();
+
+    let checked = aether(&home, &["exports"]);
+    assert!(!checked.status.success());
+    let check: Value = serde_json::from_slice(&checked.stdout).unwrap();
+    assert_eq!(
+        check["dangling"][0],
+        bin.join("ghost").to_string_lossy().as_ref()
+    );
+    assert_eq!(
+        check["missing"][0],
+        bin.join("hello").to_string_lossy().as_ref()
+    );
+    assert_eq!(check["foreign"], Value::Array(vec![]));
+
+    json(&aether(&home, &["exports", "--repair"]));
+    assert!(bin.join("hello").is_symlink());
+    assert!(!bin.join("ghost").is_symlink());
+    json(&aether(&home, &["exports"]));
+
+    let _ = remove_dir_all(&home);
+}
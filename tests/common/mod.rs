@@ -0,0 +1,29 @@
+//! fixtures shared by the integration tests
+
+use std::fs::{create_dir_all, remove_dir_all};
+use std::path::PathBuf;
+use std::sync::Once;
+
+/// a scratch directory for the test `name` of the file `prefix`, emptied
+/// first; the XDG directories point into the temporary directory so nothing
+/// else is touched
+pub fn scratch_dir(prefix: &str, name: &str) -> PathBuf {
+    static XDG: Once = Once::new();
+    XDG.call_once(|| {
+        let xdg = std::env::temp_dir().join(format!("libaether-xdg-{}", std::process::id()));
+        std::env::set_var("XDG_CACHE_HOME", xdg.join("cache"));
+        std::env::set_var("XDG_DATA_HOME", xdg.join("data"));
+        std::env::set_var("XDG_STATE_HOME", xdg.join("state"));
+    });
+
+    let dir = std::env::temp_dir().join(format!(
+        "libaether-{}-{}-{}",
+        prefix,
+        name,
+        std::process::id()
+    ));
+    let _ = remove_dir_all(&dir);
+    create_dir_all(&dir).unwrap();
+
+    dir
+}
@@ -0,0 +1,182 @@
+mod common;
+
+use libaether::{
+    cache_dir, sha256sum, Environment, LockedPkg, Lockfile, Pkg, PkgBuilder, PkgInfo, RepoSource,
+    ScriptletPolicy,
+};
+use std::ffi::OsStr;
+use std::fs::{create_dir_all, read_link, remove_dir_all, write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+/// build and extract version `ver` of a package `name` shipping each of
+/// `files` under usr
+fn build_pkg(dir: &Path, name: &str, ver: &str, files: &[&str]) -> Pkg {
+    let mut pkginfo = PkgInfo::new();
+    pkginfo.pkgname = name.into();
+    pkginfo.pkgver = ver.into();
+
+    let root = dir.join("stage").join(format!("{}-{}", name, ver));
+    for file in files {
+        let file = root.join("usr").join(file);
+        create_dir_all(file.parent().unwrap()).unwrap();
+        write(&file, ver).unwrap();
+    }
+
+    let archive = PkgBuilder::new(&root, pkginfo).build(&dir).unwrap();
+    let dest = dir.join("extract").join(format!("{}-{}", name, ver));
+
+    Pkg::from_archive(&archive, &dest).unwrap()
+}
+
+#[test]
+fn link_usr_follows_upgrades_and_removals() {
+    let dir = common::scratch_dir("env", "link-usr");
+    let env = Environment::create_in("test", &dir.join("envs")).unwrap();
+    let usr = env.usr_dir().to_path_buf();
+
+    let old = build_pkg(&dir, "foo", "1.0-1", &["share/foo/old", "share/foo/data"]);
+    let bar = build_pkg(&dir, "bar", "1.0-1", &["share/bar/data"]);
+    let mut pkglist = env.pkglist().unwrap();
+    pkglist.set_scriptlet_policy(ScriptletPolicy::Skip);
+    pkglist.install(old).unwrap();
+    pkglist.install(bar).unwrap();
+
+    assert_eq!(env.link_usr().unwrap().len(), 3);
+    let old = pkglist.pkgs()[0].clone();
+    assert!(read_link(usr.join("share/foo/old"))
+        .unwrap()
+        .starts_with(&old.path));
+
+    let new = build_pkg(&dir, "foo", "2.0-1", &["share/foo/data", "share/foo/new"]);
+    pkglist.upgrade(&old, new).unwrap();
+
+    // the old links would clash with the new version's if they were kept
+    let linked = env.link_usr().unwrap();
+    assert_eq!(
+        linked,
+        [usr.join("share/foo/data"), usr.join("share/foo/new")]
+    );
+    assert!(!usr.join("share/foo/old").exists() && !usr.join("share/foo/old").is_symlink());
+    let data = read_link(usr.join("share/foo/data")).unwrap();
+    assert!(data.ends_with("foo-2.0-1/usr/share/foo/data"), "{:?}", data);
+
+    // nothing changed, so nothing is linked again
+    assert!(env.link_usr().unwrap().is_empty());
+
+    let bar = pkglist.pkgs()[0].clone();
+    assert_eq!(bar.pkginfo.pkgname, "bar");
+    pkglist.remove(&bar).unwrap();
+    assert!(env.link_usr().unwrap().is_empty());
+    assert!(!usr.join("share/bar").exists());
+    assert!(usr.join("share/foo/new").exists());
+
+    remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn removed_packages_stay_until_no_generation_needs_them() {
+    let dir = common::scratch_dir("env", "remove");
+    let env = Environment::create_in("test", &dir.join("envs")).unwrap();
+
+    let foo = build_pkg(&dir, "foo", "1.0-1", &["bin/foo"]);
+    let mut pkglist = env.pkglist().unwrap();
+    pkglist.set_scriptlet_policy(ScriptletPolicy::Skip);
+    pkglist.install(foo).unwrap();
+    let installed = env.current_generation().unwrap().unwrap();
+
+    let foo = pkglist.pkgs()[0].clone();
+    let report = pkglist.remove(&foo).unwrap();
+    let removed = env.current_generation().unwrap().unwrap();
+    assert_eq!(report.kept.as_ref(), Some(&foo.path));
+    assert!(report.files.is_empty());
+    assert!(foo.path.join("usr/bin/foo").exists());
+
+    env.rollback_to(installed).unwrap();
+    assert_eq!(env.pkglist().unwrap().pkgs()[0].get_refstr(), "foo-1.0-1");
+
+    env.rollback_to(removed).unwrap();
+    env.delete_generations(SystemTime::now() + Duration::from_secs(1))
+        .unwrap();
+    assert!(!foo.path.exists());
+
+    remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn removing_deletes_files_with_any_name() {
+    let dir = common::scratch_dir("env", "remove-names");
+    let env = Environment::create_in("test", &dir.join("envs")).unwrap();
+
+    let foo = build_pkg(&dir, "foo", "1.0-1", &["bin/foo"]);
+    let mut pkglist = env.pkglist().unwrap();
+    pkglist.set_scriptlet_policy(ScriptletPolicy::Skip);
+    // outside the package directory, so no generation can roll back to it
+    let path = dir.join("elsewhere/foo");
+    pkglist.install_to(foo, &path).unwrap();
+
+    let name = OsStr::from_bytes(b"caf\xe9");
+    write(path.join("usr").join(name), "latin-1").unwrap();
+
+    let foo = pkglist.pkgs()[0].clone();
+    let report = pkglist.remove_from(&foo, &path).unwrap();
+    assert_eq!(report.kept, None);
+    assert!(report.files.contains(&path.join("usr").join(name)));
+    assert!(!path.exists());
+
+    remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn syncing_starts_from_a_clean_extract_dir_and_cleans_up() {
+    let dir = common::scratch_dir("env", "sync");
+    let env = Environment::create_in("test", &dir.join("envs")).unwrap();
+    build_pkg(&dir, "foo", "1.0-1", &["share/foo/data"]);
+    let archive = dir.join("foo-1.0-1-any.pkg.tar.zst");
+
+    // left behind by a sync that was interrupted
+    let extract = cache_dir().join("extract");
+    create_dir_all(extract.join("foo-1.0-1/usr/share/foo")).unwrap();
+    write(extract.join("foo-1.0-1/usr/share/foo/stale"), "").unwrap();
+
+    let locked = |name: &str, filename: &str, sha256: String| LockedPkg {
+        name: name.into(),
+        version: "1.0-1".into(),
+        repo: "local".into(),
+        filename: filename.into(),
+        sha256,
+    };
+    let mut lock = Lockfile {
+        arch: vec![],
+        repos: vec![RepoSource {
+            name: "local".into(),
+            path: dir.clone(),
+        }],
+        packages: vec![locked(
+            "foo",
+            "foo-1.0-1-any.pkg.tar.zst",
+            sha256sum(&archive).unwrap(),
+        )],
+    };
+
+    let report = env.sync_to_lock(&lock).unwrap();
+    assert_eq!(report.installed, ["foo-1.0-1"]);
+    let foo = env.pkg_dir().join("foo-1.0-1");
+    assert!(foo.join("usr/share/foo/data").exists());
+    assert!(!foo.join("usr/share/foo/stale").exists());
+    assert!(!extract.join("foo-1.0-1").exists());
+
+    // an archive that doesn't extract leaves nothing behind either
+    let broken = dir.join("broken-1.0-1-any.pkg.tar.zst");
+    write(&broken, "not an archive").unwrap();
+    lock.packages.push(locked(
+        "broken",
+        "broken-1.0-1-any.pkg.tar.zst",
+        sha256sum(&broken).unwrap(),
+    ));
+    assert!(env.sync_to_lock(&lock).is_err());
+    assert!(!extract.join("broken-1.0-1").exists());
+
+    remove_dir_all(&dir).unwrap();
+}
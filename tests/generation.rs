@@ -0,0 +1,181 @@
+mod common;
+
+use libaether::{AetherError, Environment, Pkg, PkgBuilder, PkgInfo, ScriptletPolicy};
+use std::fs::{create_dir_all, read_link, read_to_string, remove_dir_all, write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// build and extract version `ver` of the package `foo`, with an executable
+/// of the same name
+fn build_foo(dir: &Path, ver: &str) -> Pkg {
+    let mut pkginfo = PkgInfo::new();
+    pkginfo.pkgname = "foo".into();
+    pkginfo.pkgver = ver.into();
+
+    let root = dir.join("stage").join(ver);
+    create_dir_all(root.join("usr/bin")).unwrap();
+    let exec = root.join("usr/bin/foo");
+    write(&exec, format!("#!/bin/sh\necho {}\n", ver)).unwrap();
+    std::fs::set_permissions(&exec, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let archive = PkgBuilder::new(&root, pkginfo).build(&dir).unwrap();
+
+    Pkg::from_archive(&archive, &dir.join("extract").join(ver)).unwrap()
+}
+
+/// create an environment in `dir` with version 1-1 of `foo` installed and
+/// then upgraded to 2-1, returning it and the two package directories
+fn upgraded_env(dir: &Path) -> (Environment, PathBuf, PathBuf) {
+    let env = Environment::create_in("test", &dir.join("envs")).unwrap();
+    let mut pkglist = env.pkglist().unwrap();
+    pkglist.set_scriptlet_policy(ScriptletPolicy::Skip);
+
+    pkglist.install(build_foo(dir, "1-1")).unwrap();
+    let old = pkglist.pkgs()[0].clone();
+    pkglist.upgrade(&old, build_foo(dir, "2-1")).unwrap();
+    let new = pkglist.pkgs()[0].clone();
+
+    (env, old.path, new.path)
+}
+
+fn installed(env: &Environment) -> Vec<String> {
+    let pkglist = env.pkglist().unwrap();
+    pkglist.pkgs().iter().map(Pkg::get_refstr).collect()
+}
+
+#[test]
+fn rollback_re_points_the_links() {
+    let dir = common::scratch_dir("generation", "rollback");
+    let (env, old, new) = upgraded_env(&dir);
+    let link = env.bin_dir().join("foo");
+
+    let generations = env.list_generations().unwrap();
+    assert_eq!(generations.len(), 2);
+    assert_eq!(generations[0].pkgs, ["foo-1-1"]);
+    assert_eq!(generations[1].pkgs, ["foo-2-1"]);
+    assert_eq!(env.current_generation().unwrap(), Some(2));
+    assert_eq!(read_link(&link).unwrap(), new.join("usr/bin/foo"));
+
+    let generation = env.rollback_to(1).unwrap();
+    assert_eq!(generation.links["foo"], old.join("usr/bin/foo"));
+    assert_eq!(env.current_generation().unwrap(), Some(1));
+    assert_eq!(read_link(&link).unwrap(), old.join("usr/bin/foo"));
+    assert_eq!(installed(&env), ["foo-1-1"]);
+
+    // and forward again
+    env.rollback_to(2).unwrap();
+    assert_eq!(read_link(&link).unwrap(), new.join("usr/bin/foo"));
+    assert_eq!(installed(&env), ["foo-2-1"]);
+
+    assert!(matches!(
+        env.rollback_to(3),
+        Err(AetherError::MissingGeneration(3))
+    ));
+
+    remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn deleting_generations_keeps_what_the_current_one_needs() {
+    let dir = common::scratch_dir("generation", "delete");
+    let (env, old, new) = upgraded_env(&dir);
+    let later = SystemTime::now() + Duration::from_secs(1);
+
+    // nothing is older than the epoch
+    assert!(env
+        .delete_generations(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .is_empty());
+    assert!(old.is_dir() && new.is_dir());
+
+    // the current generation survives however old it is
+    env.rollback_to(1).unwrap();
+    assert_eq!(env.delete_generations(later).unwrap(), [2]);
+    assert!(old.join("usr/bin/foo").exists());
+    assert!(!new.exists());
+    assert_eq!(installed(&env), ["foo-1-1"]);
+    assert!(matches!(
+        env.rollback_to(2),
+        Err(AetherError::MissingGeneration(2))
+    ));
+
+    assert!(env.delete_generations(later).unwrap().is_empty());
+    assert!(old.is_dir());
+
+    remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn rollback_refuses_to_replace_foreign_files() {
+    let dir = common::scratch_dir("generation", "foreign");
+    let env = Environment::create_in("test", &dir.join("envs")).unwrap();
+    let mut pkglist = env.pkglist().unwrap();
+    pkglist.set_scriptlet_policy(ScriptletPolicy::Skip);
+
+    pkglist.install(build_foo(&dir, "1-1")).unwrap();
+    let foo = pkglist.pkgs()[0].clone();
+    pkglist.remove(&foo).unwrap();
+    assert_eq!(env.current_generation().unwrap(), Some(2));
+
+    // the user's own foo took the free name in the meantime
+    let link = env.bin_dir().join("foo");
+    write(&link, "#!/bin/sh\necho mine\n").unwrap();
+
+    match env.rollback_to(1) {
+        Err(AetherError::ForeignFile(file)) => assert_eq!(file, link),
+        other => panic!("{:?}", other),
+    }
+    assert_eq!(read_to_string(&link).unwrap(), "#!/bin/sh\necho mine\n");
+    assert_eq!(env.current_generation().unwrap(), Some(2));
+    assert!(installed(&env).is_empty());
+
+    std::fs::remove_file(&link).unwrap();
+    env.rollback_to(1).unwrap();
+    assert_eq!(read_link(&link).unwrap(), foo.path.join("usr/bin/foo"));
+
+    remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn failed_exports_leave_the_old_entry() {
+    let dir = common::scratch_dir("generation", "replace");
+    let (env, _, new) = upgraded_env(&dir);
+    let link = env.bin_dir().join("foo");
+
+    // a directory where the new entry is created makes exporting fail
+    create_dir_all(env.bin_dir().join(".foo.aether-new/x")).unwrap();
+    assert!(env.rollback_to(1).is_err());
+    assert_eq!(read_link(&link).unwrap(), new.join("usr/bin/foo"));
+    assert!(env.owns_export("foo").unwrap());
+
+    remove_dir_all(env.bin_dir().join(".foo.aether-new")).unwrap();
+    env.rollback_to(1).unwrap();
+    assert!(!env.bin_dir().join(".foo.aether-new").exists());
+
+    remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn unrecorded_entries_are_owned_only_if_the_generation_lists_them() {
+    let dir = common::scratch_dir("generation", "owned");
+    let (env, old, new) = upgraded_env(&dir);
+
+    // as if exported before entries were recorded
+    std::fs::remove_file(env.db_dir().join("exports.toml")).unwrap();
+    assert!(env.owns_export("foo").unwrap());
+
+    // pointing into the package directory isn't enough by itself
+    std::os::unix::fs::symlink(new.join("usr/bin/foo"), env.bin_dir().join("bar")).unwrap();
+    assert!(!env.owns_export("bar").unwrap());
+    std::fs::remove_file(env.bin_dir().join("foo")).unwrap();
+    std::os::unix::fs::symlink(old.join("usr/bin/foo"), env.bin_dir().join("foo")).unwrap();
+    assert!(!env.owns_export("foo").unwrap());
+
+    assert!(matches!(
+        env.rollback_to(1),
+        Err(AetherError::ForeignFile(_))
+    ));
+
+    remove_dir_all(&dir).unwrap();
+}
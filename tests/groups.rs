@@ -0,0 +1,31 @@
+use libaether::GroupSelection;
+
+fn members() -> Vec<String> {
+    ["a", "b", "c", "d"]
+        .iter()
+        .map(|name| name.to_string())
+        .collect()
+}
+
+fn select(s: &str) -> Vec<String> {
+    GroupSelection::parse(s)
+        .unwrap()
+        .select(&members())
+        .unwrap()
+}
+
+#[test]
+fn selection_includes_and_excludes() {
+    assert_eq!(select(""), ["a", "b", "c", "d"]);
+    assert_eq!(select("1-3 ^2"), ["a", "c"]);
+    assert_eq!(select("^b,^4"), ["a", "c"]);
+    assert_eq!(select("d 1"), ["a", "d"]);
+}
+
+#[test]
+fn selection_rejects_unknown_members() {
+    for s in ["5", "0", "3-2", "^e"] {
+        let result = GroupSelection::parse(s).and_then(|selection| selection.select(&members()));
+        assert!(result.is_err(), "{}", s);
+    }
+}
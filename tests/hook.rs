@@ -0,0 +1,286 @@
+mod common;
+
+use libaether::{
+    AetherError, Environment, Hook, HookOperation, HookTriggerType, HookWhen, Pkg, PkgBuilder,
+    PkgInfo, ScriptletPolicy,
+};
+use std::fs::{create_dir_all, remove_dir_all, write};
+
+/// a hook triggered on installing paths matching `target`, running `exec`
+fn hook(target: &str, exec: &str) -> String {
+    format!(
+        "[Trigger]\nOperation = Install\nType = Path\nTarget = {}\n\n\
+         [Action]\nWhen = PostTransaction\nExec = {}\n",
+        target, exec
+    )
+}
+
+#[test]
+fn targets_match_like_fnmatch() {
+    let cases = [
+        ("usr/bin/foo", "usr/bin/foo", true),
+        ("usr/bin/foo", "usr/bin/foobar", false),
+        ("usr/bin/*", "usr/bin/foo", true),
+        ("usr/bin/*", "usr/bin/", true),
+        // `*` crosses directories, the way ALPM matches
+        ("usr/*", "usr/share/icons/hicolor", true),
+        ("*.desktop", "usr/share/applications/foo.desktop", true),
+        ("*.desktop", "usr/share/applications/foo.desktop~", false),
+        ("usr/lib/libfoo.so.?", "usr/lib/libfoo.so.1", true),
+        ("usr/lib/libfoo.so.?", "usr/lib/libfoo.so.12", false),
+        ("usr/lib/libfoo.so.?", "usr/lib/libfoo.so.", false),
+        ("usr/lib/lib[abc].so", "usr/lib/libb.so", true),
+        ("usr/lib/lib[abc].so", "usr/lib/libd.so", false),
+        ("usr/lib/lib[a-c].so", "usr/lib/libc.so", true),
+        ("usr/lib/lib[!a-c].so", "usr/lib/libc.so", false),
+        ("usr/lib/lib[^a-c].so", "usr/lib/libd.so", true),
+        ("usr/lib/lib[]].so", "usr/lib/lib].so", true),
+        // an unterminated class is literal
+        ("usr/lib/lib[a", "usr/lib/lib[a", true),
+        ("usr/lib/lib\\*", "usr/lib/lib*", true),
+        ("usr/lib/lib\\*", "usr/lib/libfoo", false),
+        // path targets are relative to the package root
+        ("/usr/bin/*", "usr/bin/foo", true),
+        ("!/usr/bin/*", "usr/bin/foo", false),
+    ];
+
+    for (target, path, selected) in cases {
+        let hook = Hook::parse_str("test", &hook(target, "true")).unwrap();
+        assert_eq!(
+            hook.triggers[0].selects(path),
+            selected,
+            "{} against {}",
+            target,
+            path
+        );
+    }
+}
+
+#[test]
+fn the_last_matching_target_wins() {
+    let raw = "[Trigger]\nOperation = Install\nType = Package\n\
+               Target = *\nTarget = !foo-*\nTarget = foo-keep\n\
+               [Action]\nWhen = PreTransaction\nExec = true\n";
+    let trigger = &Hook::parse_str("test", raw).unwrap().triggers[0];
+
+    for (name, selected) in [("bar", true), ("foo-drop", false), ("foo-keep", true)] {
+        assert_eq!(trigger.selects(name), selected, "{}", name);
+    }
+}
+
+#[test]
+fn exec_is_split_into_words() {
+    let cases: &[(&str, Option<&[&str]>)] = &[
+        ("/bin/true", Some(&["/bin/true"])),
+        ("  sh   -c  true ", Some(&["sh", "-c", "true"])),
+        ("sh -c 'echo \"$1\"'", Some(&["sh", "-c", "echo \"$1\""])),
+        ("sh -c \"echo '$1'\"", Some(&["sh", "-c", "echo '$1'"])),
+        ("echo a\\ b", Some(&["echo", "a b"])),
+        ("echo \"a \\\" b\"", Some(&["echo", "a \" b"])),
+        // backslashes are literal in single quotes
+        ("echo 'a\\b'", Some(&["echo", "a\\b"])),
+        ("echo '' x", Some(&["echo", "", "x"])),
+        ("echo foo\"bar baz\"", Some(&["echo", "foobar baz"])),
+        ("echo 'unterminated", None),
+        ("echo \"unterminated", None),
+        ("echo trailing\\", None),
+    ];
+
+    for (exec, words) in cases {
+        match (Hook::parse_str("test", &hook("*", exec)), words) {
+            (Ok(hook), Some(words)) => assert_eq!(hook.exec, *words, "{}", exec),
+            (Err(AetherError::InfoValueError { value, .. }), None) => {
+                assert_eq!(value, *exec)
+            }
+            (parsed, _) => panic!("{}: {:?}", exec, parsed.map(|hook| hook.exec)),
+        }
+    }
+}
+
+#[test]
+fn parse_reads_every_key() {
+    let raw = "\
+# comments and blank lines are skipped
+
+[Trigger]
+Operation = Install
+Operation = Upgrade
+Type = File
+Target = usr/share/icons/*
+
+[Trigger]
+Operation = Remove
+Type = Package
+Target = foo
+
+[Action]
+Description = updating icon caches
+When = PreTransaction
+Exec = gtk-update-icon-cache -q
+Depends = gtk3
+Depends = hicolor-icon-theme
+AbortOnFail
+NeedsTargets
+";
+    let hook = Hook::parse_str("icons", raw).unwrap();
+
+    assert_eq!(hook.name, "icons");
+    assert_eq!(hook.description.as_deref(), Some("updating icon caches"));
+    assert_eq!(hook.triggers.len(), 2);
+    assert_eq!(
+        hook.triggers[0].operations,
+        [HookOperation::Install, HookOperation::Upgrade]
+    );
+    assert_eq!(hook.triggers[0].kind, HookTriggerType::Path);
+    assert_eq!(hook.triggers[1].operations, [HookOperation::Remove]);
+    assert_eq!(hook.triggers[1].kind, HookTriggerType::Package);
+    assert_eq!(hook.triggers[1].targets, ["foo"]);
+    assert_eq!(hook.when, HookWhen::PreTransaction);
+    assert_eq!(hook.exec, ["gtk-update-icon-cache", "-q"]);
+    assert_eq!(hook.depends, ["gtk3", "hicolor-icon-theme"]);
+    assert!(hook.abort_on_fail && hook.needs_targets);
+
+    // AbortOnFail only applies before the transaction
+    let raw = raw.replace("PreTransaction", "PostTransaction");
+    assert!(!Hook::parse_str("icons", &raw).unwrap().abort_on_fail);
+}
+
+#[test]
+fn parse_rejects_unknown_keys_and_values() {
+    let valid = hook("*", "true");
+
+    let unknown_keys = [
+        ("Type = Path\n", "Type = Path\nColor = red\n", "Color"),
+        ("When = ", "Target = *\nWhen = ", "Target"),
+        (
+            "Exec = true",
+            "Exec = true\nNeedsTargets = yes",
+            "NeedsTargets",
+        ),
+        (
+            "[Trigger]\n",
+            "Operation = Install\n[Trigger]\n",
+            "Operation",
+        ),
+    ];
+    for (from, to, key) in unknown_keys {
+        match Hook::parse_str("test", &valid.replacen(from, to, 1)) {
+            Err(AetherError::InfoKeyError { key: found, .. }) => assert_eq!(found, key),
+            other => panic!("{}: {:?}", key, other),
+        }
+    }
+
+    let unknown_values = [
+        ("Operation = Install", "Operation = Sync", "Sync"),
+        ("Type = Path", "Type = Directory", "Directory"),
+        ("When = PostTransaction", "When = Later", "Later"),
+        ("[Action]", "[Options]", "[Options]"),
+    ];
+    for (from, to, value) in unknown_values {
+        match Hook::parse_str("test", &valid.replacen(from, to, 1)) {
+            Err(AetherError::InfoValueError { value: found, .. }) => assert_eq!(found, value),
+            other => panic!("{}: {:?}", value, other),
+        }
+    }
+}
+
+#[test]
+fn parse_requires_every_section_and_key() {
+    let valid = hook("*", "true");
+
+    let missing = [
+        ("Operation = Install\n", "Operation"),
+        ("Type = Path\n", "Type"),
+        ("Target = *\n", "Target"),
+        ("When = PostTransaction\n", "When"),
+        ("Exec = true\n", "Exec"),
+    ];
+    for (line, key) in missing {
+        match Hook::parse_str("test", &valid.replace(line, "")) {
+            Err(AetherError::InvalidValue { key: found, .. }) => {
+                assert_eq!(found, format!("{} in hook test", key))
+            }
+            other => panic!("{}: {:?}", key, other),
+        }
+    }
+
+    match Hook::parse_str("test", &valid.replace("Exec = true", "Exec =  ")) {
+        Err(AetherError::InvalidValue { key, .. }) => assert_eq!(key, "Exec in hook test"),
+        other => panic!("{:?}", other),
+    }
+
+    let action_only = &valid[valid.find("[Action]").unwrap()..];
+    match Hook::parse_str("test", action_only) {
+        Err(AetherError::InvalidValue { key, .. }) => assert_eq!(key, "[Trigger] in hook test"),
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn sandboxed_hooks_run_or_are_skipped_with_a_reason() {
+    let dir = common::scratch_dir("hook", "sandbox");
+    let env = Environment::create_in("test", &dir.join("envs")).unwrap();
+    let targets = ["foo".to_string()];
+
+    // whether user namespaces are available depends on the kernel
+    let raw = hook("*", "sh -c 'echo ran'");
+    let output = Hook::parse_str("echo", &raw)
+        .unwrap()
+        .run(&targets, &env, ScriptletPolicy::Sandbox)
+        .unwrap()
+        .unwrap();
+    assert_eq!(output.hook, "echo");
+    match &output.skipped {
+        Some(reason) => assert!(!reason.is_empty() && output.stdout.is_empty()),
+        None => assert_eq!(output.stdout, "ran\n"),
+    }
+
+    // a skipped hook isn't a failed one, even with AbortOnFail
+    let raw = hook("*", "false").replace(
+        "When = PostTransaction",
+        "When = PreTransaction\nAbortOnFail",
+    );
+    let ran = Hook::parse_str("fail", &raw)
+        .unwrap()
+        .run(&targets, &env, ScriptletPolicy::Sandbox);
+    match output.skipped {
+        Some(_) => assert!(ran.unwrap().unwrap().skipped.is_some()),
+        None => assert!(matches!(ran, Err(AetherError::HookError { .. }))),
+    }
+
+    remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn transactions_complete_whether_or_not_hooks_are_sandboxed() {
+    let dir = common::scratch_dir("hook", "transaction");
+    let env = Environment::create_in("test", &dir.join("envs")).unwrap();
+    let hooks = env.config_dir().join("hooks");
+    create_dir_all(&hooks).unwrap();
+    write(
+        hooks.join("echo.hook"),
+        hook("usr/share/foo/*", "sh -c 'echo ran'"),
+    )
+    .unwrap();
+
+    let mut pkginfo = PkgInfo::new();
+    pkginfo.pkgname = "foo".into();
+    pkginfo.pkgver = "1-1".into();
+    let root = dir.join("stage");
+    create_dir_all(root.join("usr/share/foo")).unwrap();
+    write(root.join("usr/share/foo/data"), "data\n").unwrap();
+    let archive = PkgBuilder::new(&root, pkginfo).build(&dir).unwrap();
+
+    let mut pkglist = env.pkglist().unwrap();
+    pkglist.set_scriptlet_policy(ScriptletPolicy::Sandbox);
+    pkglist
+        .install(Pkg::from_archive(&archive, &dir.join("extract")).unwrap())
+        .unwrap();
+    assert_eq!(env.pkglist().unwrap().pkgs().len(), 1);
+
+    let output = &pkglist.hook_output()[0];
+    assert_eq!(output.hook, "echo");
+    assert!(output.skipped.is_some() || output.stdout == "ran\n");
+
+    remove_dir_all(&dir).unwrap();
+}
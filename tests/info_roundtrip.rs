@@ -0,0 +1,214 @@
+use libaether::{BuildInfo, BuildInfoFormat, InstalledPkg, ParseMode, PkgInfo, PkgType};
+use proptest::prelude::*;
+use std::path::PathBuf;
+
+/// a value that may hold spaces but never ` = ` or a line break
+fn text() -> impl Strategy<Value = String> {
+    "[a-zA-Z0-9 ._+:/@()-]{0,24}"
+}
+
+/// a value for a repeated key, like `glibc>=2.35`
+fn word() -> impl Strategy<Value = String> {
+    "[a-zA-Z0-9._+:<>=/-]{0,16}"
+}
+
+fn words() -> impl Strategy<Value = Vec<String>> {
+    prop::collection::vec(word(), 0..4)
+}
+
+fn pkginfo() -> impl Strategy<Value = PkgInfo> {
+    (
+        (text(), text(), text(), text(), text(), any::<i64>(), text()),
+        (any::<u64>(), words(), words(), words(), words(), words()),
+        (words(), words(), words(), words(), words(), words()),
+        prop::collection::vec("[a-z]{1,8}=[a-zA-Z0-9=._-]{0,8}", 0..3),
+    )
+        .prop_map(|(a, b, c, xdata)| {
+            let mut pkginfo = PkgInfo::new();
+            (
+                pkginfo.pkgname,
+                pkginfo.pkgbase,
+                pkginfo.pkgver,
+                pkginfo.pkgdesc,
+                pkginfo.url,
+                pkginfo.builddate,
+                pkginfo.packager,
+            ) = a;
+            (
+                pkginfo.size,
+                pkginfo.arch,
+                pkginfo.license,
+                pkginfo.replaces,
+                pkginfo.conflict,
+                pkginfo.provides,
+            ) = b;
+            (
+                pkginfo.depend,
+                pkginfo.optdepend,
+                pkginfo.makedepend,
+                pkginfo.checkdepend,
+                pkginfo.backup,
+                pkginfo.group,
+            ) = c;
+            pkginfo.xdata = xdata;
+            pkginfo
+        })
+}
+
+/// an `installed` entry; names may contain dashes, versions can't
+fn installed() -> impl Strategy<Value = InstalledPkg> {
+    (
+        "[a-z0-9][a-z0-9+_.-]{0,10}",
+        "([0-9]:)?[0-9a-z.+_]{1,6}",
+        "[0-9.]{1,3}",
+        "[a-z0-9_]{1,6}",
+    )
+        .prop_map(|(name, pkgver, pkgrel, arch)| InstalledPkg {
+            name,
+            version: format!("{}-{}", pkgver, pkgrel),
+            arch,
+        })
+}
+
+fn buildinfo() -> impl Strategy<Value = BuildInfo> {
+    (
+        (
+            prop_oneof![Just(BuildInfoFormat::V1), Just(BuildInfoFormat::V2)],
+            text(),
+            text(),
+            text(),
+            words(),
+            text(),
+            text(),
+        ),
+        (text(), text(), any::<i64>(), text(), text(), text()),
+        (
+            text(),
+            words(),
+            words(),
+            prop::collection::vec(installed(), 0..4),
+        ),
+    )
+        .prop_map(|(a, b, c)| {
+            let mut buildinfo = BuildInfo::new();
+            (
+                buildinfo.format,
+                buildinfo.pkgname,
+                buildinfo.pkgbase,
+                buildinfo.pkgver,
+                buildinfo.pkgarch,
+                buildinfo.pkgbuild_sha256sum,
+                buildinfo.pkgbuild_md5sum,
+            ) = a;
+            (
+                buildinfo.pkgbuild_sha1sum,
+                buildinfo.packager,
+                buildinfo.builddate,
+                buildinfo.builddir,
+                buildinfo.startdir,
+                buildinfo.buildtool,
+            ) = b;
+            (
+                buildinfo.buildtoolver,
+                buildinfo.buildenv,
+                buildinfo.options,
+                buildinfo.installed,
+            ) = c;
+            // format 1 has no place for these
+            if buildinfo.format == BuildInfoFormat::V1 {
+                buildinfo.startdir.clear();
+                buildinfo.buildtool.clear();
+                buildinfo.buildtoolver.clear();
+            }
+            buildinfo
+        })
+}
+
+fn scratch_file(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("libaether-{}-{}", name, std::process::id()))
+}
+
+proptest! {
+    #[test]
+    fn pkginfo_round_trips(pkginfo in pkginfo()) {
+        let file = scratch_file("PKGINFO");
+        pkginfo.write_to(&file).unwrap();
+        let parsed = PkgInfo::parse(&file).unwrap();
+
+        prop_assert_eq!(&parsed, &pkginfo);
+        prop_assert_eq!(parsed.to_string(), pkginfo.to_string());
+    }
+
+    #[test]
+    fn buildinfo_round_trips(buildinfo in buildinfo()) {
+        let file = scratch_file("BUILDINFO");
+        buildinfo.write_to(&file).unwrap();
+        let parsed = BuildInfo::parse(&file).unwrap();
+
+        prop_assert_eq!(&parsed, &buildinfo);
+        prop_assert_eq!(parsed.to_string(), buildinfo.to_string());
+    }
+}
+
+#[test]
+fn pkginfo_uses_makepkg_order() {
+    let mut pkginfo = PkgInfo::new();
+    pkginfo.pkgname = "hello".into();
+    pkginfo.pkgver = "1.0-1".into();
+    pkginfo.arch = vec!["x86_64".into()];
+    pkginfo.depend = vec!["glibc".into(), "bash>=5".into()];
+    pkginfo.provides = vec!["greeting".into()];
+    pkginfo.license = vec!["MIT".into(), "Apache-2.0".into()];
+    pkginfo.xdata = vec!["pkgtype=split".into()];
+
+    let lines: Vec<String> = pkginfo
+        .to_string()
+        .lines()
+        .skip(1)
+        .map(String::from)
+        .collect();
+
+    assert_eq!(
+        lines,
+        [
+            "pkgname = hello",
+            "xdata = pkgtype=split",
+            "pkgver = 1.0-1",
+            "builddate = 0",
+            "size = 0",
+            "arch = x86_64",
+            "license = MIT",
+            "license = Apache-2.0",
+            "provides = greeting",
+            "depend = glibc",
+            "depend = bash>=5",
+        ]
+    );
+    assert_eq!(pkginfo.pkgtype(), Some(PkgType::Split));
+}
+
+#[test]
+fn buildinfo_format_1_leaves_out_newer_keys() {
+    let mut buildinfo = BuildInfo::new();
+    buildinfo.format = BuildInfoFormat::V1;
+    buildinfo.pkgname = "hello".into();
+    buildinfo.builddir = "/build".into();
+    buildinfo.startdir = "/start".into();
+    buildinfo.buildtool = "makepkg".into();
+    buildinfo.buildtoolver = "6.0.2".into();
+
+    assert_eq!(
+        buildinfo.to_string(),
+        "format = 1\npkgname = hello\nbuilddate = 0\nbuilddir = /build\n"
+    );
+
+    // so it parses strictly again
+    let file = scratch_file("BUILDINFO-1");
+    buildinfo.write_to(&file).unwrap();
+    let parsed = BuildInfo::parse_with(&file, ParseMode::Strict).unwrap();
+    assert_eq!(parsed.format, BuildInfoFormat::V1);
+    assert!(parsed.startdir.is_empty() && parsed.buildtool.is_empty());
+
+    buildinfo.format = BuildInfoFormat::V2;
+    assert!(buildinfo.to_string().contains("startdir = /start\n"));
+}
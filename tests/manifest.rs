@@ -0,0 +1,167 @@
+mod common;
+
+use libaether::{AetherError, Lockfile, Manifest, Repo, RepoSource};
+use std::fs::{create_dir_all, remove_dir_all, write};
+use std::path::Path;
+use std::process::Command;
+
+/// write and load a repository `name` in `dir` with packages of
+/// `(name, version, depends)`
+fn repo(dir: &Path, name: &str, pkgs: &[(&str, &str, &[&str])]) -> Repo {
+    let entries = dir.join(format!("{}.entries", name));
+    for (pkgname, version, depends) in pkgs {
+        let entry = entries.join(format!("{}-{}", pkgname, version));
+        create_dir_all(&entry).unwrap();
+
+        let mut desc = format!(
+            "%FILENAME%\n{0}-{1}-any.pkg.tar.zst\n\n%NAME%\n{0}\n\n%VERSION%\n{1}\n\n\
+             %SHA256SUM%\n{0}{1}sum\n\n%ARCH%\nany\n\n",
+            pkgname, version
+        );
+        if !depends.is_empty() {
+            desc.push_str(&format!("%DEPENDS%\n{}\n\n", depends.join("\n")));
+        }
+        write(entry.join("desc"), desc).unwrap();
+    }
+
+    let status = Command::new("tar")
+        .arg("-czf")
+        .arg(dir.join(format!("{}.db", name)))
+        .arg("-C")
+        .arg(&entries)
+        .arg(".")
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    Repo::load(name, &dir).unwrap()
+}
+
+fn manifest(packages: &[(&str, &str)]) -> Manifest {
+    Manifest {
+        arch: vec!["x86_64".into()],
+        repos: vec![],
+        packages: packages
+            .iter()
+            .map(|(name, constraint)| (name.to_string(), constraint.to_string()))
+            .collect(),
+    }
+}
+
+/// return `name-version` of every locked package
+fn locked(lockfile: &Lockfile) -> Vec<String> {
+    lockfile
+        .packages
+        .iter()
+        .map(|pkg| pkg.get_refstr())
+        .collect()
+}
+
+#[test]
+fn resolve_pulls_in_dependencies() {
+    let dir = common::scratch_dir("manifest", "resolve");
+    let core = repo(
+        &dir,
+        "manifest-resolve-core",
+        &[
+            ("hello", "2.0-1", &["glibc>=2.35", "sh"]),
+            ("hello", "1.0-1", &["glibc"]),
+            ("glibc", "2.38-1", &[]),
+            ("glibc", "2.30-1", &[]),
+        ],
+    );
+    // later repositories are searched for what earlier ones lack
+    let extra = repo(&dir, "manifest-resolve-extra", &[("sh", "1-1", &[])]);
+
+    let lockfile = manifest(&[("hello", ">=1.0")])
+        .resolve_with(&[core.clone(), extra])
+        .unwrap();
+    assert_eq!(locked(&lockfile), ["glibc-2.38-1", "hello-2.0-1", "sh-1-1"]);
+    assert_eq!(lockfile.arch, ["x86_64"]);
+    let sh = lockfile.get("sh").unwrap();
+    assert_eq!(sh.repo, "manifest-resolve-extra");
+    assert_eq!(sh.filename, "sh-1-1-any.pkg.tar.zst");
+    assert_eq!(sh.sha256, "sh1-1sum");
+
+    let lockfile = manifest(&[("hello", "<2"), ("glibc", "*")])
+        .resolve_with(&[core])
+        .unwrap();
+    assert_eq!(locked(&lockfile), ["glibc-2.38-1", "hello-1.0-1"]);
+
+    remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn resolve_reports_what_cant_be_satisfied() {
+    let dir = common::scratch_dir("manifest", "unsatisfied");
+    let repos = [repo(
+        &dir,
+        "manifest-unsatisfied-core",
+        &[
+            ("hello", "1.0-1", &["glibc>=3"]),
+            ("glibc", "2.38-1", &[]),
+            ("broken", "1.0-1", &["glibc>= 2 3"]),
+            ("old", "1.0-1", &["glibc<2"]),
+        ],
+    )];
+
+    let cases = [
+        (vec![("missing", "*")], "missing"),
+        (vec![("hello", "*")], "glibc>=3"),
+        (vec![("hello", ">=2")], "hello>=2"),
+        // a malformed constraint in a package's metadata matches nothing
+        (vec![("broken", "*")], "glibc>=2 3"),
+        (vec![("glibc", "*"), ("old", "*")], "glibc<2"),
+    ];
+    for (packages, unsatisfied) in cases {
+        match manifest(&packages).resolve_with(&repos) {
+            Err(AetherError::UnsatisfiedDepend { depend, .. }) => {
+                assert_eq!(depend, unsatisfied)
+            }
+            other => panic!("{:?}: {:?}", packages, other),
+        }
+    }
+
+    // a malformed constraint in the manifest itself is an error of its own
+    assert!(matches!(
+        manifest(&[("hello", ">= 1 2")]).resolve_with(&repos),
+        Err(AetherError::InvalidValue { .. })
+    ));
+
+    remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn lockfiles_round_trip() {
+    let dir = common::scratch_dir("manifest", "lockfile");
+    let core = repo(
+        &dir,
+        "manifest-lockfile-core",
+        &[("hello", "1:1.0-1", &["glibc"]), ("glibc", "2.38-1", &[])],
+    );
+
+    let mut manifest = manifest(&[("hello", "*")]);
+    manifest.repos.push(RepoSource {
+        name: core.name.clone(),
+        path: dir.clone(),
+    });
+    let file = dir.join("aether.toml");
+    write(&file, toml::to_string(&manifest).unwrap()).unwrap();
+    assert_eq!(Manifest::parse(&file).unwrap(), manifest);
+
+    let lockfile = Manifest::parse(&file).unwrap().resolve().unwrap();
+    assert_eq!(locked(&lockfile), ["glibc-2.38-1", "hello-1:1.0-1"]);
+    assert_eq!(lockfile.repos, manifest.repos);
+
+    let file = dir.join("aether.lock");
+    lockfile.write_to(&file).unwrap();
+    let parsed = Lockfile::from_dir(&dir).unwrap();
+    assert_eq!(parsed, lockfile);
+
+    // writing it again doesn't change a byte
+    let raw = std::fs::read(&file).unwrap();
+    parsed.write_to(&file).unwrap();
+    assert_eq!(std::fs::read(&file).unwrap(), raw);
+
+    remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,62 @@
+use libaether::{AetherError, ParseMode, PkgInfo};
+use std::path::PathBuf;
+
+fn write_pkginfo(name: &str, raw: &str) -> PathBuf {
+    let file = std::env::temp_dir().join(format!("libaether-{}-{}", name, std::process::id()));
+    std::fs::write(&file, raw).unwrap();
+
+    file
+}
+
+#[test]
+fn skips_blank_lines_and_keeps_separators_in_values() {
+    let file = write_pkginfo(
+        "blank",
+        "# comment\n\npkgname = hello\npkgdesc = a = b\nlicense =\n\nsize = 5000000000\n",
+    );
+    let pkginfo = PkgInfo::parse(&file).unwrap();
+
+    assert_eq!(pkginfo.pkgname, "hello");
+    assert_eq!(pkginfo.pkgdesc, "a = b");
+    assert_eq!(pkginfo.license, [""]);
+    assert_eq!(pkginfo.size, 5_000_000_000);
+}
+
+#[test]
+fn reports_the_line_of_a_malformed_entry() {
+    let file = write_pkginfo("malformed", "pkgname = hello\nsize = big\n");
+
+    match PkgInfo::parse(&file) {
+        Err(AetherError::InfoLineError { line, source, .. }) => {
+            assert_eq!(line, 2);
+            assert!(matches!(*source, AetherError::InvalidValue { .. }));
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    let file = write_pkginfo("nosep", "pkgname hello\n");
+    assert!(matches!(
+        PkgInfo::parse(&file),
+        Err(AetherError::InfoLineError { line: 1, .. })
+    ));
+}
+
+#[test]
+fn unknown_keys_depend_on_the_mode() {
+    let file = write_pkginfo("unknown", "pkgname = hello\nnewkey = value\n");
+
+    assert_eq!(
+        PkgInfo::parse_with(&file, ParseMode::Lenient)
+            .unwrap()
+            .pkgname,
+        "hello"
+    );
+
+    match PkgInfo::parse_with(&file, ParseMode::Strict) {
+        Err(AetherError::InfoLineError { line, source, .. }) => {
+            assert_eq!(line, 2);
+            assert!(matches!(*source, AetherError::InfoKeyError { .. }));
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
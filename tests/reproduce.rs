@@ -0,0 +1,148 @@
+mod common;
+
+use libaether::{BuildInfo, InstalledPkg, Repo, ReproduceSource};
+use std::fs::{create_dir_all, remove_dir_all, write};
+use std::path::Path;
+use std::process::Command;
+
+/// write and load a repository `name` in `dir` with packages of
+/// `(name, version, arch)`
+fn repo(dir: &Path, name: &str, pkgs: &[(&str, &str, &str)]) -> Repo {
+    let entries = dir.join(format!("{}.entries", name));
+    for (pkgname, version, arch) in pkgs {
+        let entry = entries.join(format!("{}-{}", pkgname, version));
+        create_dir_all(&entry).unwrap();
+
+        let desc = format!(
+            "%FILENAME%\n{0}-{1}-{2}.pkg.tar.zst\n\n%NAME%\n{0}\n\n%VERSION%\n{1}\n\n\
+             %ARCH%\n{2}\n\n",
+            pkgname, version, arch
+        );
+        write(entry.join("desc"), desc).unwrap();
+    }
+
+    let status = Command::new("tar")
+        .arg("-czf")
+        .arg(dir.join(format!("{}.db", name)))
+        .arg("-C")
+        .arg(&entries)
+        .arg(".")
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    Repo::load(name, &dir).unwrap()
+}
+
+fn buildinfo(installed: &[&str]) -> BuildInfo {
+    let mut buildinfo = BuildInfo::new();
+    buildinfo.pkgname = "hello".into();
+    buildinfo.installed = installed
+        .iter()
+        .map(|pkg| InstalledPkg::parse(pkg).unwrap())
+        .collect();
+
+    buildinfo
+}
+
+#[test]
+fn reproduce_plan_prefers_caches_then_repos() {
+    let dir = common::scratch_dir("reproduce", "plan");
+    let caches = [dir.join("cache-a"), dir.join("cache-b")];
+    for cache in &caches {
+        create_dir_all(cache).unwrap();
+    }
+    write(caches[1].join("glibc-2.38-1-x86_64.pkg.tar.zst"), "").unwrap();
+    write(caches[0].join("bash-5.2-1-x86_64.pkg.tar.xz"), "").unwrap();
+    // the first cache directory with the package wins
+    write(caches[1].join("bash-5.2-1-x86_64.pkg.tar.zst"), "").unwrap();
+
+    let core = repo(
+        &dir,
+        "reproduce-plan-core",
+        &[
+            ("bash", "5.2-1", "x86_64"),
+            ("gcc", "13.2-1", "x86_64"),
+            ("make", "4.4-1", "x86_64"),
+        ],
+    );
+    let extra = repo(
+        &dir,
+        "reproduce-plan-extra",
+        &[
+            ("make", "4.4-1", "x86_64"),
+            ("ca-certs", "20240101-1", "any"),
+        ],
+    );
+
+    let plan = buildinfo(&[
+        "glibc-2.38-1-x86_64",
+        "bash-5.2-1-x86_64",
+        "gcc-13.2-1-x86_64",
+        "make-4.4-1-x86_64",
+        "ca-certs-20240101-1-any",
+    ])
+    .reproduce_plan(&caches, &[core.clone(), extra.clone()]);
+
+    let sources: Vec<&ReproduceSource> = plan.steps.iter().map(|step| &step.source).collect();
+    assert_eq!(
+        sources,
+        [
+            &ReproduceSource::Cache(caches[1].join("glibc-2.38-1-x86_64.pkg.tar.zst")),
+            &ReproduceSource::Cache(caches[0].join("bash-5.2-1-x86_64.pkg.tar.xz")),
+            &ReproduceSource::Repo {
+                repo: core.name.clone(),
+                archive: dir.join("gcc-13.2-1-x86_64.pkg.tar.zst"),
+            },
+            // earlier repositories win too
+            &ReproduceSource::Repo {
+                repo: core.name.clone(),
+                archive: dir.join("make-4.4-1-x86_64.pkg.tar.zst"),
+            },
+            &ReproduceSource::Repo {
+                repo: extra.name.clone(),
+                archive: dir.join("ca-certs-20240101-1-any.pkg.tar.zst"),
+            },
+        ]
+    );
+    assert_eq!(plan.steps[2].pkg.to_string(), "gcc-13.2-1-x86_64");
+    assert!(plan.is_complete());
+    assert!(plan.missing().is_empty());
+
+    remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn reproduce_plan_reports_what_no_source_has() {
+    let dir = common::scratch_dir("reproduce", "missing");
+    let core = repo(
+        &dir,
+        "reproduce-missing-core",
+        &[("gcc", "13.2-1", "x86_64"), ("make", "4.4-1", "i686")],
+    );
+    write(dir.join("gcc-13.1-1-x86_64.pkg.tar.zst"), "").unwrap();
+    // not an extension makepkg gives packages
+    write(dir.join("unknown-1.0-1-x86_64.tar.zst"), "").unwrap();
+
+    let plan = buildinfo(&[
+        "gcc-13.1-1-x86_64",
+        "gcc-13.2-1-x86_64",
+        "make-4.4-1-x86_64",
+        "unknown-1.0-1-x86_64",
+    ])
+    .reproduce_plan(&[dir.join("nonexistent"), dir.clone()], &[core]);
+
+    let missing: Vec<String> = plan.missing().iter().map(ToString::to_string).collect();
+    // the wrong architecture doesn't match either
+    assert_eq!(missing, ["make-4.4-1-x86_64", "unknown-1.0-1-x86_64"]);
+    assert!(!plan.is_complete());
+    assert_eq!(
+        plan.steps[0].source,
+        ReproduceSource::Cache(dir.join("gcc-13.1-1-x86_64.pkg.tar.zst"))
+    );
+
+    let empty = buildinfo(&[]).reproduce_plan(&[], &[]);
+    assert!(empty.steps.is_empty() && empty.is_complete());
+
+    remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,248 @@
+mod common;
+
+use libaether::{
+    AetherError, Environment, Pkg, PkgBuilder, PkgInfo, Scriptlet, ScriptletPolicy, ScriptletStage,
+};
+use std::fs::{create_dir_all, remove_dir_all, write};
+use std::path::Path;
+
+fn parse(dir: &Path, source: &str) -> Scriptlet {
+    let file = dir.join(".INSTALL");
+    write(&file, source).unwrap();
+
+    Scriptlet::parse(&file).unwrap()
+}
+
+/// build and extract version `ver` of the package `foo` with `install` as
+/// its .INSTALL
+fn build_foo(dir: &Path, ver: &str, install: &str) -> Pkg {
+    let mut pkginfo = PkgInfo::new();
+    pkginfo.pkgname = "foo".into();
+    pkginfo.pkgver = ver.into();
+
+    let root = dir.join("stage").join(ver);
+    create_dir_all(root.join("usr/share/foo")).unwrap();
+    write(root.join("usr/share/foo/ver"), ver).unwrap();
+    let scriptlet = dir.join(format!("{}.install", ver));
+    write(&scriptlet, install).unwrap();
+
+    let mut builder = PkgBuilder::new(&root, pkginfo);
+    builder.install = Some(scriptlet);
+    let archive = builder.build(&dir).unwrap();
+
+    Pkg::from_archive(&archive, &dir.join("extract").join(ver)).unwrap()
+}
+
+/// a scriptlet printing each function's name and arguments
+const ECHO_ALL: &str = "\
+pre_install() { echo pre_install \"$@\"; }
+post_install() { echo post_install \"$@\"; }
+pre_upgrade() { echo pre_upgrade \"$@\"; }
+post_upgrade() { echo post_upgrade \"$@\"; }
+pre_remove() { echo pre_remove \"$@\"; }
+post_remove() { echo post_remove \"$@\"; }
+";
+
+#[test]
+fn parse_finds_defined_functions() {
+    let dir = common::scratch_dir("scriptlet", "parse");
+
+    let cases: &[(&str, &[ScriptletStage])] = &[
+        ("", &[]),
+        (
+            "post_install() {\n  true\n}\n",
+            &[ScriptletStage::PostInstall],
+        ),
+        (
+            "function pre_remove {\n  true\n}\n",
+            &[ScriptletStage::PreRemove],
+        ),
+        (
+            "  pre_upgrade () { true; }\nfunction   post_upgrade() { true; }\n",
+            &[ScriptletStage::PreUpgrade, ScriptletStage::PostUpgrade],
+        ),
+        // only calls, comments and functions with a stage's name as prefix
+        (
+            "post_install_extra() { true; }\n# pre_install() {}\npost_remove\n",
+            &[],
+        ),
+        (ECHO_ALL, &ScriptletStage::ALL),
+    ];
+
+    for (source, stages) in cases {
+        let scriptlet = parse(&dir, source);
+        for stage in ScriptletStage::ALL {
+            assert_eq!(
+                scriptlet.has(stage),
+                stages.contains(&stage),
+                "{:?} in {:?}",
+                stage,
+                source
+            );
+        }
+        assert_eq!(scriptlet.source(), *source);
+    }
+
+    remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn run_passes_versions_and_reports_failures() {
+    let dir = common::scratch_dir("scriptlet", "run");
+    let scriptlet = parse(&dir, ECHO_ALL);
+
+    let output = scriptlet
+        .run(
+            "foo-2-1",
+            ScriptletStage::PreUpgrade,
+            &["2-1", "1-1"],
+            &dir,
+            ScriptletPolicy::Run,
+        )
+        .unwrap()
+        .unwrap();
+    assert_eq!(output.stdout, "pre_upgrade 2-1 1-1\n");
+    assert_eq!(output.skipped, None);
+
+    let skipped = scriptlet.run(
+        "foo-2-1",
+        ScriptletStage::PreUpgrade,
+        &["2-1", "1-1"],
+        &dir,
+        ScriptletPolicy::Skip,
+    );
+    assert_eq!(skipped.unwrap(), None);
+
+    let missing = parse(&dir, "post_install() { true; }\n");
+    let output = missing.run(
+        "foo-2-1",
+        ScriptletStage::PreInstall,
+        &["2-1"],
+        &dir,
+        ScriptletPolicy::Run,
+    );
+    assert_eq!(output.unwrap(), None);
+
+    let failing = parse(&dir, "post_install() { echo broken >&2; exit 3; }\n");
+    match failing.run(
+        "foo-2-1",
+        ScriptletStage::PostInstall,
+        &["2-1"],
+        &dir,
+        ScriptletPolicy::Run,
+    ) {
+        Err(AetherError::ScriptletError {
+            pkg,
+            function,
+            status,
+            stderr,
+        }) => {
+            assert_eq!(pkg, "foo-2-1");
+            assert_eq!(function, "post_install");
+            assert_eq!(status, Some(3));
+            assert_eq!(stderr, "broken\n");
+        }
+        other => panic!("{:?}", other),
+    }
+
+    remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn sandboxed_scriptlets_run_or_are_skipped_with_a_reason() {
+    let dir = common::scratch_dir("scriptlet", "sandbox");
+    let scriptlet = parse(&dir, ECHO_ALL);
+
+    // whether user namespaces are available depends on the kernel, but
+    // either way installing mustn't fail
+    let output = scriptlet
+        .run(
+            "foo-1-1",
+            ScriptletStage::PostInstall,
+            &["1-1"],
+            &dir,
+            ScriptletPolicy::Sandbox,
+        )
+        .unwrap()
+        .unwrap();
+    match &output.skipped {
+        Some(reason) => assert!(!reason.is_empty() && output.stdout.is_empty()),
+        None => assert_eq!(output.stdout, "post_install 1-1\n"),
+    }
+
+    remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn transactions_run_each_stage_with_its_versions() {
+    let dir = common::scratch_dir("scriptlet", "stages");
+    let env = Environment::create_in("test", &dir.join("envs")).unwrap();
+    let mut pkglist = env.pkglist().unwrap();
+    pkglist.set_scriptlet_policy(ScriptletPolicy::Run);
+
+    pkglist.install(build_foo(&dir, "1-1", ECHO_ALL)).unwrap();
+    let old = pkglist.pkgs()[0].clone();
+    pkglist
+        .upgrade(&old, build_foo(&dir, "2-1", ECHO_ALL))
+        .unwrap();
+    let new = pkglist.pkgs()[0].clone();
+    pkglist.remove(&new).unwrap();
+
+    let ran: Vec<(ScriptletStage, String)> = pkglist
+        .scriptlet_output()
+        .iter()
+        .map(|output| (output.stage, output.stdout.clone()))
+        .collect();
+    assert_eq!(
+        ran,
+        [
+            (ScriptletStage::PreInstall, "pre_install 1-1\n".into()),
+            (ScriptletStage::PostInstall, "post_install 1-1\n".into()),
+            (ScriptletStage::PreUpgrade, "pre_upgrade 2-1 1-1\n".into()),
+            (ScriptletStage::PostUpgrade, "post_upgrade 2-1 1-1\n".into()),
+            (ScriptletStage::PreRemove, "pre_remove 2-1\n".into()),
+            (ScriptletStage::PostRemove, "post_remove 2-1\n".into()),
+        ]
+    );
+
+    remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn failed_upgrades_leave_the_old_version_installed() {
+    let dir = common::scratch_dir("scriptlet", "restore");
+    let env = Environment::create_in("test", &dir.join("envs")).unwrap();
+    let mut pkglist = env.pkglist().unwrap();
+    pkglist.set_scriptlet_policy(ScriptletPolicy::Skip);
+
+    let mut pkginfo = PkgInfo::new();
+    pkginfo.pkgname = "foo".into();
+    pkginfo.pkgver = "1-1".into();
+    let root = dir.join("stage/foo");
+    create_dir_all(root.join("usr/bin")).unwrap();
+    write(root.join("usr/bin/foo"), "#!/bin/sh\n").unwrap();
+    let archive = PkgBuilder::new(&root, pkginfo.clone()).build(&dir).unwrap();
+    pkglist
+        .install(Pkg::from_archive(&archive, &dir.join("extract/1")).unwrap())
+        .unwrap();
+    let old = pkglist.pkgs()[0].clone();
+    let link = env.bin_dir().join("foo");
+    let exported = std::fs::read_link(&link).unwrap();
+
+    pkginfo.pkgver = "2-1".into();
+    let archive = PkgBuilder::new(&root, pkginfo).build(&dir).unwrap();
+    let new = Pkg::from_archive(&archive, &dir.join("extract/2")).unwrap();
+
+    // a file where a directory is needed makes installing the new one fail
+    write(dir.join("file"), "").unwrap();
+    assert!(pkglist
+        .upgrade_to(&old, new, &dir.join("file/foo-2-1"))
+        .is_err());
+
+    let refstrs: Vec<String> = pkglist.pkgs().iter().map(Pkg::get_refstr).collect();
+    assert_eq!(refstrs, [old.get_refstr()]);
+    assert_eq!(std::fs::read_link(&link).unwrap(), exported);
+    assert_eq!(env.pkglist().unwrap().pkgs()[0].get_refstr(), "foo-1-1");
+
+    remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,129 @@
+#![cfg(feature = "serde")]
+
+use libaether::{
+    AetherError, BuildInfo, BuildInfoFormat, InstalledPkg, MTree, MTreeEntry, PkgInfo, PkgType,
+};
+use serde_json::json;
+
+#[test]
+fn pkginfo_has_stable_json() {
+    let mut pkginfo = PkgInfo::new();
+    pkginfo.pkgname = "hello".into();
+    pkginfo.pkgver = "1.0-1".into();
+    pkginfo.size = 5_000_000_000;
+    pkginfo.depend = vec!["glibc".into()];
+    pkginfo.xdata = vec!["pkgtype=pkg".into()];
+
+    let value = serde_json::to_value(&pkginfo).unwrap();
+    assert_eq!(value["pkgname"], "hello");
+    assert_eq!(value["size"], 5_000_000_000u64);
+    assert_eq!(value["depend"], json!(["glibc"]));
+    assert_eq!(value["xdata"], json!(["pkgtype=pkg"]));
+
+    let parsed: PkgInfo = serde_json::from_value(value).unwrap();
+    assert_eq!(parsed, pkginfo);
+
+    // missing fields take their defaults
+    let parsed: PkgInfo = serde_json::from_str(r#"{"pkgname": "hello"}"#).unwrap();
+    assert_eq!(parsed.pkgname, "hello");
+    assert!(parsed.depend.is_empty());
+
+    assert_eq!(serde_json::to_value(PkgType::Debug).unwrap(), "debug");
+}
+
+#[test]
+fn buildinfo_has_stable_json() {
+    let mut buildinfo = BuildInfo::new();
+    buildinfo.format = BuildInfoFormat::V1;
+    buildinfo.pkgname = "hello".into();
+    buildinfo.installed = vec![InstalledPkg::parse("gcc-libs-13.2.1-3-x86_64").unwrap()];
+
+    let value = serde_json::to_value(&buildinfo).unwrap();
+    assert_eq!(value["format"], 1);
+    assert_eq!(
+        value["installed"],
+        json!([{"name": "gcc-libs", "version": "13.2.1-3", "arch": "x86_64"}])
+    );
+
+    let parsed: BuildInfo = serde_json::from_value(value).unwrap();
+    assert_eq!(parsed, buildinfo);
+
+    assert!(serde_json::from_str::<BuildInfo>(r#"{"format": 3}"#).is_err());
+}
+
+#[test]
+fn mtree_entry_round_trips() {
+    let entry = MTreeEntry {
+        path: "./usr/bin/hello".into(),
+        file_type: Some("file".into()),
+        mode: Some(0o755),
+        size: Some(12),
+        sha256: Some("ab".repeat(32)),
+        ..MTreeEntry::default()
+    };
+
+    let value = serde_json::to_value(&entry).unwrap();
+    assert_eq!(value["mode"], 0o755);
+    assert_eq!(value["md5"], serde_json::Value::Null);
+
+    let parsed: MTreeEntry = serde_json::from_value(value).unwrap();
+    assert_eq!(parsed, entry);
+}
+
+#[test]
+fn mtree_round_trips() {
+    let entries = json!([
+        {"path": "./.PKGINFO", "file_type": "file", "mode": 0o644, "size": 300,
+         "time": 1700000000, "md5": null, "sha256": "cd".repeat(32), "link": null,
+         "uid": 0, "gid": 0},
+        {"path": "./usr/bin", "file_type": "dir", "mode": 0o755, "size": null,
+         "time": 1700000000, "md5": null, "sha256": null, "link": null,
+         "uid": 0, "gid": 0},
+        {"path": "./usr/bin/hello world", "file_type": "file", "mode": 0o755,
+         "size": 12, "time": 1700000000, "md5": "ef".repeat(16),
+         "sha256": "ab".repeat(32), "link": null, "uid": 0, "gid": 0},
+        {"path": "./usr/bin/hi", "file_type": "link", "mode": 0o777, "size": null,
+         "time": 1700000000, "md5": null, "sha256": null, "link": "hello world",
+         "uid": 0, "gid": 0},
+        // escaped as octal in the file, but not once parsed
+        {"path": "./usr/share/doc/café #1\\", "file_type": "file", "mode": 0o644,
+         "size": 0, "time": 1700000000, "md5": null, "sha256": null, "link": null,
+         "uid": 0, "gid": 0},
+    ]);
+
+    let mtree: MTree = serde_json::from_value(entries.clone()).unwrap();
+    let value = serde_json::to_value(&mtree).unwrap();
+    assert_eq!(value, entries);
+
+    let again: MTree = serde_json::from_value(value.clone()).unwrap();
+    assert_eq!(serde_json::to_value(&again).unwrap(), value);
+}
+
+#[test]
+fn mtree_entries_that_dont_parse_are_errors() {
+    // paths in an mtree are relative, and a leading `/` makes a command
+    let mtree: MTree = serde_json::from_value(json!([
+        {"path": "./usr/bin/hello", "file_type": "file", "mode": 0o755},
+        {"path": "/usr/bin/broken", "file_type": "file", "mode": 0o755},
+    ]))
+    .unwrap();
+
+    assert!(serde_json::to_value(&mtree).is_err());
+}
+
+#[test]
+fn errors_serialize_with_their_kind() {
+    let error = AetherError::InfoLineError {
+        file: ".PKGINFO".into(),
+        line: 2,
+        source: Box::new(AetherError::InvalidValue {
+            key: "size".into(),
+            value: "big".into(),
+        }),
+    };
+
+    let value = serde_json::to_value(&error).unwrap();
+    assert_eq!(value["kind"], "InfoLineError");
+    assert_eq!(value["message"], error.to_string());
+    assert!(value["source"].is_string());
+}
@@ -0,0 +1,193 @@
+mod common;
+
+use libaether::{sha256sum, GcReport, Pkg, PkgBuilder, PkgInfo, Store};
+use std::fs::{create_dir_all, metadata, read_to_string, remove_dir_all, write};
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::Path;
+
+/// build and extract the package `name` with `files` of `(path, contents)`
+fn build(dir: &Path, name: &str, files: &[(&str, &str)]) -> Pkg {
+    let mut pkginfo = PkgInfo::new();
+    pkginfo.pkgname = name.into();
+    pkginfo.pkgver = "1-1".into();
+
+    let root = dir.join("stage").join(name);
+    for (path, contents) in files {
+        let file = root.join(path);
+        create_dir_all(file.parent().unwrap()).unwrap();
+        write(&file, contents).unwrap();
+    }
+
+    let archive = PkgBuilder::new(&root, pkginfo).build(&dir).unwrap();
+
+    Pkg::from_archive(&archive, &dir.join("extract").join(name)).unwrap()
+}
+
+#[test]
+fn import_dedups_by_digest() {
+    let dir = common::scratch_dir("store", "dedup");
+    let store = Store::at(&dir.join("store"));
+
+    let foo = build(
+        &dir,
+        "foo",
+        &[("usr/share/common", "shared\n"), ("usr/share/foo", "foo\n")],
+    );
+    let bar = build(
+        &dir,
+        "bar",
+        &[("usr/share/common", "shared\n"), ("usr/share/bar", "bar\n")],
+    );
+    store.import(&foo, &dir.join("pkgs/foo")).unwrap();
+    store.import(&bar, &dir.join("pkgs/bar")).unwrap();
+
+    let common = dir.join("pkgs/foo/usr/share/common");
+    let sum = sha256sum(&common).unwrap();
+    let object = store.object_path(&sum, metadata(&common).unwrap().mode());
+    assert!(object.starts_with(store.path().join(&sum[..2])));
+
+    // one object, linked into both packages
+    let meta = metadata(&object).unwrap();
+    assert_eq!(meta.nlink(), 3);
+    assert_eq!(meta.ino(), metadata(&common).unwrap().ino());
+    assert_eq!(
+        meta.ino(),
+        metadata(dir.join("pkgs/bar/usr/share/common"))
+            .unwrap()
+            .ino()
+    );
+    // and read-only, so editing one package's copy can't touch the other
+    assert_eq!(meta.permissions().mode() & 0o222, 0);
+
+    let foo_only = dir.join("pkgs/foo/usr/share/foo");
+    assert_ne!(
+        metadata(&foo_only).unwrap().ino(),
+        metadata(dir.join("pkgs/bar/usr/share/bar")).unwrap().ino()
+    );
+    assert_eq!(read_to_string(&foo_only).unwrap(), "foo\n");
+
+    // adding the same contents again returns the existing object
+    let again = dir.join("again");
+    write(&again, "shared\n").unwrap();
+    std::fs::set_permissions(&again, metadata(&common).unwrap().permissions()).unwrap();
+    assert_eq!(store.add(&again, &sum).unwrap(), object);
+    assert_eq!(metadata(&object).unwrap().nlink(), 3);
+
+    remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn import_falls_back_to_copying_across_filesystems() {
+    let dir = common::scratch_dir("store", "fallback");
+    let shm = Path::new("/dev/shm");
+
+    // the fallback only happens where hardlinking fails, which needs a
+    // second filesystem
+    if !shm.is_dir() || metadata(shm).unwrap().dev() == metadata(&dir).unwrap().dev() {
+        remove_dir_all(&dir).unwrap();
+        return;
+    }
+    let other = shm.join(dir.file_name().unwrap());
+    let _ = remove_dir_all(&other);
+
+    let store = Store::at(&dir.join("store"));
+    let foo = build(&dir, "foo", &[("usr/bin/foo", "#!/bin/sh\n")]);
+    std::fs::set_permissions(
+        foo.path.join("usr/bin/foo"),
+        std::fs::Permissions::from_mode(0o755),
+    )
+    .unwrap();
+    store.import(&foo, &other.join("foo")).unwrap();
+
+    let file = other.join("foo/usr/bin/foo");
+    let sum = sha256sum(&file).unwrap();
+    let object = store.object_path(&sum, 0o755);
+    assert_eq!(read_to_string(&file).unwrap(), "#!/bin/sh\n");
+    assert_eq!(
+        metadata(&file).unwrap().permissions().mode() & 0o7777,
+        0o555
+    );
+    // reflinked or copied, the object isn't held by the package
+    assert_eq!(metadata(&object).unwrap().nlink(), 1);
+
+    remove_dir_all(&other).unwrap();
+    remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn import_refuses_to_overwrite_links_to_the_object() {
+    let dir = common::scratch_dir("store", "overwrite");
+    let store = Store::at(&dir.join("store"));
+    let foo = build(&dir, "foo", &[("usr/share/foo", "foo\n")]);
+
+    store.import(&foo, &dir.join("pkgs/foo")).unwrap();
+    assert!(store.import(&foo, &dir.join("pkgs/foo")).is_err());
+    assert_eq!(
+        read_to_string(dir.join("pkgs/foo/usr/share/foo")).unwrap(),
+        "foo\n"
+    );
+
+    remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn gc_reclaims_only_unlinked_objects() {
+    let dir = common::scratch_dir("store", "gc");
+    let store = Store::at(&dir.join("store"));
+    assert_eq!(store.gc().unwrap(), GcReport::default());
+
+    let foo = build(
+        &dir,
+        "foo",
+        &[("usr/share/common", "shared\n"), ("usr/share/foo", "foo\n")],
+    );
+    let bar = build(&dir, "bar", &[("usr/share/common", "shared\n")]);
+    store.import(&foo, &dir.join("pkgs/foo")).unwrap();
+    store.import(&bar, &dir.join("pkgs/bar")).unwrap();
+
+    // everything is still linked
+    assert_eq!(store.gc().unwrap(), GcReport::default());
+
+    let object = |path: &Path| {
+        let meta = metadata(path).unwrap();
+        store.object_path(&sha256sum(&path).unwrap(), meta.mode())
+    };
+    let common = object(&dir.join("pkgs/bar/usr/share/common"));
+    let foo_only = object(&dir.join("pkgs/foo/usr/share/foo"));
+
+    // only what foo alone used goes, along with foo's metadata files
+    let mut foo_files = vec![];
+    for entry in std::fs::read_dir(dir.join("pkgs/foo")).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_file() {
+            foo_files.push(object(&path));
+        }
+    }
+    foo_files.push(foo_only.clone());
+    let foo_bytes: u64 = foo_files
+        .iter()
+        .filter(|object| metadata(object).unwrap().nlink() == 2)
+        .map(|object| metadata(object).unwrap().len())
+        .sum();
+    let foo_objects = foo_files
+        .iter()
+        .filter(|object| metadata(object).unwrap().nlink() == 2)
+        .count();
+    assert!(foo_objects > 1);
+
+    remove_dir_all(dir.join("pkgs/foo")).unwrap();
+    let report = store.gc().unwrap();
+    assert_eq!(report.objects, foo_objects);
+    assert_eq!(report.bytes, foo_bytes);
+    assert!(!foo_only.exists());
+    assert!(common.exists());
+    assert_eq!(metadata(&common).unwrap().nlink(), 2);
+
+    remove_dir_all(dir.join("pkgs/bar")).unwrap();
+    let report = store.gc().unwrap();
+    assert!(report.objects >= 1);
+    assert!(!common.exists());
+    assert_eq!(store.gc().unwrap(), GcReport::default());
+
+    remove_dir_all(&dir).unwrap();
+}
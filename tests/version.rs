@@ -0,0 +1,164 @@
+use libaether::{vercmp, AetherError, Constraint, Depend, VersionOp};
+use std::cmp::Ordering;
+
+/// the vectors from pacman's test/util/vercmptest.sh, as `(a, b, a cmp b)`
+const VERCMP: &[(&str, &str, i8)] = &[
+    // all similar length, no pkgrel
+    ("1.5.0", "1.5.0", 0),
+    ("1.5.1", "1.5.0", 1),
+    // mixed length
+    ("1.5.1", "1.5", 1),
+    // with pkgrel, simple
+    ("1.5.0-1", "1.5.0-1", 0),
+    ("1.5.0-1", "1.5.0-2", -1),
+    ("1.5.0-1", "1.5.1-1", -1),
+    ("1.5.0-2", "1.5.1-1", -1),
+    // with pkgrel, mixed lengths
+    ("1.5-1", "1.5.1-1", -1),
+    ("1.5-2", "1.5.1-1", -1),
+    ("1.5-2", "1.5.1-2", -1),
+    // mixed pkgrel inclusion
+    ("1.5", "1.5-1", 0),
+    ("1.5-1", "1.5", 0),
+    ("1.1-1", "1.1", 0),
+    ("1.0-1", "1.1", -1),
+    ("1.1-1", "1.0", 1),
+    // alphanumeric versions
+    ("1.5b-1", "1.5-1", -1),
+    ("1.5b", "1.5", -1),
+    ("1.5b-1", "1.5", -1),
+    ("1.5b", "1.5.1", -1),
+    // from the manpage
+    ("1.0a", "1.0alpha", -1),
+    ("1.0alpha", "1.0b", -1),
+    ("1.0b", "1.0beta", -1),
+    ("1.0beta", "1.0rc", -1),
+    ("1.0rc", "1.0", -1),
+    // alpha-dotted versions
+    ("1.5.a", "1.5", 1),
+    ("1.5.b", "1.5.a", 1),
+    ("1.5.1", "1.5.b", 1),
+    // alpha dots and dashes
+    ("1.5.b-1", "1.5.b", 0),
+    ("1.5-1", "1.5.b", -1),
+    // same/similar content, differing separators
+    ("2.0", "2_0", 0),
+    ("2.0_a", "2_0.a", 0),
+    ("2.0a", "2.0.a", -1),
+    ("2___a", "2_a", 1),
+    // epoch included version comparisons
+    ("0:1.0", "0:1.0", 0),
+    ("0:1.0", "0:1.1", -1),
+    ("1:1.0", "0:1.0", 1),
+    ("1:1.0", "0:1.1", 1),
+    ("1:1.0", "2:1.1", -1),
+    // epoch + sometimes present pkgrel
+    ("1:1.0", "0:1.0-1", 1),
+    ("1:1.0-1", "0:1.1-1", 1),
+    // epoch included on one version
+    ("0:1.0", "1.0", 0),
+    ("0:1.0", "1.1", -1),
+    ("0:1.1", "1.0", 1),
+    ("1:1.0", "1.0", 1),
+    ("1:1.0", "1.1", 1),
+    ("1:1.1", "1.1", 1),
+];
+
+#[test]
+fn vercmp_matches_pacman() {
+    for (a, b, expected) in VERCMP {
+        let expected = expected.cmp(&0);
+
+        assert_eq!(vercmp(a, b), expected, "{} vs {}", a, b);
+        assert_eq!(vercmp(b, a), expected.reverse(), "{} vs {}", b, a);
+    }
+}
+
+#[test]
+fn vercmp_compares_numbers_by_value() {
+    let cases = [
+        ("1.10", "1.9", Ordering::Greater),
+        ("1.010", "1.10", Ordering::Equal),
+        ("1.0001", "1.1", Ordering::Equal),
+        ("20240101", "3", Ordering::Greater),
+        // numeric segments are newer than alphabetic ones
+        ("1.1", "1.a", Ordering::Greater),
+        ("1.0~rc1", "1.0", Ordering::Greater),
+    ];
+
+    for (a, b, expected) in cases {
+        assert_eq!(vercmp(a, b), expected, "{} vs {}", a, b);
+    }
+}
+
+#[test]
+fn constraints_parse_and_match() {
+    let cases = [
+        ("", None, ""),
+        ("*", None, ""),
+        (">=1.2", Some(VersionOp::Ge), "1.2"),
+        ("<= 1.2", Some(VersionOp::Le), "1.2"),
+        ("> 1:1.2-3", Some(VersionOp::Gt), "1:1.2-3"),
+        ("<1.2", Some(VersionOp::Lt), "1.2"),
+        ("=1.2", Some(VersionOp::Eq), "1.2"),
+        ("1.2", Some(VersionOp::Eq), "1.2"),
+    ];
+    for (raw, op, version) in cases {
+        let constraint = Constraint::parse(raw).unwrap();
+        assert_eq!((constraint.op, constraint.version.as_str()), (op, version));
+    }
+
+    let matches = [
+        (">=1.2", "1.2", true),
+        (">=1.2", "1.10", true),
+        (">=1.2", "1.1", false),
+        (">1.2", "1.2", false),
+        ("<1.2", "1.2rc1", true),
+        ("=1.2", "1.2-5", true),
+        ("=1.2-1", "1.2-5", false),
+        ("*", "0", true),
+    ];
+    for (raw, version, expected) in matches {
+        let constraint = Constraint::parse(raw).unwrap();
+        assert_eq!(constraint.matches(version), expected, "{} {}", raw, version);
+    }
+
+    for raw in [">=", "<", "= ", ">= 1 2"] {
+        assert!(
+            matches!(
+                Constraint::parse(raw),
+                Err(AetherError::InvalidValue { .. })
+            ),
+            "{}",
+            raw
+        );
+    }
+}
+
+#[test]
+fn malformed_depends_are_unsatisfiable() {
+    let cases = [
+        ("foo>=", "foo>="),
+        ("foo>= 1 2", "foo>=1 2"),
+        ("foo<", "foo<"),
+        ("foo=", "foo="),
+    ];
+    for (raw, displayed) in cases {
+        assert!(Depend::try_parse(raw).is_err(), "{}", raw);
+
+        let depend = Depend::parse(raw);
+        assert_eq!(depend.name, "foo");
+        assert_eq!(depend.to_string(), displayed);
+        for version in ["", "1", "1 2", "2", "1:1-1"] {
+            assert!(!depend.satisfied_by("foo", version), "{} {}", raw, version);
+        }
+        assert!(!depend.satisfied_by_provides(&["foo=1".into(), "foo".into()]));
+    }
+
+    let depend = Depend::try_parse("foo>=1.2").unwrap();
+    assert_eq!(depend, Depend::parse("foo>=1.2"));
+    assert!(depend.satisfied_by("foo", "1.3"));
+    assert!(depend.satisfied_by_provides(&["foo=1.2".into()]));
+    assert!(!depend.satisfied_by_provides(&["foo".into()]));
+    assert!(Depend::parse("foo").satisfied_by_provides(&["foo".into()]));
+}